@@ -0,0 +1,100 @@
+//! Matching engine benchmarks. Every scenario replays a fixed, seeded flow, so numbers are
+//! comparable between runs; criterion's throughput line gives the per-order rate.
+
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use lob_rs::flow::{self, FlowConfig, FlowEvent, FlowGenerator, MarketRegime};
+use lob_rs::{Order, OrderBook, Side};
+use std::hint::black_box;
+
+const ORDERS: usize = 10_000;
+const SEED: u64 = 42;
+
+fn run(book: &mut OrderBook, events: Vec<FlowEvent>) {
+    for event in events {
+        match event {
+            FlowEvent::Add(order) => {
+                let _ = black_box(book.add_order(order));
+            }
+            FlowEvent::Cancel(id) => {
+                let _ = black_box(book.cancel_order(id));
+            }
+        }
+    }
+}
+
+/// Bids strictly below 100 and asks strictly above, so nothing ever crosses.
+fn non_crossing_flow() -> Vec<FlowEvent> {
+    let bids = FlowConfig { min_price: 90.0, max_price: 99.99, buy_probability: 1.0, ..FlowConfig::default() };
+    let asks = FlowConfig { min_price: 100.01, max_price: 110.0, buy_probability: 0.0, ..FlowConfig::default() };
+    flow::generate(SEED, ORDERS / 2, &bids)
+        .into_iter()
+        .zip(flow::generate(SEED + 1, ORDERS / 2, &asks))
+        .flat_map(|(bid, ask)| {
+            // Keep ids unique across the two halves
+            let FlowEvent::Add(mut ask) = ask else { unreachable!() };
+            ask.id += ORDERS as u64;
+            [bid, FlowEvent::Add(ask)]
+        })
+        .collect()
+}
+
+/// A book ten orders deep at each of 200 ask levels starting at 100.
+fn deep_ask_book() -> OrderBook {
+    let mut book = OrderBook::new();
+    for tick in 0..200 {
+        let price = book.price(100.0 + tick as f64 * 0.01).unwrap();
+        for i in 0..10 {
+            book.add_order(Order::limit(tick * 10 + i, Side::Sell, price, 100)).unwrap();
+        }
+    }
+    book
+}
+
+fn benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("matching");
+    group.throughput(Throughput::Elements(ORDERS as u64));
+
+    group.bench_function("insert_non_crossing", |b| {
+        let events = non_crossing_flow();
+        b.iter_batched(|| (OrderBook::new(), events.clone()), |(mut book, events)| run(&mut book, events), BatchSize::LargeInput)
+    });
+
+    group.bench_function("aggressive_vs_deep_book", |b| {
+        let book = deep_ask_book();
+        let takers: Vec<FlowEvent> = (0..ORDERS as u64)
+            .map(|i| FlowEvent::Add(Order::market(1_000_000 + i, Side::Buy, 7)))
+            .collect();
+        b.iter_batched(
+            || (OrderBook::from_snapshot(book.snapshot()).unwrap(), takers.clone()),
+            |(mut book, events)| run(&mut book, events),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("mixed_50_50", |b| {
+        let events = flow::generate(SEED, ORDERS, &FlowConfig::default());
+        b.iter_batched(|| (OrderBook::new(), events.clone()), |(mut book, events)| run(&mut book, events), BatchSize::LargeInput)
+    });
+
+    group.bench_function("cancel_heavy", |b| {
+        let config = FlowConfig { cancel_probability: 0.5, ..FlowConfig::default() };
+        let events = flow::generate(SEED, ORDERS, &config);
+        b.iter_batched(|| (OrderBook::new(), events.clone()), |(mut book, events)| run(&mut book, events), BatchSize::LargeInput)
+    });
+
+    for (name, regime) in [
+        ("regime_calm", MarketRegime::calm()),
+        ("regime_trending", MarketRegime::trending()),
+        ("regime_volatile", MarketRegime::volatile()),
+    ] {
+        group.bench_function(name, |b| {
+            let events: Vec<FlowEvent> = FlowGenerator::new(SEED, regime.clone()).take(ORDERS).collect();
+            b.iter_batched(|| (OrderBook::new(), events.clone()), |(mut book, events)| run(&mut book, events), BatchSize::LargeInput)
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(matching, benches);
+criterion_main!(matching);
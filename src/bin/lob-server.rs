@@ -0,0 +1,29 @@
+use lob_rs::server::Server;
+use lob_rs::OrderBook;
+use std::env;
+use std::process;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:7878";
+
+/// Serves an empty book over line-delimited JSON, see `lob_rs::server` for the protocol.
+///
+/// ```text
+/// cargo run --release --features server --bin lob-server                   # on 127.0.0.1:7878
+/// cargo run --release --features server --bin lob-server -- 0.0.0.0:9000
+/// ```
+fn main() {
+    let addr = env::args().nth(1).unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    let server = Server::bind(&addr).unwrap_or_else(|err| exit(&format!("cannot listen on {}: {}", addr, err)));
+    match server.local_addr() {
+        Ok(local) => println!("Serving the book on {}", local),
+        Err(_) => println!("Serving the book on {}", addr),
+    }
+    if let Err(err) = server.run(OrderBook::new()) {
+        exit(&format!("server stopped: {}", err));
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
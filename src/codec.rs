@@ -0,0 +1,455 @@
+//! Nasdaq ITCH-style binary order messages, for replaying historical order flow.
+//!
+//! A stream is a sequence of messages, each framed by a big-endian `u16` length followed by
+//! that many bytes: a type byte, then the fields below. All integers are big-endian and
+//! prices are `u32`s with four implied decimal places.
+//!
+//! | Type | Message        | Fields after the header                                          | Length |
+//! |------|----------------|------------------------------------------------------------------|--------|
+//! | `A`  | Add Order      | order ref `u64`, side `B`/`S`, shares `u32`, stock `[u8; 8]`, price | 36     |
+//! | `E`  | Order Executed | order ref `u64`, executed shares `u32`, match number `u64`       | 31     |
+//! | `X`  | Order Cancel   | order ref `u64`, cancelled shares `u32`                          | 23     |
+//! | `D`  | Order Delete   | order ref `u64`                                                  | 19     |
+//! | `U`  | Order Replace  | original ref `u64`, new ref `u64`, shares `u32`, price           | 35     |
+//!
+//! Every message starts with the same header after its type byte: stock locate `u16`,
+//! tracking number `u16` and a 48-bit timestamp in nanoseconds since midnight. Messages of
+//! any other type are passed through undecoded as [`Message::Other`].
+//!
+//! ```
+//! use lob_rs::codec::{encode, replay_itch, Header, Message};
+//! use lob_rs::{OrderBook, Side};
+//!
+//! let header = Header::default();
+//! let mut stream = Vec::new();
+//! for (order_ref, side) in [(1, Side::Sell), (2, Side::Buy)] {
+//!     let add = Message::AddOrder { header, order_ref, side, shares: 100, stock: b"ACME    ", price: 1_005_000 };
+//!     encode(&add, &mut stream);
+//! }
+//! encode(&Message::OrderExecuted { header, order_ref: 1, shares: 100, match_number: 1 }, &mut stream);
+//!
+//! let mut book = OrderBook::new();
+//! let stats = replay_itch(&stream[..], &mut book)?;
+//! assert_eq!((stats.trades, stats.volume), (1, 100));
+//! assert!(stats.mismatches.is_empty());
+//! # Ok::<(), lob_rs::codec::CodecError>(())
+//! ```
+
+use crate::{Order, OrderBook, Price, Side, Trade};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+
+/// Wire prices are in units of 1/10,000.
+const PRICE_SCALE: f64 = 10_000.0;
+
+/// How much [`replay_itch`] reads at a time.
+const CHUNK: usize = 64 * 1024;
+
+/// The fields every message starts with after its type byte.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Header {
+    pub stock_locate: u16,
+    pub tracking_number: u16,
+    /// Nanoseconds since midnight. Only the low 48 bits go on the wire.
+    pub timestamp: u64,
+}
+
+/// One decoded message, borrowing from the buffer it was parsed out of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message<'a> {
+    AddOrder { header: Header, order_ref: u64, side: Side, shares: u32, stock: &'a [u8; 8], price: u32 },
+    OrderExecuted { header: Header, order_ref: u64, shares: u32, match_number: u64 },
+    /// Part of an order cancelled; `shares` is how many were taken off, not how many are left.
+    OrderCancel { header: Header, order_ref: u64, shares: u32 },
+    OrderDelete { header: Header, order_ref: u64 },
+    /// An order replaced by one under `new_ref`, with `shares` as its new size.
+    OrderReplace { header: Header, original_ref: u64, new_ref: u64, shares: u32, price: u32 },
+    /// A message type outside the subset: its type byte and the rest of the message.
+    Other { kind: u8, body: &'a [u8] },
+}
+
+/// Error returned when a stream cannot be decoded or read.
+#[derive(Debug)]
+pub enum CodecError {
+    Io(io::Error),
+    /// The buffer ends partway through a message, of which it would need `needed` bytes from
+    /// its start. Nothing was consumed; call again once more bytes have arrived.
+    Incomplete { needed: usize },
+    /// The message at stream `offset` has the wrong length for its type or an invalid field.
+    Malformed { offset: u64, reason: &'static str },
+    /// The stream ended partway through the message at `offset`.
+    Truncated { offset: u64 },
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Io(err) => write!(f, "failed to read message stream: {}", err),
+            CodecError::Incomplete { needed } => write!(f, "incomplete message, {} bytes needed", needed),
+            CodecError::Malformed { offset, reason } => write!(f, "malformed message at offset {}: {}", offset, reason),
+            CodecError::Truncated { offset } => {
+                write!(f, "stream ends partway through the message at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CodecError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CodecError {
+    fn from(err: io::Error) -> Self {
+        CodecError::Io(err)
+    }
+}
+
+/// Splits a stream into messages without copying them, keeping track of the stream offset
+/// for error reports.
+#[derive(Debug, Default)]
+pub struct ItchParser {
+    offset: u64,
+}
+
+impl ItchParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stream offset of the next message, i.e. how many bytes have been consumed so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Decodes the message at the start of `buf` and returns it along with the number of
+    /// bytes it took up, length prefix included. A buffer that ends partway through the
+    /// message gives [`CodecError::Incomplete`] and consumes nothing.
+    pub fn next<'a>(&mut self, buf: &'a [u8]) -> Result<(Message<'a>, usize), CodecError> {
+        let Some((len, rest)) = buf.split_first_chunk() else {
+            return Err(CodecError::Incomplete { needed: 2 });
+        };
+        let len = u16::from_be_bytes(*len) as usize;
+        let Some(bytes) = rest.get(..len) else {
+            return Err(CodecError::Incomplete { needed: 2 + len });
+        };
+
+        let message = decode(bytes).map_err(|reason| CodecError::Malformed { offset: self.offset, reason })?;
+        self.offset += 2 + len as u64;
+        Ok((message, 2 + len))
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<Message<'_>, &'static str> {
+    let (&kind, body) = bytes.split_first().ok_or("empty message")?;
+    let expected = match kind {
+        b'A' => 36,
+        b'E' => 31,
+        b'X' => 23,
+        b'D' => 19,
+        b'U' => 35,
+        _ => return Ok(Message::Other { kind, body }),
+    };
+    if bytes.len() != expected {
+        return Err("wrong length for the message type");
+    }
+
+    let mut fields = Fields(body);
+    let header = Header {
+        stock_locate: fields.u16(),
+        tracking_number: fields.u16(),
+        timestamp: fields.u48(),
+    };
+    Ok(match kind {
+        b'A' => Message::AddOrder {
+            header,
+            order_ref: fields.u64(),
+            side: match fields.take::<1>() {
+                b"B" => Side::Buy,
+                b"S" => Side::Sell,
+                _ => return Err("side is neither B nor S"),
+            },
+            shares: fields.u32(),
+            stock: fields.take(),
+            price: fields.u32(),
+        },
+        b'E' => {
+            Message::OrderExecuted { header, order_ref: fields.u64(), shares: fields.u32(), match_number: fields.u64() }
+        }
+        b'X' => Message::OrderCancel { header, order_ref: fields.u64(), shares: fields.u32() },
+        b'D' => Message::OrderDelete { header, order_ref: fields.u64() },
+        _ => Message::OrderReplace {
+            header,
+            original_ref: fields.u64(),
+            new_ref: fields.u64(),
+            shares: fields.u32(),
+            price: fields.u32(),
+        },
+    })
+}
+
+/// Reads fixed-size fields off the front of a message whose length has been checked.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take<const N: usize>(&mut self) -> &'a [u8; N] {
+        let (field, rest) = self.0.split_first_chunk().expect("message length checked");
+        self.0 = rest;
+        field
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_be_bytes(*self.take())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_be_bytes(*self.take())
+    }
+
+    fn u48(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes[2..].copy_from_slice(self.take::<6>());
+        u64::from_be_bytes(bytes)
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_be_bytes(*self.take())
+    }
+}
+
+/// Appends `message` to `out`, framed, in the format [`ItchParser::next`] reads.
+pub fn encode(message: &Message<'_>, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(&[0, 0]);
+    let header = |out: &mut Vec<u8>, kind: u8, header: Header| {
+        out.push(kind);
+        out.extend_from_slice(&header.stock_locate.to_be_bytes());
+        out.extend_from_slice(&header.tracking_number.to_be_bytes());
+        out.extend_from_slice(&header.timestamp.to_be_bytes()[2..]);
+    };
+
+    match *message {
+        Message::AddOrder { header: fields, order_ref, side, shares, stock, price } => {
+            header(out, b'A', fields);
+            out.extend_from_slice(&order_ref.to_be_bytes());
+            out.push(match side {
+                Side::Buy => b'B',
+                Side::Sell => b'S',
+            });
+            out.extend_from_slice(&shares.to_be_bytes());
+            out.extend_from_slice(stock);
+            out.extend_from_slice(&price.to_be_bytes());
+        }
+        Message::OrderExecuted { header: fields, order_ref, shares, match_number } => {
+            header(out, b'E', fields);
+            out.extend_from_slice(&order_ref.to_be_bytes());
+            out.extend_from_slice(&shares.to_be_bytes());
+            out.extend_from_slice(&match_number.to_be_bytes());
+        }
+        Message::OrderCancel { header: fields, order_ref, shares } => {
+            header(out, b'X', fields);
+            out.extend_from_slice(&order_ref.to_be_bytes());
+            out.extend_from_slice(&shares.to_be_bytes());
+        }
+        Message::OrderDelete { header: fields, order_ref } => {
+            header(out, b'D', fields);
+            out.extend_from_slice(&order_ref.to_be_bytes());
+        }
+        Message::OrderReplace { header: fields, original_ref, new_ref, shares, price } => {
+            header(out, b'U', fields);
+            out.extend_from_slice(&original_ref.to_be_bytes());
+            out.extend_from_slice(&new_ref.to_be_bytes());
+            out.extend_from_slice(&shares.to_be_bytes());
+            out.extend_from_slice(&price.to_be_bytes());
+        }
+        Message::Other { kind, body } => {
+            out.push(kind);
+            out.extend_from_slice(body);
+        }
+    }
+
+    let len = u16::try_from(out.len() - start - 2).expect("message longer than u16::MAX bytes");
+    out[start..start + 2].copy_from_slice(&len.to_be_bytes());
+}
+
+/// An execution the stream and the engine disagree on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionMismatch {
+    /// The order's reference in the message, or the one it was added under for an engine
+    /// execution no message reported.
+    pub order_ref: u64,
+    /// Shares the stream reported executed, zero if it reported none.
+    pub reported: u64,
+    /// How many of those the engine had executed itself.
+    pub matched: u64,
+}
+
+/// Totals from [`replay_itch`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ItchReplayStats {
+    /// Messages decoded, skipped ones included.
+    pub messages: usize,
+    /// Trades the engine made, and the quantity they add up to.
+    pub trades: usize,
+    pub volume: u64,
+    /// Messages the book refused: rejected adds, and cancels, deletes and replaces of orders
+    /// it doesn't have.
+    pub rejects: usize,
+    /// Messages of a type outside the subset.
+    pub skipped: usize,
+    /// Executions the stream reported that the engine didn't make, in stream order, then
+    /// those the engine made that the stream never reported, by order reference.
+    pub mismatches: Vec<ExecutionMismatch>,
+}
+
+/// Reads `reader` to the end and applies each message to `book` as it arrives.
+///
+/// Add Order becomes [`OrderBook::add_order`] of a GTC limit order whose id is the order
+/// reference, Order Delete [`OrderBook::cancel_order`], and Order Cancel and Order Replace
+/// [`OrderBook::modify_order`]. A replaced order keeps its book id, and later messages may
+/// refer to it by its new reference.
+///
+/// The engine does its own matching, so Order Executed only checks the engine's executions
+/// against the stream's. Each reported execution the engine didn't make is recorded as a
+/// mismatch and taken off the order, so the book stays in line with the stream.
+///
+/// Messages may be split across reads anywhere. A stream that ends partway through a message
+/// gives [`CodecError::Truncated`] after everything before it has been applied.
+pub fn replay_itch<R: Read>(mut reader: R, book: &mut OrderBook) -> Result<ItchReplayStats, CodecError> {
+    let mut replay = Replay::default();
+    let mut parser = ItchParser::new();
+    let (mut pending, mut chunk) = (Vec::new(), vec![0; CHUNK]);
+
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        pending.extend_from_slice(&chunk[..read]);
+
+        // Whatever is left after the last whole message waits for the next read
+        let mut consumed = 0;
+        loop {
+            match parser.next(&pending[consumed..]) {
+                Ok((message, len)) => {
+                    replay.apply(&message, book);
+                    consumed += len;
+                }
+                Err(CodecError::Incomplete { .. }) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        pending.drain(..consumed);
+    }
+
+    if !pending.is_empty() {
+        return Err(CodecError::Truncated { offset: parser.offset() });
+    }
+    Ok(replay.finish())
+}
+
+#[derive(Default)]
+struct Replay {
+    stats: ItchReplayStats,
+    /// Book id of every order now known by a reference other than the one it was added under.
+    ids: HashMap<u64, u64>,
+    /// Quantity the engine executed per resting order that no Order Executed has reported yet.
+    unreported: HashMap<u64, u64>,
+}
+
+impl Replay {
+    fn apply(&mut self, message: &Message<'_>, book: &mut OrderBook) {
+        self.stats.messages += 1;
+        let applied = match *message {
+            Message::AddOrder { header, order_ref, side, shares, price, .. } => book_price(book, price)
+                .and_then(|price| {
+                    let order = Order::limit(order_ref, side, price, shares.into());
+                    book.add_order(order.with_timestamp(header.timestamp)).ok()
+                })
+                .map(|trades| self.record(&trades))
+                .is_some(),
+            Message::OrderExecuted { order_ref, shares, .. } => {
+                let id = self.id(order_ref);
+                let reported = u64::from(shares);
+                let unreported = self.unreported.remove(&id).unwrap_or(0);
+                let matched = unreported.min(reported);
+                if unreported > matched {
+                    self.unreported.insert(id, unreported - matched);
+                }
+                if matched < reported {
+                    self.stats.mismatches.push(ExecutionMismatch { order_ref, reported, matched });
+                    take_off(book, id, reported - matched);
+                }
+                true
+            }
+            Message::OrderCancel { order_ref, shares, .. } => take_off(book, self.id(order_ref), shares.into()),
+            Message::OrderDelete { order_ref, .. } => {
+                let id = self.ids.remove(&order_ref).unwrap_or(order_ref);
+                book.cancel_order(id).is_ok()
+            }
+            Message::OrderReplace { original_ref, new_ref, shares, price, .. } => {
+                let id = self.id(original_ref);
+                let trades = book_price(book, price).and_then(|price| book.modify_order(id, price, shares.into()).ok());
+                if let Some(trades) = &trades {
+                    self.record(trades);
+                    self.ids.remove(&original_ref);
+                    self.ids.insert(new_ref, id);
+                }
+                trades.is_some()
+            }
+            Message::Other { .. } => {
+                self.stats.skipped += 1;
+                true
+            }
+        };
+        if !applied {
+            self.stats.rejects += 1;
+        }
+    }
+
+    fn id(&self, order_ref: u64) -> u64 {
+        self.ids.get(&order_ref).copied().unwrap_or(order_ref)
+    }
+
+    fn record(&mut self, trades: &[Trade]) {
+        self.stats.trades += trades.len();
+        for trade in trades {
+            self.stats.volume += trade.quantity;
+            *self.unreported.entry(trade.maker_id).or_default() += trade.quantity;
+        }
+    }
+
+    fn finish(mut self) -> ItchReplayStats {
+        let mut unreported: Vec<_> = self.unreported.into_iter().collect();
+        unreported.sort_unstable();
+        let missing = |(order_ref, matched)| ExecutionMismatch { order_ref, reported: 0, matched };
+        self.stats.mismatches.extend(unreported.into_iter().map(missing));
+        self.stats
+    }
+}
+
+/// A wire price in `book`'s ticks, if the book accepts it.
+fn book_price(book: &OrderBook, price: u32) -> Option<Price> {
+    book.price(f64::from(price) / PRICE_SCALE).ok()
+}
+
+/// Takes `quantity` off the open order `id` without costing it its place in the queue,
+/// cancelling it if that's all there is. Returns whether the order was there.
+fn take_off(book: &mut OrderBook, id: u64, quantity: u64) -> bool {
+    let Some(order) = book.bids().get(id).or_else(|| book.asks().get(id)) else {
+        return false;
+    };
+    let (price, remaining) = (order.price, order.remaining());
+    if quantity >= remaining {
+        book.cancel_order(id).is_ok()
+    } else {
+        book.modify_order(id, price, remaining - quantity).is_ok()
+    }
+}
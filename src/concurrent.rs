@@ -0,0 +1,168 @@
+//! An order book owned by its own matching thread, shared between threads through handles.
+//!
+//! Every call is a command sent over a channel to the matching thread, which applies
+//! commands one at a time in the order they arrive, so the book is never locked and callers
+//! never block each other while an order matches.
+//!
+//! ```
+//! use lob_rs::concurrent::ConcurrentBook;
+//! use lob_rs::{Order, OrderBook, Side};
+//!
+//! let book = ConcurrentBook::spawn(OrderBook::new());
+//! let handle = book.handle();
+//! let price = handle.inspect(|book| book.price(101.0).unwrap());
+//! std::thread::spawn(move || handle.submit_and_wait(Order::limit(1, Side::Sell, price, 10)).unwrap())
+//!     .join()
+//!     .unwrap();
+//!
+//! let trades = book.submit_and_wait(Order::limit(2, Side::Buy, price, 4)).unwrap();
+//! assert_eq!(trades[0].maker_id, 1);
+//! ```
+
+use crate::{BookSnapshot, CancelError, LobError, Order, OrderBook, Trade};
+use std::ops::Deref;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce(&mut OrderBook) + Send>;
+
+/// Owner of a book running on a dedicated matching thread. It derefs to a [`Handle`], and
+/// more handles for other threads come from [`ConcurrentBook::handle`].
+///
+/// The matching thread stops once the `ConcurrentBook` and every handle are dropped: commands
+/// already sent are applied first, then the last drop waits for the thread to finish.
+#[derive(Debug)]
+pub struct ConcurrentBook {
+    handle: Handle,
+}
+
+impl ConcurrentBook {
+    /// Moves `book` onto a new matching thread.
+    pub fn spawn(mut book: OrderBook) -> Self {
+        let (commands, jobs) = mpsc::channel::<Job>();
+        let thread = thread::spawn(move || {
+            for job in jobs {
+                job(&mut book);
+            }
+        });
+
+        let worker = Arc::new(Worker { thread: Some(thread) });
+        ConcurrentBook { handle: Handle { commands, _worker: worker } }
+    }
+
+    /// Another handle to the same book, for a different thread.
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+}
+
+impl Deref for ConcurrentBook {
+    type Target = Handle;
+
+    fn deref(&self) -> &Handle {
+        &self.handle
+    }
+}
+
+/// A cloneable, `Send` way to reach a [`ConcurrentBook`].
+///
+/// # Panics
+///
+/// Every method panics if the matching thread has died, which only happens if the book
+/// itself panicked (e.g. an invariant check under strict validation).
+#[derive(Debug, Clone)]
+pub struct Handle {
+    // Declared before the worker so the last handle's channel closes before the join
+    commands: Sender<Job>,
+    _worker: Arc<Worker>,
+}
+
+impl Handle {
+    /// Queues `order` and returns a receiver for its outcome, so the caller can carry on and
+    /// collect the fills later.
+    pub fn submit(&self, order: Order) -> Receiver<Result<Vec<Trade>, LobError>> {
+        let (reply, outcome) = mpsc::channel();
+        self.send(move |book| {
+            let _ = reply.send(book.add_order(order));
+        });
+        outcome
+    }
+
+    /// Submits `order` and waits for its fills.
+    pub fn submit_and_wait(&self, order: Order) -> Result<Vec<Trade>, LobError> {
+        self.submit(order).recv().expect("matching thread exited")
+    }
+
+    pub fn cancel(&self, id: u64) -> Result<Order, CancelError> {
+        self.inspect_mut(move |book| book.cancel_order(id))
+    }
+
+    pub fn snapshot(&self) -> BookSnapshot {
+        self.inspect(OrderBook::snapshot)
+    }
+
+    /// Runs `f` against the book on the matching thread, between commands, and returns its
+    /// result.
+    pub fn inspect<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&OrderBook) -> R + Send + 'static,
+    {
+        self.inspect_mut(move |book| f(book))
+    }
+
+    fn inspect_mut<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut OrderBook) -> R + Send + 'static,
+    {
+        let (reply, result) = mpsc::channel();
+        self.send(move |book| {
+            let _ = reply.send(f(book));
+        });
+        result.recv().expect("matching thread exited")
+    }
+
+    fn send(&self, job: impl FnOnce(&mut OrderBook) + Send + 'static) {
+        self.commands.send(Box::new(job)).expect("matching thread exited");
+    }
+}
+
+/// Joins the matching thread when the last handle goes away.
+#[derive(Debug)]
+struct Worker {
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            // A panic on the matching thread was already reported to whoever was waiting
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BookEvent, RecordingListener, Side};
+
+    #[test]
+    fn test_drop_drains_pending_commands() {
+        let mut book = OrderBook::new();
+        let price = book.price(100.0).unwrap();
+        let listener = RecordingListener::new();
+        book.set_listener(Box::new(listener.clone()));
+
+        let book = ConcurrentBook::spawn(book);
+        let outcomes: Vec<_> = (0..100).map(|id| book.submit(Order::limit(id, Side::Buy, price, 1))).collect();
+        drop(book);
+
+        // The thread has been joined, so every queued order was applied before it stopped
+        let rested = listener.events().iter().filter(|event| matches!(event, BookEvent::OrderRested(_))).count();
+        assert_eq!(rested, 100);
+        assert!(outcomes.into_iter().all(|outcome| outcome.try_recv().unwrap().unwrap().is_empty()));
+    }
+}
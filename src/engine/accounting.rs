@@ -0,0 +1,120 @@
+use super::*;
+
+/// Running totals of where the quantity submitted to a book went, from
+/// [`OrderBook::accounting`].
+///
+/// Every trade takes its quantity off two orders, the maker and the taker, so nothing has been
+/// created or destroyed as long as `submitted` equals twice `executed` plus everything
+/// cancelled, expired and still open; [`Accounting::check`] tests exactly that.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Accounting {
+    /// Open quantity of every order accepted, plus whatever modifies added.
+    pub submitted: u64,
+    /// Traded volume, each trade counted once.
+    pub executed: u64,
+    /// Cancelled by request, time in force, self-trade prevention or post-only, or taken off
+    /// by a modify.
+    pub cancelled: u64,
+    /// Removed by expiry or evicted by the level cap.
+    pub expired: u64,
+    /// Open quantity resting on each side, hidden iceberg reserves included.
+    pub resting_bids: u64,
+    pub resting_asks: u64,
+    /// Open quantity of stops waiting for their trigger and orders collected by an auction.
+    pub pending: u64,
+    /// Drops the next cancellation, so tests can see the check catch drift.
+    #[cfg(test)]
+    skip_cancel: bool,
+}
+
+/// Where an order's open quantity is held while the accounting moves it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Held {
+    Resting(Side),
+    /// A pending stop or an order collected by an auction.
+    Pending,
+    /// An order being matched, held nowhere until it rests or leaves.
+    Arriving,
+}
+
+impl Accounting {
+    /// Whether the totals balance; O(1), so cheap enough to run after every operation.
+    pub fn check(&self) -> Result<(), AccountingError> {
+        let open = self.resting_bids + self.resting_asks + self.pending;
+        let accounted = 2 * self.executed + self.cancelled + self.expired + open;
+        if accounted != self.submitted {
+            return Err(AccountingError::Unbalanced { submitted: self.submitted, accounted });
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, held: Held) -> Option<&mut u64> {
+        match held {
+            Held::Resting(Side::Buy) => Some(&mut self.resting_bids),
+            Held::Resting(Side::Sell) => Some(&mut self.resting_asks),
+            Held::Pending => Some(&mut self.pending),
+            Held::Arriving => None,
+        }
+    }
+
+    pub(crate) fn hold(&mut self, held: Held, quantity: u64) {
+        if let Some(open) = self.open(held) {
+            *open += quantity;
+        }
+    }
+
+    pub(crate) fn release(&mut self, held: Held, quantity: u64) {
+        if let Some(open) = self.open(held) {
+            *open -= quantity;
+        }
+    }
+
+    pub(crate) fn cancel(&mut self, held: Held, quantity: u64) {
+        self.release(held, quantity);
+        #[cfg(test)]
+        if std::mem::take(&mut self.skip_cancel) {
+            return;
+        }
+        self.cancelled += quantity;
+    }
+
+    pub(crate) fn expire(&mut self, held: Held, quantity: u64) {
+        self.release(held, quantity);
+        self.expired += quantity;
+    }
+
+    /// A trade of `quantity` against a maker held at `maker`.
+    pub(crate) fn trade(&mut self, maker: Held, quantity: u64) {
+        self.release(maker, quantity);
+        self.executed += quantity;
+    }
+}
+
+impl OrderBook {
+    /// Where every share submitted to the book has gone; see [`Accounting`].
+    ///
+    /// A book built from a fixture or a snapshot counts the orders it starts with as
+    /// submitted.
+    pub fn accounting(&self) -> Accounting {
+        self.accounting
+    }
+
+    /// Starts the accounting over from the orders open right now.
+    pub(crate) fn reset_accounting(&mut self) {
+        let resting = |side: &BookSide| side.levels().flat_map(|(_, level)| level).map(Order::remaining).sum::<u64>();
+        let (resting_bids, resting_asks) = (resting(&self.bids), resting(&self.asks));
+        let pending = self.stops.orders().chain(self.auction.iter().flatten()).map(Order::remaining).sum::<u64>();
+        self.accounting = Accounting {
+            submitted: resting_bids + resting_asks + pending,
+            resting_bids,
+            resting_asks,
+            pending,
+            ..Accounting::default()
+        };
+    }
+
+    #[cfg(test)]
+    pub(crate) fn skip_next_cancel_accounting(&mut self) {
+        self.accounting.skip_cancel = true;
+    }
+}
@@ -0,0 +1,285 @@
+use super::*;
+use std::collections::HashSet;
+
+/// Outcome of [`OrderBook::uncross`], or of [`OrderBook::indicative_auction`] before it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuctionResult {
+    /// The single price every auction trade executes at, or `None` if no buy and sell cross.
+    pub clearing_price: Option<Price>,
+    pub matched_volume: u64,
+    /// Buy quantity minus sell quantity willing to trade at the clearing price; the part of
+    /// the heavier side left unfilled.
+    pub imbalance: i64,
+    /// The auction's trades, followed by those of any stops the clearing price triggered.
+    /// Always empty from `indicative_auction`.
+    pub trades: Vec<Trade>,
+}
+
+impl OrderBook {
+    /// Switches the book into auction mode. Until [`OrderBook::uncross`], new limit and market
+    /// orders are validated and collected but not matched; they can still be cancelled. Stop
+    /// orders go on being held for their trigger as usual. Does nothing if an auction is
+    /// already running.
+    pub fn start_auction(&mut self) {
+        self.auction.get_or_insert_with(Vec::new);
+    }
+
+    pub fn in_auction(&self) -> bool {
+        self.auction.is_some()
+    }
+
+    /// The price, volume and imbalance an uncross would produce right now, for an imbalance
+    /// feed during the auction.
+    pub fn indicative_auction(&self) -> AuctionResult {
+        let (buys, sells) = self.auction_interest();
+        clearing(&buys, &sells, self.last_trade_price)
+    }
+
+    /// Ends the auction: executes every buy and sell that cross at the single clearing price,
+    /// then returns the rest to continuous trading.
+    ///
+    /// Resting orders and orders collected during the auction take part together. The
+    /// clearing price is the limit price that executes the most volume, ties broken by the
+    /// smaller imbalance, then by closeness to the last trade price, then by the lower price.
+    /// Buys are filled in price then time priority (market orders first, resting orders ahead
+    /// of collected ones at the same price) against sells in the same order; each trade reports
+    /// the buy as `taker_id` and the sell as `maker_id`, stamped with the later of their two
+    /// timestamps.
+    ///
+    /// A fill-or-kill order the clearing price would only part fill is cancelled instead, and
+    /// the price found again without it. Unfilled GTC limits rest at their own prices, resting
+    /// orders keeping their place; anything else left over is cancelled. Resting orders that
+    /// don't trade stay on the book untouched, except those queued behind one that only part
+    /// fills, which are put back behind it. Collected orders start counting towards their
+    /// expiry only once they reach the book.
+    pub fn uncross(&mut self) -> AuctionResult {
+        let (mut buys, mut sells) = self.auction_interest();
+        let collected: HashSet<u64> = self.auction.take().unwrap_or_default().iter().map(|order| order.id).collect();
+        for order in buys.iter().chain(&sells).filter(|order| collected.contains(&order.id)) {
+            self.accounting.release(Held::Pending, order.quantity);
+        }
+        let mut result = clearing(&buys, &sells, self.last_trade_price);
+        while let Some(price) = result.clearing_price {
+            let volume = result.matched_volume;
+            let killed = match (part_filled_fok(&buys, price, volume), part_filled_fok(&sells, price, volume)) {
+                (Some(i), _) => buys.remove(i),
+                (None, Some(i)) => sells.remove(i),
+                (None, None) => break,
+            };
+            self.listener.on_order_cancelled(&killed);
+            self.accounting.cancel(Held::Arriving, killed.quantity);
+            result = clearing(&buys, &sells, self.last_trade_price);
+        }
+        let mut last_traded = None;
+        if let Some(price) = result.clearing_price {
+            let (mut b, mut s) = (0, 0);
+            while b < buys.len() && s < sells.len() && crosses(&buys[b], price) && crosses(&sells[s], price) {
+                last_traded = Some((b, s));
+                let (buy, sell) = (&mut buys[b], &mut sells[s]);
+                let quantity = buy.quantity.min(sell.quantity);
+                let trade = Trade {
+                    maker_id: sell.id,
+                    taker_id: buy.id,
+                    price,
+                    quantity,
+                    timestamp: buy.timestamp.max(sell.timestamp),
+                    maker_seq: sell.seq,
+                    taker_seq: buy.seq,
+                };
+                self.listener.on_trade(&trade);
+                self.accounting.trade(Held::Arriving, quantity);
+                result.trades.push(trade);
+
+                buy.quantity -= quantity;
+                buy.taker_quantity += quantity;
+                sell.quantity -= quantity;
+                b += usize::from(buy.quantity == 0);
+                s += usize::from(sell.quantity == 0);
+            }
+
+            self.last_trade_price = Some(price);
+            if let Some(tape) = &mut self.tape {
+                result.trades.iter().for_each(|trade| tape.record(trade));
+            }
+            self.stats.trades += result.trades.len() as u64;
+            self.stats.volume += result.matched_volume;
+        }
+
+        // Pull the resting orders that traded off the book, with any queued behind the last;
+        // whatever of them is left gets put back in order, and the rest never moves
+        let (last_buy, last_sell) = last_traded.unzip();
+        let (pulled_buys, pulled_sells) = (pulled(&buys, last_buy, &collected), pulled(&sells, last_sell, &collected));
+        let mut emptied = Vec::new();
+        for order in buys[..pulled_buys].iter().chain(&sells[..pulled_sells]) {
+            if let Some(order) = self.remove_resting(order.id) {
+                self.accounting.release(Held::Resting(order.side), order.quantity);
+                emptied.push((order.side, order.price));
+            }
+        }
+        emptied.dedup();
+        let keep = |orders: Vec<Order>, pulled: usize| -> Vec<Order> {
+            let orders = orders.into_iter().enumerate();
+            orders.filter(|(i, order)| *i < pulled || collected.contains(&order.id)).map(|(_, order)| order).collect()
+        };
+        let (buys, sells) = (keep(buys, pulled_buys), keep(sells, pulled_sells));
+
+        for order in buys.into_iter().chain(sells) {
+            if order.quantity == 0 {
+                self.recent_fills.push(&order);
+                continue;
+            }
+            let is_collected = collected.contains(&order.id);
+            if order.order_type != OrderType::Limit || order.time_in_force != TimeInForce::Gtc {
+                self.listener.on_order_cancelled(&order);
+                self.accounting.cancel(Held::Arriving, order.quantity);
+                continue;
+            }
+
+            let id = order.id;
+            self.accounting.hold(Held::Resting(order.side), order.quantity);
+            if is_collected && let Some(expires_at) = order.expires_at {
+                self.expiries.push(Reverse((expires_at, id)));
+            }
+            let own = match order.side {
+                Side::Buy => &mut self.bids,
+                Side::Sell => &mut self.asks,
+            };
+            own.insert(order);
+            if let Some(feed) = &mut self.order_data {
+                feed.publish(own, self.now);
+            }
+            if is_collected && let Some(order) = own.get(id) {
+                self.listener.on_order_rested(order);
+            }
+        }
+
+        for (side, price) in emptied {
+            self.report_if_level_removed(side, price);
+        }
+        self.enforce_level_cap(Side::Buy);
+        self.enforce_level_cap(Side::Sell);
+
+        let mut report = ExecutionReport { trades: std::mem::take(&mut result.trades), ..Default::default() };
+        self.trigger_stops(&mut report);
+        result.trades = report.trades;
+        self.publish_market_data();
+        self.check_invariants();
+        result
+    }
+
+    /// Every buy and sell that could take part in an uncross, each in fill priority, with
+    /// iceberg reserves folded into `quantity`.
+    fn auction_interest(&self) -> (Vec<Order>, Vec<Order>) {
+        let resting = |side: &BookSide| -> Vec<Order> {
+            let orders = side.iter_best_first().flat_map(|(_, level)| level.iter().cloned());
+            orders.map(|order| Order { quantity: order.remaining(), hidden_quantity: 0, ..order }).collect()
+        };
+        let (mut buys, mut sells) = (resting(&self.bids), resting(&self.asks));
+        for order in self.auction.iter().flatten() {
+            match order.side {
+                Side::Buy => buys.push(order.clone()),
+                Side::Sell => sells.push(order.clone()),
+            }
+        }
+
+        // Stable, so resting orders stay ahead of collected ones at the same price
+        buys.sort_by_key(|order| (order.order_type != OrderType::Market, Reverse(order.price)));
+        sells.sort_by_key(|order| (order.order_type != OrderType::Market, order.price));
+        (buys, sells)
+    }
+
+    /// Adds an accepted order to the running auction's collected orders.
+    pub(crate) fn collect(&mut self, order: Order) {
+        self.listener.on_order_accepted(&order);
+        self.accounting.hold(Held::Pending, order.remaining());
+        if let Some(collected) = &mut self.auction {
+            collected.push(order);
+        }
+    }
+
+    /// Shrinks a collected order's open quantity in place, keeping its place among them.
+    /// Returns `false` (and changes nothing) if it isn't there or `quantity` isn't a decrease.
+    pub(crate) fn reduce_collected(&mut self, id: u64, quantity: u64) -> bool {
+        let Some(order) = self.auction.iter_mut().flatten().find(|order| order.id == id) else {
+            return false;
+        };
+        if quantity >= order.remaining() {
+            return false;
+        }
+        order.quantity = quantity;
+        order.hidden_quantity = 0;
+        true
+    }
+
+    /// Takes an order collected by the running auction back out, if it is there.
+    pub(crate) fn cancel_collected(&mut self, id: u64) -> Option<Order> {
+        let collected = self.auction.as_mut()?;
+        let position = collected.iter().position(|order| order.id == id)?;
+        Some(collected.remove(position))
+    }
+}
+
+/// Whether `order` is willing to trade at `price`.
+fn crosses(order: &Order, price: Price) -> bool {
+    match (order.order_type, order.side) {
+        (OrderType::Market, _) => true,
+        (_, Side::Buy) => order.price >= price,
+        (_, Side::Sell) => order.price <= price,
+    }
+}
+
+/// Position of the first fill-or-kill order in `orders` (one side, in fill priority) that an
+/// uncross matching `volume` at `price` would fill only in part.
+fn part_filled_fok(orders: &[Order], price: Price, volume: u64) -> Option<usize> {
+    let mut filled = 0;
+    for (i, order) in orders.iter().enumerate().take_while(|(_, order)| crosses(order, price)) {
+        let fill = order.quantity.min(volume - filled);
+        if order.time_in_force == TimeInForce::Fok && 0 < fill && fill < order.quantity {
+            return Some(i);
+        }
+        filled += fill;
+    }
+    None
+}
+
+/// How many of `orders` (one side, in fill priority) come off the book for an uncross whose
+/// last trade on this side was with `orders[last]`: every order up to it, and if it is a
+/// resting order that only part filled, those queued behind it at its price, so it can go back
+/// ahead of them.
+fn pulled(orders: &[Order], last: Option<usize>, collected: &HashSet<u64>) -> usize {
+    let Some(last) = last else {
+        return 0;
+    };
+    let order = &orders[last];
+    if order.quantity == 0 || collected.contains(&order.id) {
+        return last + 1;
+    }
+    last + 1 + orders[last + 1..].iter().take_while(|behind| behind.price == order.price).count()
+}
+
+/// Picks the clearing price for `buys` and `sells` (see [`OrderBook::uncross`]).
+fn clearing(buys: &[Order], sells: &[Order], last_trade_price: Option<Price>) -> AuctionResult {
+    let limits = buys.iter().chain(sells).filter(|order| order.order_type != OrderType::Market);
+    let mut candidates: Vec<Price> = limits.map(|order| order.price).chain(last_trade_price).collect();
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    let at = |price: Price| {
+        let demand: u64 = buys.iter().filter(|order| crosses(order, price)).map(|order| order.quantity).sum();
+        let supply: u64 = sells.iter().filter(|order| crosses(order, price)).map(|order| order.quantity).sum();
+        (price, demand.min(supply), demand as i64 - supply as i64)
+    };
+    let distance = |price: Price| last_trade_price.map_or(0, |last| (price.0 - last.0).abs());
+    let best = candidates
+        .into_iter()
+        .map(at)
+        .filter(|&(_, volume, _)| volume > 0)
+        .min_by_key(|&(price, volume, imbalance)| (Reverse(volume), imbalance.abs(), distance(price), price));
+
+    match best {
+        Some((price, matched_volume, imbalance)) => {
+            AuctionResult { clearing_price: Some(price), matched_volume, imbalance, trades: Vec::new() }
+        }
+        None => AuctionResult::default(),
+    }
+}
@@ -0,0 +1,785 @@
+use super::*;
+use super::price_level::LevelQueue;
+use super::slab::{Handle, OrderSlab};
+use std::collections::{btree_map, HashMap};
+
+/// One side of the order book: its price levels plus cached best price and totals.
+///
+/// Levels are keyed by price in a `BTreeMap` (always sorted Low -> High); what counts as the
+/// "best" end of the map depends on which side this is. Everything that needs that knowledge
+/// lives here so `OrderBook` only has to deal with cross-side logic.
+///
+/// The orders themselves live in a slab owned by the side, moved in once when they rest. Each
+/// level's queue links their handles, so adding, filling and cancelling never copy an order
+/// and emptied levels free nothing but their map entry.
+#[derive(Debug)]
+pub struct BookSide {
+    side: Side,
+    levels: BTreeMap<Price, LevelQueue>,
+    orders: OrderSlab,
+    best: Option<Price>,
+    total_quantity: u64,
+    order_count: usize,
+    /// Slab handle of every resting order, so lookup and removal by id don't scan the levels.
+    index: HashMap<u64, Handle>,
+    /// Levels whose quantity may have changed since the last `clear_touched`.
+    touched: Vec<Price>,
+    /// Makers the sweep has completely filled since the last `take_filled`.
+    filled: Vec<Order>,
+    /// Order-level changes since the last `take_order_changes`, once `record_order_changes`
+    /// turns them on.
+    order_changes: Option<Vec<L3Change>>,
+}
+
+impl BookSide {
+    pub fn new(side: Side) -> Self {
+        Self {
+            side,
+            levels: BTreeMap::new(),
+            orders: OrderSlab::default(),
+            best: None,
+            total_quantity: 0,
+            order_count: 0,
+            index: HashMap::new(),
+            touched: Vec::new(),
+            filled: Vec::new(),
+            order_changes: None,
+        }
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// The price levels, sorted Low -> High regardless of side.
+    pub fn levels(&self) -> Levels<'_> {
+        Levels { levels: self.levels.iter(), orders: &self.orders }
+    }
+
+    /// The level at `price`, if any order rests there.
+    pub fn level(&self, price: Price) -> Option<PriceLevel<'_>> {
+        self.levels.get(&price).map(|queue| PriceLevel::new(queue, &self.orders))
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Sum of the visible quantity of every order on this side; hidden iceberg reserves are
+    /// not included.
+    pub fn total_quantity(&self) -> u64 {
+        self.total_quantity
+    }
+
+    /// Number of resting orders on this side.
+    pub fn order_count(&self) -> usize {
+        self.order_count
+    }
+
+    /// Whether an order with `id` is resting on this side.
+    pub fn contains(&self, id: u64) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    /// Looks up a resting order by id.
+    pub fn get(&self, id: u64) -> Option<&Order> {
+        Some(self.orders.order(*self.index.get(&id)?))
+    }
+
+    /// Looks up a resting order by id along with the number of orders ahead of it at its
+    /// level.
+    pub fn queue_position(&self, id: u64) -> Option<(usize, &Order)> {
+        let order = self.get(id)?;
+        let position = self.level(order.price)?.iter().position(|queued| queued.id == id)?;
+        Some((position, order))
+    }
+
+    /// Shrinks a resting order's total open quantity in place, keeping its position in the
+    /// queue. An iceberg gives up hidden reserve before visible quantity.
+    ///
+    /// Returns `false` (and changes nothing) if the order isn't on this side or `quantity`
+    /// isn't a strict decrease to a non-zero size.
+    pub fn reduce(&mut self, id: u64, quantity: u64) -> bool {
+        let Some(&handle) = self.index.get(&id) else {
+            return false;
+        };
+        let price = self.orders.order(handle).price;
+        let level = self.levels.get_mut(&price);
+        let Some(removed) = level.and_then(|level| level.reduce(&mut self.orders, handle, quantity)) else {
+            return false;
+        };
+
+        self.total_quantity -= removed;
+        self.touched.push(price);
+        if removed > 0 {
+            let cancelled = L3Change::OrderCancelled { side: self.side, price, order_id: id, quantity: removed };
+            record(&mut self.order_changes, cancelled);
+        }
+        true
+    }
+
+    /// Visible quantity resting at `price`, if there is a level there.
+    pub fn level_quantity(&self, price: Price) -> Option<u64> {
+        self.levels.get(&price).map(LevelQueue::total_quantity)
+    }
+
+    /// Levels touched since the last `clear_touched`, in the order they were touched (repeats
+    /// possible), for market data.
+    pub(crate) fn touched(&self) -> &[Price] {
+        &self.touched
+    }
+
+    pub(crate) fn clear_touched(&mut self) {
+        self.touched.clear();
+    }
+
+    /// Makers filled by sweeps since the last call, in the order they completed.
+    pub(crate) fn take_filled(&mut self) -> Vec<Order> {
+        std::mem::take(&mut self.filled)
+    }
+
+    /// Starts keeping every order-level change to this side for an L3 feed, from now on.
+    pub(crate) fn record_order_changes(&mut self) {
+        self.order_changes = Some(Vec::new());
+    }
+
+    /// Drains the order-level changes since the last call, in the order they happened; empty
+    /// unless `record_order_changes` was called.
+    pub(crate) fn drain_order_changes(&mut self) -> impl Iterator<Item = L3Change> + '_ {
+        self.order_changes.iter_mut().flat_map(|changes| changes.drain(..))
+    }
+
+    pub(crate) fn has_order_changes(&self) -> bool {
+        self.order_changes.as_ref().is_some_and(|changes| !changes.is_empty())
+    }
+
+    /// Best price level: the highest bid or the lowest ask.
+    pub fn best(&self) -> Option<(Price, PriceLevel<'_>)> {
+        let price = self.best?;
+        self.level(price).map(|level| (price, level))
+    }
+
+    /// Iterates price levels starting from the best one.
+    pub fn iter_best_first(&self) -> BestFirst<'_> {
+        BestFirst { side: self.side, levels: self.levels() }
+    }
+
+    /// Quantity `taker` could execute against this side right now, walking levels best-first
+    /// up to its limit. Hidden iceberg reserves count, since they trade as soon as their visible
+    /// slice does. Stops counting once the taker's full quantity is covered.
+    ///
+    /// Orders `stp_policy` would cancel instead of trading don't count, and reaching one that
+    /// would cancel the taker ends the walk, as does reaching a level `collar` stops at.
+    pub(crate) fn available(&self, taker: &Order, stp_policy: StpPolicy, mut collar: Collar) -> u64 {
+        let mut available = 0;
+        for (price, level) in self.iter_best_first() {
+            if !reaches(self.side, price, taker, &mut collar) {
+                break;
+            }
+            collar.record(price);
+            for maker in level {
+                if available >= taker.quantity {
+                    return available;
+                }
+                if self_trade(stp_policy, maker, taker).is_none() {
+                    available += maker.remaining();
+                } else if stp_policy != StpPolicy::CancelOldest {
+                    return available;
+                }
+            }
+        }
+        available
+    }
+
+    /// What `taker` would get from the visible quantity on this side right now, walking the
+    /// same levels [`BookSide::sweep`] would. Changes nothing.
+    pub(crate) fn simulate(&self, taker: &Order, mut collar: Collar) -> FillSimulation {
+        let mut simulation = FillSimulation { unfilled_quantity: taker.quantity, ..FillSimulation::default() };
+        for (price, level) in self.iter_best_first() {
+            if simulation.unfilled_quantity == 0 || !reaches(self.side, price, taker, &mut collar) {
+                break;
+            }
+            let quantity = simulation.unfilled_quantity.min(level.total_quantity());
+            simulation.filled_quantity += quantity;
+            simulation.unfilled_quantity -= quantity;
+            simulation.notional += price.0 as i128 * quantity as i128;
+            simulation.worst_price = Some(price);
+            simulation.levels += 1;
+            collar.record(price);
+        }
+        simulation
+    }
+
+    /// Appends an order to the back of the queue at its price. An iceberg that hasn't been
+    /// split yet rests with only its first slice visible.
+    pub fn insert(&mut self, mut order: Order) {
+        if order.hidden_quantity == 0 {
+            order.split_display();
+        }
+        let price = order.price;
+        if self.best.is_none_or(|best| self.is_better(price, best)) {
+            self.best = Some(price);
+        }
+
+        self.total_quantity += order.quantity;
+        self.order_count += 1;
+        self.touched.push(price);
+        let (id, quantity) = (order.id, order.quantity);
+        record(&mut self.order_changes, L3Change::OrderAdded { side: self.side, price, order_id: id, quantity });
+        let handle = self.levels.entry(price).or_default().push_back(&mut self.orders, order);
+        self.index.insert(id, handle);
+    }
+
+    /// Removes the resting order with `id`, cleaning up its level if it empties. An iceberg
+    /// comes back with its hidden reserve folded into `quantity`.
+    pub fn remove(&mut self, id: u64) -> Option<Order> {
+        let handle = self.index.remove(&id)?;
+        let price = self.orders.order(handle).price;
+        let btree_map::Entry::Occupied(mut entry) = self.levels.entry(price) else {
+            return None;
+        };
+        self.touched.push(price);
+
+        let level = entry.get_mut();
+        let mut order = level.remove(&mut self.orders, handle)?;
+        if level.is_empty() {
+            entry.remove();
+            self.refresh_best();
+        }
+
+        self.total_quantity -= order.quantity;
+        self.order_count -= 1;
+        let cancelled = L3Change::OrderCancelled { side: self.side, price, order_id: id, quantity: order.quantity };
+        record(&mut self.order_changes, cancelled);
+        order.quantity = order.remaining();
+        order.hidden_quantity = 0;
+        Some(order)
+    }
+
+    /// The worst price level: the lowest bid or the highest ask.
+    pub fn worst(&self) -> Option<(Price, PriceLevel<'_>)> {
+        match self.side {
+            Side::Buy => self.levels().next(),
+            Side::Sell => self.levels().next_back(),
+        }
+    }
+
+    /// Removes every order at the worst level, front of the queue first. Icebergs come back
+    /// with their hidden reserve folded into `quantity`, as from [`BookSide::remove`].
+    pub(crate) fn remove_worst_level(&mut self) -> Option<(Price, Vec<Order>)> {
+        let (price, mut level) = match self.side {
+            Side::Buy => self.levels.pop_first(),
+            Side::Sell => self.levels.pop_last(),
+        }?;
+        self.touched.push(price);
+
+        let mut removed = Vec::new();
+        while let Some(mut order) = level.pop_front(&mut self.orders) {
+            self.index.remove(&order.id);
+            self.total_quantity -= order.quantity;
+            self.order_count -= 1;
+            let (order_id, quantity) = (order.id, order.quantity);
+            let cancelled = L3Change::OrderCancelled { side: self.side, price, order_id, quantity };
+            record(&mut self.order_changes, cancelled);
+            order.quantity = order.remaining();
+            order.hidden_quantity = 0;
+            removed.push(order);
+        }
+        self.refresh_best();
+        Some((price, removed))
+    }
+
+    /// Matches `taker` against this side, best price first and FIFO within a level, until it is
+    /// filled or the next level is beyond its limit price (market orders have no limit). Every
+    /// execution is appended to `report`, along with any cancellations `stp_policy` makes
+    /// instead of letting a participant trade with itself, and passed to `listener` as it happens.
+    ///
+    /// Under [`MatchPolicy::ProRata`] a taker too small to clear a level is first shared out
+    /// across it in proportion to each order's visible size, and whatever rounding leaves over
+    /// is then matched FIFO as usual. A level holding an order `stp_policy` would act on is
+    /// matched FIFO only.
+    ///
+    /// The sweep also stops at the first level `collar` refuses, leaving `collar.tripped` set.
+    pub(crate) fn sweep(
+        &mut self,
+        taker: &mut Order,
+        stp_policy: StpPolicy,
+        match_policy: MatchPolicy,
+        collar: &mut Collar,
+        report: &mut ExecutionReport,
+        listener: &mut dyn OrderBookListener,
+    ) {
+        while taker.quantity > 0 {
+            // Bids are matched from the HIGHEST price (end of the map),
+            // asks from the LOWEST price (start of the map).
+            let entry = match self.side {
+                Side::Buy => self.levels.last_entry(),
+                Side::Sell => self.levels.first_entry(),
+            };
+            let Some(mut entry) = entry else {
+                break; // No liquidity left on this side
+            };
+
+            // If the best level is worse than the taker's limit, break
+            let level_price = *entry.key();
+            if !reaches(self.side, level_price, taker, collar) {
+                break;
+            }
+
+            // Drain the level front to back; the tree is only touched again once the level
+            // is exhausted or the taker is done.
+            self.touched.push(level_price);
+            let level = entry.get_mut();
+
+            let view = PriceLevel::new(level, &self.orders);
+            if let MatchPolicy::ProRata { min_allocation } = match_policy
+                && taker.quantity < view.total_quantity()
+                && view.iter().all(|maker| self_trade(stp_policy, maker, taker).is_none())
+            {
+                let mut allocations = Vec::with_capacity(view.order_count());
+                allocate_pro_rata(view, taker.quantity, min_allocation, &mut allocations);
+                for (maker, &quantity) in view.iter().zip(&allocations).filter(|(_, quantity)| **quantity > 0) {
+                    let trade = Trade {
+                        maker_id: maker.id,
+                        taker_id: taker.id,
+                        price: level_price,
+                        quantity,
+                        timestamp: taker.timestamp,
+                        maker_seq: maker.seq,
+                        taker_seq: taker.seq,
+                    };
+                    listener.on_trade(&trade);
+                    report.trades.push(trade);
+                    let (side, order_id) = (self.side, maker.id);
+                    let executed = L3Change::OrderExecuted { side, price: level_price, order_id, quantity };
+                    record(&mut self.order_changes, executed);
+                }
+                collar.record(level_price);
+
+                // Every share is below its order's size, so the round never empties an order
+                let filled: u64 = allocations.iter().sum();
+                level.fill_each(&mut self.orders, &allocations);
+                taker.quantity -= filled;
+                self.total_quantity -= filled;
+            }
+
+            while taker.quantity > 0 {
+                let Some(front) = level.front() else {
+                    break;
+                };
+                let maker = self.orders.order(front);
+
+                // Cancel instead of trading against our own participant
+                if let Some(participant_id) = self_trade(stp_policy, maker, taker) {
+                    if stp_policy != StpPolicy::CancelNewest
+                        && let Some(mut maker) = level.pop_front(&mut self.orders)
+                    {
+                        self.index.remove(&maker.id);
+                        self.total_quantity -= maker.quantity;
+                        self.order_count -= 1;
+                        let (order_id, quantity) = (maker.id, maker.quantity);
+                        let (side, price) = (self.side, level_price);
+                        let cancelled = L3Change::OrderCancelled { side, price, order_id, quantity };
+                        record(&mut self.order_changes, cancelled);
+                        maker.quantity = maker.remaining();
+                        maker.hidden_quantity = 0;
+                        report.self_trade_cancels.push(SelfTradeCancel {
+                            order_id: maker.id,
+                            participant_id,
+                            quantity: maker.quantity,
+                            resting: true,
+                        });
+                        listener.on_order_cancelled(&maker);
+                    }
+                    if stp_policy != StpPolicy::CancelOldest {
+                        report.self_trade_cancels.push(SelfTradeCancel {
+                            order_id: taker.id,
+                            participant_id,
+                            quantity: taker.quantity,
+                            resting: false,
+                        });
+                        listener.on_order_cancelled(taker);
+                        taker.quantity = 0;
+                    }
+                    continue;
+                }
+
+                // Execute the trade
+                let trade_qty = taker.quantity.min(maker.quantity);
+                let trade = Trade {
+                    maker_id: maker.id,
+                    taker_id: taker.id,
+                    price: level_price,
+                    quantity: trade_qty,
+                    timestamp: taker.timestamp,
+                    maker_seq: maker.seq,
+                    taker_seq: taker.seq,
+                };
+                listener.on_trade(&trade);
+                report.trades.push(trade);
+                collar.record(level_price);
+
+                // Update the quantities as per the trade quantity
+                let (side, price, order_id) = (self.side, level_price, self.orders.order(front).id);
+                let executed = L3Change::OrderExecuted { side, price, order_id, quantity: trade_qty };
+                record(&mut self.order_changes, executed);
+                taker.quantity -= trade_qty;
+                level.fill_front(&mut self.orders, trade_qty);
+                self.total_quantity -= trade_qty;
+
+                // An iceberg whose slice is used up shows its next slice at the back of the
+                // level, behind everyone else; other completed orders leave the queue
+                if self.orders.order(front).quantity == 0 {
+                    let shown = level.total_quantity();
+                    if level.replenish_front(&mut self.orders) {
+                        self.total_quantity += level.total_quantity() - shown;
+                        let quantity = self.orders.order(front).quantity;
+                        let added = L3Change::OrderAdded { side: self.side, price: level_price, order_id, quantity };
+                        record(&mut self.order_changes, added);
+                    } else if let Some(maker) = level.pop_front(&mut self.orders) {
+                        self.index.remove(&maker.id);
+                        self.order_count -= 1;
+                        self.filled.push(maker);
+                    }
+                }
+            }
+
+            // Cleanup empty price levels
+            if level.is_empty() {
+                entry.remove();
+                self.refresh_best();
+                listener.on_level_removed(self.side, level_price);
+            }
+        }
+    }
+
+    /// Checks every level and order on this side and recomputes every cached total and index
+    /// entry from scratch.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        let (mut quantity, mut orders) = (0, 0);
+        for (&price, queue) in &self.levels {
+            if queue.is_empty() {
+                return Err(InvariantViolation::EmptyLevel { side: self.side, price });
+            }
+            if !queue.links_intact(&self.orders) {
+                return Err(InvariantViolation::BrokenQueue { side: self.side, price });
+            }
+            let level = PriceLevel::new(queue, &self.orders);
+            for order in level {
+                if order.side != self.side || order.price != price {
+                    return Err(InvariantViolation::MisplacedOrder { side: self.side, price, order_id: order.id });
+                }
+                if order.quantity == 0 {
+                    return Err(InvariantViolation::ZeroQuantity { side: self.side, price, order_id: order.id });
+                }
+            }
+
+            let actual = level.recomputed_quantity();
+            if actual != level.total_quantity() {
+                return Err(InvariantViolation::LevelQuantity { side: self.side, price, cached: level.total_quantity(), actual });
+            }
+            // The index must lead back to this very slot, not just to an order with the same id
+            let indexed = |order: &Order| self.get(order.id).is_some_and(|indexed| std::ptr::eq(indexed, order));
+            if let Some(order) = level.iter().find(|order| !indexed(order)) {
+                return Err(InvariantViolation::Index { side: self.side, price, order_id: order.id });
+            }
+            quantity += actual;
+            orders += level.order_count();
+        }
+
+        let actual_best = match self.side {
+            Side::Buy => self.levels.keys().next_back().copied(),
+            Side::Sell => self.levels.keys().next().copied(),
+        };
+        if self.best != actual_best {
+            return Err(InvariantViolation::StaleBest { side: self.side, cached: self.best, actual: actual_best });
+        }
+
+        let stored = (self.index.len(), self.orders.len());
+        if quantity != self.total_quantity || orders != self.order_count || stored != (orders, orders) {
+            return Err(InvariantViolation::SideTotals {
+                side: self.side,
+                cached_quantity: self.total_quantity,
+                actual_quantity: quantity,
+                cached_orders: self.order_count,
+                actual_orders: orders,
+            });
+        }
+        Ok(())
+    }
+
+    /// Raw access to the levels and the orders they link, for tests that need to corrupt the
+    /// book on purpose.
+    #[cfg(test)]
+    pub(crate) fn levels_mut(&mut self) -> (&mut BTreeMap<Price, LevelQueue>, &mut OrderSlab) {
+        (&mut self.levels, &mut self.orders)
+    }
+
+    fn is_better(&self, price: Price, than: Price) -> bool {
+        match self.side {
+            Side::Buy => price > than,
+            Side::Sell => price < than,
+        }
+    }
+
+    fn refresh_best(&mut self) {
+        self.best = match self.side {
+            Side::Buy => self.levels.keys().next_back().copied(),
+            Side::Sell => self.levels.keys().next().copied(),
+        };
+    }
+}
+
+/// Splits `quantity` across the orders at `level` in proportion to their visible size,
+/// rounding down, into `allocations` (one entry per order, in queue order). `quantity` must be
+/// less than the level's total. A share below `min_allocation` is dropped. The lots lost to
+/// rounding and dropped shares are not allocated here; they go to the front of the queue
+/// through ordinary FIFO matching.
+fn allocate_pro_rata(level: PriceLevel<'_>, quantity: u64, min_allocation: u64, allocations: &mut Vec<u64>) {
+    let total = level.total_quantity() as u128;
+    allocations.extend(level.iter().map(|maker| {
+        let share = (quantity as u128 * maker.quantity as u128 / total) as u64;
+        if share < min_allocation { 0 } else { share }
+    }));
+}
+
+/// Whether `taker` can trade at `level_price` on `side`; market orders have no limit.
+fn is_marketable(side: Side, level_price: Price, taker: &Order) -> bool {
+    match (taker.order_type, side) {
+        (OrderType::Market | OrderType::Stop { .. }, _) => true,
+        (_, Side::Buy) => level_price >= taker.price,
+        (_, Side::Sell) => level_price <= taker.price,
+    }
+}
+
+/// Appends `change` to a side's order-level changes, if it is keeping them.
+fn record(order_changes: &mut Option<Vec<L3Change>>, change: L3Change) {
+    if let Some(order_changes) = order_changes {
+        order_changes.push(change);
+    }
+}
+
+/// Whether a sweep by `taker` goes on to the level at `level_price` on `side`: the level is
+/// within the taker's limit and `collar` lets it through. Every walk of the book goes through
+/// this, so matching, the FOK pre-check and fill simulation always stop at the same level.
+fn reaches(side: Side, level_price: Price, taker: &Order, collar: &mut Collar) -> bool {
+    is_marketable(side, level_price, taker) && collar.allows(level_price)
+}
+
+/// Participant both orders belong to, if `stp_policy` stops them from trading with each other.
+fn self_trade(stp_policy: StpPolicy, maker: &Order, taker: &Order) -> Option<u64> {
+    match (stp_policy, maker.participant_id, taker.participant_id) {
+        (StpPolicy::None, _, _) => None,
+        (_, Some(maker_participant), Some(taker_participant)) if maker_participant == taker_participant => {
+            Some(maker_participant)
+        }
+        _ => None,
+    }
+}
+
+/// Iterator over a side's price levels from low to high price, see [`BookSide::levels`].
+#[derive(Clone)]
+pub struct Levels<'a> {
+    levels: btree_map::Iter<'a, Price, LevelQueue>,
+    orders: &'a OrderSlab,
+}
+
+impl<'a> Iterator for Levels<'a> {
+    type Item = (Price, PriceLevel<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (price, queue) = self.levels.next()?;
+        Some((*price, PriceLevel::new(queue, self.orders)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.levels.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Levels<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (price, queue) = self.levels.next_back()?;
+        Some((*price, PriceLevel::new(queue, self.orders)))
+    }
+}
+
+impl ExactSizeIterator for Levels<'_> {}
+
+/// Iterator over a side's price levels from best to worst, see [`BookSide::iter_best_first`].
+pub struct BestFirst<'a> {
+    side: Side,
+    levels: Levels<'a>,
+}
+
+impl<'a> Iterator for BestFirst<'a> {
+    type Item = (Price, PriceLevel<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.side {
+            Side::Buy => self.levels.next_back(),
+            Side::Sell => self.levels.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn prices(side: &BookSide) -> Vec<Price> {
+        side.iter_best_first().map(|(price, _)| price).collect()
+    }
+
+    #[test]
+    fn test_bids_iterate_highest_first() {
+        let mut bids = BookSide::new(Side::Buy);
+        bids.insert(Order::limit(1, Side::Buy, Price(99), 10));
+        bids.insert(Order::limit(2, Side::Buy, Price(101), 10));
+        bids.insert(Order::limit(3, Side::Buy, Price(100), 10));
+
+        assert_eq!(prices(&bids), vec![Price(101), Price(100), Price(99)]);
+        assert_eq!(bids.best().unwrap().0, Price(101));
+    }
+
+    #[test]
+    fn test_asks_iterate_lowest_first() {
+        let mut asks = BookSide::new(Side::Sell);
+        asks.insert(Order::limit(1, Side::Sell, Price(101), 10));
+        asks.insert(Order::limit(2, Side::Sell, Price(99), 10));
+        asks.insert(Order::limit(3, Side::Sell, Price(100), 10));
+
+        assert_eq!(prices(&asks), vec![Price(99), Price(100), Price(101)]);
+        assert_eq!(asks.best().unwrap().0, Price(99));
+    }
+
+    #[test]
+    fn test_remove_updates_best_and_totals() {
+        let mut asks = BookSide::new(Side::Sell);
+        asks.insert(Order::limit(1, Side::Sell, Price(100), 10));
+        asks.insert(Order::limit(2, Side::Sell, Price(100), 20));
+        asks.insert(Order::limit(3, Side::Sell, Price(101), 30));
+        assert_eq!((asks.total_quantity(), asks.order_count()), (60, 3));
+
+        assert_eq!(asks.remove(1).unwrap().id, 1);
+        assert_eq!(asks.best().unwrap().0, Price(100));
+        assert_eq!(asks.remove(2).unwrap().id, 2);
+        assert_eq!(asks.best().unwrap().0, Price(101));
+        assert!(asks.remove(2).is_none());
+        assert!(asks.remove(99).is_none());
+        assert!(asks.contains(3));
+        assert_eq!((asks.total_quantity(), asks.order_count()), (30, 1));
+    }
+
+    #[test]
+    fn test_sweep_respects_limit_and_fifo() {
+        let mut bids = BookSide::new(Side::Buy);
+        bids.insert(Order::limit(1, Side::Buy, Price(100), 10));
+        bids.insert(Order::limit(2, Side::Buy, Price(100), 10));
+        bids.insert(Order::limit(3, Side::Buy, Price(99), 10));
+
+        // Sell 25 @ 100 clears the 100 level in FIFO order and never reaches 99
+        let mut taker = Order::limit(4, Side::Sell, Price(100), 25);
+        let mut report = ExecutionReport::default();
+        let mut collar = Collar::new(&taker);
+        bids.sweep(&mut taker, StpPolicy::None, MatchPolicy::Fifo, &mut collar, &mut report, &mut NoopListener);
+
+        assert_eq!(taker.quantity, 5);
+        assert_eq!(report.trades.iter().map(|trade| trade.maker_id).collect::<Vec<_>>(), vec![1, 2]);
+        let (price, queue) = bids.best().unwrap();
+        assert_eq!(price, Price(99));
+        assert_eq!(queue.front().unwrap().id, 3);
+        assert_eq!((bids.total_quantity(), bids.order_count()), (10, 1));
+        assert!(!bids.contains(1) && !bids.contains(2));
+    }
+
+    #[test]
+    fn test_reduce_iceberg_takes_hidden_first() {
+        let mut asks = BookSide::new(Side::Sell);
+        asks.insert(Order::limit(1, Side::Sell, Price(100), 1_000).with_display_quantity(100));
+        assert_eq!(asks.total_quantity(), 100);
+
+        assert!(asks.reduce(1, 150));
+        let order = asks.get(1).unwrap();
+        assert_eq!((order.quantity, order.hidden_quantity), (100, 50));
+
+        assert!(asks.reduce(1, 40));
+        let order = asks.get(1).unwrap();
+        assert_eq!((order.quantity, order.hidden_quantity), (40, 0));
+        assert_eq!(asks.total_quantity(), 40);
+    }
+
+    #[test]
+    fn test_slab_reuse_under_churn_keeps_fifo_and_totals() {
+        let mut asks = BookSide::new(Side::Sell);
+        // The expected queue at each price, as (id, quantity) front to back
+        let mut model: BTreeMap<Price, VecDeque<(u64, u64)>> = BTreeMap::new();
+        let mut rng = StdRng::seed_from_u64(17);
+        let (mut next_id, mut peak) = (1, 0);
+
+        for step in 0..20_000 {
+            match rng.random_range(0..10) {
+                0..5 => {
+                    let (price, quantity) = (Price(rng.random_range(100..110)), rng.random_range(1..50));
+                    asks.insert(Order::limit(next_id, Side::Sell, price, quantity));
+                    model.entry(price).or_default().push_back((next_id, quantity));
+                    next_id += 1;
+                }
+                5..8 if !model.is_empty() => {
+                    let nth = rng.random_range(0..model.len());
+                    let (&price, queue) = model.iter_mut().nth(nth).unwrap();
+                    let (id, quantity) = queue.remove(rng.random_range(0..queue.len())).unwrap();
+                    if queue.is_empty() {
+                        model.remove(&price);
+                    }
+                    let removed = asks.remove(id).unwrap();
+                    assert_eq!((removed.id, removed.quantity), (id, quantity));
+                }
+                _ => {
+                    let (limit, quantity) = (Price(rng.random_range(100..110)), rng.random_range(1..120));
+                    let mut taker = Order::limit(next_id, Side::Buy, limit, quantity);
+                    next_id += 1;
+                    let (mut collar, mut report) = (Collar::new(&taker), ExecutionReport::default());
+                    let policies = (StpPolicy::None, MatchPolicy::Fifo);
+                    asks.sweep(&mut taker, policies.0, policies.1, &mut collar, &mut report, &mut NoopListener);
+
+                    let mut left = quantity;
+                    while left > 0
+                        && let Some(mut entry) = model.first_entry()
+                        && *entry.key() <= limit
+                    {
+                        let front = entry.get_mut().front_mut().unwrap();
+                        let traded = left.min(front.1);
+                        (left, front.1) = (left - traded, front.1 - traded);
+                        if front.1 == 0 {
+                            entry.get_mut().pop_front();
+                        }
+                        if entry.get().is_empty() {
+                            entry.remove();
+                        }
+                    }
+                    assert_eq!(taker.quantity, left, "step {}", step);
+                    asks.take_filled();
+                }
+            }
+            asks.clear_touched();
+            peak = peak.max(asks.order_count());
+
+            assert_eq!(asks.validate(), Ok(()), "step {}", step);
+            let levels: BTreeMap<Price, VecDeque<(u64, u64)>> = asks
+                .levels()
+                .map(|(price, level)| (price, level.iter().map(|order| (order.id, order.quantity)).collect()))
+                .collect();
+            assert_eq!(levels, model, "step {}", step);
+        }
+
+        // Every slot freed along the way was handed out again before the slab grew
+        assert_eq!(asks.orders.slot_count(), peak);
+    }
+}
@@ -0,0 +1,68 @@
+use super::*;
+
+impl OrderBook {
+    /// Reads book time from `clock` instead of waiting for [`OrderBook::advance_time`].
+    ///
+    /// Every add, cancel and modify first reads the clock and, if it has moved past
+    /// [`OrderBook::now`], advances the book to it, expiring whatever that reaches. Orders
+    /// arriving with a zero timestamp are stamped with the book's time. The clock is never
+    /// read anywhere else, so a simulation can drive it from a counter it controls.
+    ///
+    /// What the clock expires goes to the listener, and [`OrderBook::add_order_with_report`]
+    /// also returns it in [`ExecutionReport::expired`]. Cancels, modifies and adds that are
+    /// rejected return nothing of it, so a caller without a listener that needs every expiry
+    /// should call [`OrderBook::expire_orders`] first.
+    pub fn set_clock(&mut self, clock: Box<dyn Fn() -> u64 + Send>) {
+        self.clock = Some(clock);
+    }
+
+    /// Goes back to moving time only through [`OrderBook::advance_time`].
+    pub fn clear_clock(&mut self) {
+        self.clock = None;
+    }
+
+    pub fn has_clock(&self) -> bool {
+        self.clock.is_some()
+    }
+
+    /// Rejects orders timestamped before the latest timestamp the book has accepted, with
+    /// [`LobError::TimestampBehind`]. Orders with equal timestamps are still accepted and keep
+    /// their arrival order through [`Order::seq`].
+    pub fn with_strict_timestamps(mut self) -> Self {
+        self.strict_timestamps = true;
+        self
+    }
+
+    pub fn strict_timestamps(&self) -> bool {
+        self.strict_timestamps
+    }
+
+    /// [`Order::seq`] of the last order accepted, 0 before the first.
+    pub fn last_sequence(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Expires every order whose expiry `now` has reached, or the clock's reading if the book
+    /// has a clock that is further on, without waiting for the next add, cancel or modify. Like
+    /// [`OrderBook::advance_time`], it never moves time backwards.
+    pub fn expire_orders(&mut self, now: u64) -> Vec<ExpiredOrder> {
+        let reading = self.clock.as_ref().map_or(0, |clock| clock());
+        self.advance_time(now.max(reading))
+    }
+
+    /// Advances the book to the clock's reading, if it has one and it has moved on.
+    pub(crate) fn tick(&mut self) -> Vec<ExpiredOrder> {
+        match self.clock.as_ref().map(|clock| clock()) {
+            Some(reading) if reading > self.now => self.advance_time(reading),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gives an accepted order the next sequence number, stamped with the book's time.
+    pub(crate) fn sequence(&mut self, order: &mut Order) {
+        self.last_seq += 1;
+        order.seq = self.last_seq;
+        order.accepted_at = self.now;
+        self.last_timestamp = self.last_timestamp.max(order.timestamp);
+    }
+}
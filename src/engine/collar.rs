@@ -0,0 +1,151 @@
+use super::*;
+
+/// What a book's price band is centred on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReferencePrice {
+    /// The last trade price, or the mid before the book has traded.
+    LastTrade,
+    /// The mid between best bid and best ask, or the last trade price while one side is empty.
+    Mid,
+}
+
+/// A book's protection against orders far through the market, see
+/// [`OrderBook::set_price_bands`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PriceBand {
+    pub reference: ReferencePrice,
+    /// Half-width of the band as a percentage of the reference price, e.g. `5.0` for ±5%.
+    pub pct: f64,
+}
+
+impl PriceBand {
+    /// The lowest and highest prices inside the band around `reference` (in ticks), rounded
+    /// inwards to whole ticks.
+    fn limits(&self, reference: f64) -> (Price, Price) {
+        let width = reference * self.pct / 100.0;
+        (Price((reference - width).ceil() as i64), Price((reference + width).floor() as i64))
+    }
+}
+
+impl OrderBook {
+    /// Keeps trading within `pct` percent of `reference`.
+    ///
+    /// Limit orders priced outside the band are rejected with [`LobError::OutsidePriceBand`].
+    /// An order that sweeps the book stops before the first level outside the band and its
+    /// remainder is cancelled rather than rested. The reference follows the sweep, so each
+    /// level is measured against the book as the order's own earlier fills left it. With no
+    /// reference at all (no trades and an empty side) nothing is banded.
+    pub fn set_price_bands(&mut self, reference: ReferencePrice, pct: f64) {
+        self.price_band = Some(PriceBand { reference, pct });
+    }
+
+    pub fn clear_price_bands(&mut self) {
+        self.price_band = None;
+    }
+
+    pub fn price_band(&self) -> Option<PriceBand> {
+        self.price_band
+    }
+
+    /// The lowest and highest prices an order arriving now may be priced at, or `None` if
+    /// there is no band or nothing to centre it on.
+    pub fn price_band_limits(&self) -> Option<(Price, Price)> {
+        let band = self.price_band?;
+        let best_bid = self.bids.best().map(|(price, _)| price);
+        let best_ask = self.asks.best().map(|(price, _)| price);
+        let mid = best_bid.zip(best_ask).map(|(bid, ask)| midpoint(bid, ask));
+        Some(band.limits(reference(band.reference, self.last_trade_price, mid)?))
+    }
+
+    /// The band's limits if `price` falls outside them.
+    pub(crate) fn outside_price_band(&self, price: Price) -> Option<(Price, Price)> {
+        self.price_band_limits().filter(|&(low, high)| price < low || price > high)
+    }
+
+    /// The limits on how far `order` may sweep right now.
+    pub(crate) fn collar(&self, order: &Order) -> Collar {
+        Collar {
+            band: self.price_band,
+            own_best: self.side(order.side).best().map(|(price, _)| price),
+            last_trade: self.last_trade_price,
+            ..Collar::new(order)
+        }
+    }
+}
+
+/// Limits on one incoming order's sweep: the book's price band plus the order's own
+/// `max_sweep_levels` and `protection_price`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Collar {
+    side: Side,
+    band: Option<PriceBand>,
+    /// Best price on the taker's own side. It doesn't move during the sweep, so with the
+    /// opposite level being reached it gives the mid the book would show there.
+    own_best: Option<Price>,
+    /// Last trade price, including the sweep's own fills.
+    last_trade: Option<Price>,
+    levels_left: Option<usize>,
+    protection_price: Option<Price>,
+    /// Set once the collar has stopped the sweep before the taker's own limit did.
+    pub(crate) tripped: bool,
+}
+
+impl Collar {
+    /// A collar with only `order`'s own protection and no price band.
+    pub(crate) fn new(order: &Order) -> Self {
+        Self {
+            side: order.side,
+            band: None,
+            own_best: None,
+            last_trade: None,
+            levels_left: order.max_sweep_levels,
+            protection_price: order.protection_price,
+            tripped: false,
+        }
+    }
+
+    /// Whether the sweep may go on to trade at `level`, a price on the opposite side.
+    /// Counts the level against `max_sweep_levels` if it may, and trips the collar if not.
+    pub(crate) fn allows(&mut self, level: Price) -> bool {
+        let beyond_protection = self.protection_price.is_some_and(|protection| match self.side {
+            Side::Buy => level > protection,
+            Side::Sell => level < protection,
+        });
+        let outside_band = self.band.is_some_and(|band| {
+            let mid = self.own_best.map(|own_best| midpoint(own_best, level));
+            reference(band.reference, self.last_trade, mid).is_some_and(|reference| {
+                let (low, high) = band.limits(reference);
+                level < low || level > high
+            })
+        });
+
+        if self.levels_left == Some(0) || beyond_protection || outside_band {
+            self.tripped = true;
+            return false;
+        }
+        if let Some(levels_left) = &mut self.levels_left {
+            *levels_left -= 1;
+        }
+        true
+    }
+
+    /// Moves the band's reference with a fill of the sweep.
+    pub(crate) fn record(&mut self, price: Price) {
+        self.last_trade = Some(price);
+    }
+}
+
+fn midpoint(a: Price, b: Price) -> f64 {
+    (a.0 + b.0) as f64 / 2.0
+}
+
+/// Reference price in ticks, falling back to the other kind when the preferred one is missing.
+fn reference(kind: ReferencePrice, last_trade: Option<Price>, mid: Option<f64>) -> Option<f64> {
+    let last_trade = last_trade.map(|price| price.0 as f64);
+    match kind {
+        ReferencePrice::LastTrade => last_trade.or(mid),
+        ReferencePrice::Mid => mid.or(last_trade),
+    }
+}
@@ -0,0 +1,238 @@
+use super::{Price, Side, SymbolId};
+use std::fmt;
+
+/// Reasons `OrderBook::cancel_order` can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelError {
+    /// No resting order with this id: it never existed, was already fully filled, or was
+    /// already cancelled.
+    NotFound(u64),
+}
+
+impl fmt::Display for CancelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CancelError::NotFound(id) => write!(f, "no resting order with id {}", id),
+        }
+    }
+}
+
+impl std::error::Error for CancelError {}
+
+/// Reasons `OrderBook::modify_order` can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModifyError {
+    /// No resting order with this id.
+    NotFound(u64),
+    /// The new quantity was zero; use `cancel_order` to remove an order.
+    ZeroQuantity,
+    /// The new price isn't a positive number of ticks.
+    InvalidPrice(Price),
+    /// The order is post-only and the new price would trade, under `PostOnlyPolicy::Reject`.
+    PostOnlyWouldCross(Price),
+    /// The new price is outside the book's price band, which spans `low` to `high`.
+    OutsidePriceBand { price: Price, low: Price, high: Price },
+    /// The new price would open a level worse than every other on a side already holding
+    /// `max_levels` levels, under `LevelCapPolicy::Reject`.
+    LevelCapReached { price: Price, max_levels: usize },
+}
+
+impl fmt::Display for ModifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModifyError::NotFound(id) => write!(f, "no resting order with id {}", id),
+            ModifyError::ZeroQuantity => write!(f, "modified quantity must be non-zero"),
+            ModifyError::InvalidPrice(price) => write!(f, "price {} is not positive", price),
+            ModifyError::PostOnlyWouldCross(price) => write!(f, "post-only order at {} would cross the book", price),
+            ModifyError::OutsidePriceBand { price, low, high } => {
+                write!(f, "price {} is outside the price band {} to {}", price, low, high)
+            }
+            ModifyError::LevelCapReached { price, max_levels } => {
+                write!(f, "price {} would open a level beyond the cap of {} levels", price, max_levels)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModifyError {}
+
+/// Reasons a float price cannot be converted to ticks by `OrderBook::price`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceError {
+    /// NaN or infinite.
+    NotFinite(f64),
+    /// Not a multiple of the tick size (only reported in strict mode).
+    OffTick(f64),
+}
+
+impl fmt::Display for PriceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PriceError::NotFinite(price) => write!(f, "price {} is not finite", price),
+            PriceError::OffTick(price) => write!(f, "price {} is not a multiple of the tick size", price),
+        }
+    }
+}
+
+impl std::error::Error for PriceError {}
+
+/// Reasons a `MatchingEngine` call can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineError {
+    /// No symbol with this id was added to the engine.
+    UnknownSymbol(SymbolId),
+    /// No open order with this id on any symbol.
+    UnknownOrder(u64),
+    /// The symbol's book rejected the order.
+    Rejected(LobError),
+    /// An order quantity, or iceberg display quantity, that isn't a multiple of the symbol's
+    /// lot size.
+    OffLot { quantity: u64, lot_size: u64 },
+    /// A tick size that isn't a positive, finite number.
+    InvalidTickSize,
+    ZeroLotSize,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::UnknownSymbol(symbol) => write!(f, "unknown symbol {}", symbol),
+            EngineError::UnknownOrder(id) => write!(f, "no open order with id {}", id),
+            EngineError::Rejected(err) => write!(f, "order rejected: {}", err),
+            EngineError::OffLot { quantity, lot_size } => {
+                write!(f, "quantity {} is not a multiple of the lot size {}", quantity, lot_size)
+            }
+            EngineError::InvalidTickSize => write!(f, "tick size must be positive and finite"),
+            EngineError::ZeroLotSize => write!(f, "lot size must be non-zero"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/// Reasons `OrderBook::add_order` rejects an order. A rejected order leaves the book untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LobError {
+    /// A limit, stop or stop-limit price that isn't a positive number of ticks.
+    InvalidPrice(Price),
+    ZeroQuantity,
+    /// An order with this id is already resting or pending as a stop.
+    DuplicateOrderId(u64),
+    /// A post-only order whose price would trade on arrival, under `PostOnlyPolicy::Reject`.
+    PostOnlyWouldCross(Price),
+    /// An order whose expiry the book's time has already reached.
+    Expired(u64),
+    /// A limit price outside the book's price band, which spans `low` to `high`.
+    OutsidePriceBand { price: Price, low: Price, high: Price },
+    /// A limit price that would open a level worse than every other on a side already holding
+    /// `max_levels` levels, under `LevelCapPolicy::Reject`.
+    LevelCapReached { price: Price, max_levels: usize },
+    /// An order timestamped before the `last` one the book accepted, with strict timestamps on.
+    TimestampBehind { timestamp: u64, last: u64 },
+}
+
+impl fmt::Display for LobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LobError::InvalidPrice(price) => write!(f, "price {} is not positive", price),
+            LobError::ZeroQuantity => write!(f, "order quantity must be non-zero"),
+            LobError::DuplicateOrderId(id) => write!(f, "an open order with id {} already exists", id),
+            LobError::PostOnlyWouldCross(price) => write!(f, "post-only order at {} would cross the book", price),
+            LobError::Expired(expires_at) => write!(f, "order expiring at {} has already expired", expires_at),
+            LobError::OutsidePriceBand { price, low, high } => {
+                write!(f, "price {} is outside the price band {} to {}", price, low, high)
+            }
+            LobError::LevelCapReached { price, max_levels } => {
+                write!(f, "price {} would open a level beyond the cap of {} levels", price, max_levels)
+            }
+            LobError::TimestampBehind { timestamp, last } => {
+                write!(f, "timestamp {} is before the last accepted timestamp {}", timestamp, last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LobError {}
+
+/// Reasons `Accounting::check` fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountingError {
+    /// The quantity traded, cancelled, expired and still open adds up to `accounted` rather
+    /// than the `submitted` total.
+    Unbalanced { submitted: u64, accounted: u64 },
+}
+
+impl fmt::Display for AccountingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountingError::Unbalanced { submitted, accounted } => {
+                write!(f, "{} submitted but {} accounted for", submitted, accounted)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AccountingError {}
+
+/// A broken internal invariant found by `OrderBook::validate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// A level's cached visible quantity doesn't match the orders in it.
+    LevelQuantity { side: Side, price: Price, cached: u64, actual: u64 },
+    /// A side's cached total quantity or order count doesn't match its levels.
+    SideTotals { side: Side, cached_quantity: u64, actual_quantity: u64, cached_orders: usize, actual_orders: usize },
+    /// A resting order the id index doesn't point at, or points at the wrong level.
+    Index { side: Side, price: Price, order_id: u64 },
+    /// The best bid is at or above the best ask.
+    Crossed { best_bid: Price, best_ask: Price },
+    /// A price level with no orders left in it.
+    EmptyLevel { side: Side, price: Price },
+    /// A level whose linked queue doesn't add up: a dangling or one-way link, or a different
+    /// number of orders than it caches.
+    BrokenQueue { side: Side, price: Price },
+    /// A resting order with nothing visible left to trade.
+    ZeroQuantity { side: Side, price: Price, order_id: u64 },
+    /// A resting order whose own side or price doesn't match the level holding it.
+    MisplacedOrder { side: Side, price: Price, order_id: u64 },
+    /// The same id on more than one open order (resting on either side or pending as a stop).
+    DuplicateOrderId { order_id: u64 },
+    /// The cached best price isn't the best level.
+    StaleBest { side: Side, cached: Option<Price>, actual: Option<Price> },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::LevelQuantity { side, price, cached, actual } => {
+                write!(f, "{:?} level at {} caches quantity {} but holds {}", side, price, cached, actual)
+            }
+            InvariantViolation::SideTotals { side, cached_quantity, actual_quantity, cached_orders, actual_orders } => write!(
+                f,
+                "{:?} side caches {} orders / quantity {} but holds {} orders / quantity {}",
+                side, cached_orders, cached_quantity, actual_orders, actual_quantity
+            ),
+            InvariantViolation::Index { side, price, order_id } => {
+                write!(f, "order {} at {:?} {} is missing from the id index", order_id, side, price)
+            }
+            InvariantViolation::Crossed { best_bid, best_ask } => {
+                write!(f, "book is crossed: best bid {} >= best ask {}", best_bid, best_ask)
+            }
+            InvariantViolation::EmptyLevel { side, price } => write!(f, "{:?} level at {} is empty", side, price),
+            InvariantViolation::BrokenQueue { side, price } => {
+                write!(f, "{:?} level at {} has a broken order queue", side, price)
+            }
+            InvariantViolation::ZeroQuantity { side, price, order_id } => {
+                write!(f, "order {} at {:?} {} has zero quantity", order_id, side, price)
+            }
+            InvariantViolation::MisplacedOrder { side, price, order_id } => {
+                write!(f, "order {} doesn't belong in the {:?} level at {}", order_id, side, price)
+            }
+            InvariantViolation::DuplicateOrderId { order_id } => write!(f, "order id {} is open more than once", order_id),
+            InvariantViolation::StaleBest { side, cached, actual } => {
+                write!(f, "{:?} side caches best {:?} but its best level is {:?}", side, cached, actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
@@ -0,0 +1,204 @@
+use super::*;
+use std::fmt;
+
+/// Error returned when a fixture string cannot be turned into a book.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureError {
+    /// Byte offset into the fixture string where the problem was found.
+    pub position: usize,
+    pub message: String,
+}
+
+impl FixtureError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self { position, message: message.into() }
+    }
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid fixture at byte {}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for FixtureError {}
+
+impl OrderBook {
+    /// Builds a resting book (with the default tick size) from a compact, human-readable
+    /// description.
+    ///
+    /// The format is one section per side separated by `;`, each starting with `ASK` or `BID`
+    /// and followed by `|`-separated levels written as `PRICExQTY`:
+    ///
+    /// ```text
+    /// ASK 101.0x200(2) | 100.5x50 ; BID 100.0x300 | 99.5x100(3)
+    /// ```
+    ///
+    /// A trailing `(n)` splits the level into `n` equal orders; when the quantity does not
+    /// divide evenly, the earliest orders carry one extra lot each. Repeating a price appends
+    /// further orders behind the ones already at that level. Orders get ids `1, 2, 3, ...` in
+    /// the order they appear, so time priority within a level follows the text. The resulting
+    /// book must not be crossed.
+    pub fn from_fixture(fixture: &str) -> Result<Self, FixtureError> {
+        let mut book = OrderBook::new();
+        let mut next_id = 1;
+
+        let mut section_start = 0;
+        for section in fixture.split(';') {
+            let offset = section_start + leading_whitespace(section);
+            section_start += section.len() + 1;
+
+            let section = section.trim();
+            if section.is_empty() {
+                continue;
+            }
+
+            let (side, rest) = if let Some(rest) = section.strip_prefix("ASK") {
+                (Side::Sell, rest)
+            } else if let Some(rest) = section.strip_prefix("BID") {
+                (Side::Buy, rest)
+            } else {
+                return Err(FixtureError::new(offset, "expected section to start with ASK or BID"));
+            };
+
+            let mut level_start = offset + 3;
+            for level in rest.split('|') {
+                let level_offset = level_start + leading_whitespace(level);
+                level_start += level.len() + 1;
+
+                let (price, quantity, count) = parse_level(level.trim(), level_offset)?;
+                let price = book
+                    .price(price)
+                    .map_err(|err| FixtureError::new(level_offset, err.to_string()))?;
+                let levels = match side {
+                    Side::Buy => &mut book.bids,
+                    Side::Sell => &mut book.asks,
+                };
+                for slice in split_quantity(quantity, count) {
+                    levels.insert(Order { seq: next_id, ..Order::limit(next_id, side, price, slice) });
+                    next_id += 1;
+                }
+            }
+        }
+        book.last_seq = next_id - 1;
+        book.reset_accounting();
+
+        if let (Some((bid, _)), Some((ask, _))) = (book.bids.best(), book.asks.best())
+            && bid >= ask
+        {
+            return Err(FixtureError::new(
+                0,
+                format!(
+                    "book is crossed: best bid {:?} >= best ask {:?}",
+                    bid.to_f64(book.tick_size),
+                    ask.to_f64(book.tick_size)
+                ),
+            ));
+        }
+
+        Ok(book)
+    }
+
+    /// Renders the resting book in the format accepted by [`OrderBook::from_fixture`].
+    ///
+    /// Levels are listed from the highest price down on both sides. Consecutive orders that look
+    /// like an `(n)` split are folded back into a single entry, so `from_fixture(&book.to_fixture())`
+    /// reproduces the same levels and queues (with freshly assigned ids).
+    pub fn to_fixture(&self) -> String {
+        let asks = render_side(self.asks.levels().rev(), self.tick_size);
+        let bids = render_side(self.bids.levels().rev(), self.tick_size);
+
+        match (asks.is_empty(), bids.is_empty()) {
+            (true, true) => String::new(),
+            (false, true) => format!("ASK {}", asks),
+            (true, false) => format!("BID {}", bids),
+            (false, false) => format!("ASK {} ; BID {}", asks, bids),
+        }
+    }
+}
+
+fn leading_whitespace(text: &str) -> usize {
+    text.len() - text.trim_start().len()
+}
+
+/// Parses `PRICExQTY` with an optional `(n)` suffix into `(price, total_quantity, n)`.
+fn parse_level(level: &str, offset: usize) -> Result<(f64, u64, u64), FixtureError> {
+    let Some(x) = level.find('x') else {
+        return Err(FixtureError::new(offset, format!("expected PRICExQTY, found {:?}", level)));
+    };
+
+    let price_text = &level[..x];
+    let price: f64 = price_text
+        .trim()
+        .parse()
+        .map_err(|_| FixtureError::new(offset, format!("invalid price {:?}", price_text)))?;
+    if !price.is_finite() || price <= 0.0 {
+        return Err(FixtureError::new(offset, format!("price must be positive, found {}", price)));
+    }
+
+    let rest = &level[x + 1..];
+    let rest_offset = offset + x + 1;
+    let (quantity_text, count) = match rest.find('(') {
+        Some(open) => {
+            let Some(count_text) = rest[open + 1..].strip_suffix(')') else {
+                return Err(FixtureError::new(rest_offset + open, "unterminated order count"));
+            };
+            let count: u64 = count_text.trim().parse().map_err(|_| {
+                FixtureError::new(rest_offset + open + 1, format!("invalid order count {:?}", count_text))
+            })?;
+            if count == 0 {
+                return Err(FixtureError::new(rest_offset + open + 1, "order count must be positive"));
+            }
+            (&rest[..open], count)
+        }
+        None => (rest, 1),
+    };
+
+    let quantity: u64 = quantity_text
+        .trim()
+        .parse()
+        .map_err(|_| FixtureError::new(rest_offset, format!("invalid quantity {:?}", quantity_text)))?;
+    if quantity == 0 {
+        return Err(FixtureError::new(rest_offset, "quantity must be positive"));
+    }
+    if quantity < count {
+        return Err(FixtureError::new(
+            rest_offset,
+            format!("quantity {} cannot be split into {} orders", quantity, count),
+        ));
+    }
+
+    Ok((price, quantity, count))
+}
+
+/// Splits `quantity` into `count` orders, handing the remainder out one lot at a time from the front.
+fn split_quantity(quantity: u64, count: u64) -> impl Iterator<Item = u64> {
+    (0..count).map(move |i| quantity / count + u64::from(i < quantity % count))
+}
+
+fn render_side<'a>(levels: impl Iterator<Item = (Price, PriceLevel<'a>)>, tick_size: f64) -> String {
+    let mut entries = Vec::new();
+    for (price, level) in levels {
+        let price = price.to_f64(tick_size);
+        let mut orders = level.iter().peekable();
+        while let Some(first) = orders.next() {
+            // A run is foldable while sizes never increase and stay within one lot of the first,
+            // which is exactly the shape split_quantity produces.
+            let mut count = 1;
+            let mut total = first.quantity;
+            let mut last = first.quantity;
+            while let Some(next) = orders.next_if(|next| next.quantity <= last && next.quantity + 1 >= first.quantity) {
+                count += 1;
+                total += next.quantity;
+                last = next.quantity;
+            }
+
+            if count == 1 {
+                entries.push(format!("{:?}x{}", price, total));
+            } else {
+                entries.push(format!("{:?}x{}({})", price, total, count));
+            }
+        }
+    }
+    entries.join(" | ")
+}
@@ -0,0 +1,90 @@
+use super::*;
+
+/// What a book capped by [`OrderBook::with_max_levels_per_side`] does with an order that would
+/// open a level worse than every other on a full side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LevelCapPolicy {
+    /// Refuse the order with [`LobError::LevelCapReached`].
+    #[default]
+    Reject,
+    /// Accept it and evict the worst level, which is the order's own.
+    Evict,
+}
+
+impl OrderBook {
+    /// Keeps at most `max_levels` price levels on each side of the book.
+    ///
+    /// Whenever resting an order leaves a side with one level too many, the worst level is
+    /// evicted: its orders leave the book, are reported in [`ExecutionReport::evicted`] and
+    /// passed to [`OrderBookListener::on_order_evicted`], and can no longer be cancelled. An
+    /// order better than the worst level therefore always rests. One worse than every level
+    /// of a full side is handled by the [`LevelCapPolicy`], which rejects it by default.
+    /// Orders joining a level that already exists are never affected.
+    pub fn with_max_levels_per_side(mut self, max_levels: usize) -> Self {
+        self.max_levels = Some(max_levels);
+        self
+    }
+
+    pub fn max_levels_per_side(&self) -> Option<usize> {
+        self.max_levels
+    }
+
+    /// Sets what happens to orders the level cap has no room for.
+    pub fn with_level_cap_policy(mut self, level_cap_policy: LevelCapPolicy) -> Self {
+        self.level_cap_policy = level_cap_policy;
+        self
+    }
+
+    pub fn level_cap_policy(&self) -> LevelCapPolicy {
+        self.level_cap_policy
+    }
+
+    /// The cap if resting at `price` on `side` would open a level the cap has no room for and
+    /// the policy is to reject it. `leaving` is an order a modify takes off the book first,
+    /// which frees its level if it is alone there.
+    pub(crate) fn beyond_level_cap(&self, side: Side, price: Price, leaving: Option<&Order>) -> Option<usize> {
+        let max_levels = self.max_levels.filter(|_| self.level_cap_policy == LevelCapPolicy::Reject)?;
+        let own = self.side(side);
+        if own.level(price).is_some() {
+            return None;
+        }
+
+        let alone = |order: &&Order| own.level(order.price).is_some_and(|level| level.order_count() == 1);
+        let vacated = leaving.filter(alone).map(|order| order.price);
+        if own.level_count() - usize::from(vacated.is_some()) < max_levels {
+            return None;
+        }
+        let mut levels = own.levels().map(|(price, _)| price).filter(|&level| Some(level) != vacated);
+        let worst = match side {
+            Side::Buy => levels.next().filter(|&worst| price > worst),
+            Side::Sell => levels.next_back().filter(|&worst| price < worst),
+        };
+        worst.is_none().then_some(max_levels)
+    }
+
+    /// Evicts the worst levels of `side` until it is back within the cap, reporting each
+    /// evicted order and emptied level to the listener. Returns the evicted orders.
+    pub(crate) fn enforce_level_cap(&mut self, side: Side) -> Vec<Order> {
+        let mut evicted = Vec::new();
+        let Some(max_levels) = self.max_levels else {
+            return evicted;
+        };
+        let own = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        while own.level_count() > max_levels
+            && let Some((price, orders)) = own.remove_worst_level()
+        {
+            for order in &orders {
+                self.listener.on_order_evicted(order);
+                self.accounting.expire(Held::Resting(side), order.remaining());
+            }
+            self.listener.on_level_removed(side, price);
+            evicted.extend(orders);
+        }
+        self.publish_order_data();
+        evicted
+    }
+}
@@ -0,0 +1,104 @@
+use super::*;
+use std::sync::{Arc, Mutex};
+
+/// Callbacks for everything that happens to the book, in the order it happens.
+///
+/// For each incoming order the sequence is: accepted, then its trades in execution order
+/// (interleaved with any levels they empty), then rested or cancelled. Every method has an
+/// empty default, so a listener only implements what it cares about.
+pub trait OrderBookListener: Send {
+    /// An order was submitted, before any matching.
+    fn on_order_accepted(&mut self, _order: &Order) {}
+
+    fn on_trade(&mut self, _trade: &Trade) {}
+
+    /// The unfilled remainder of an order was placed on the book.
+    fn on_order_rested(&mut self, _order: &Order) {}
+
+    /// An order left the book (or never made it there) without filling: an explicit cancel,
+    /// a dropped market/IOC remainder, a killed FOK order or a self-trade cancellation. The
+    /// order carries the quantity that was cancelled.
+    fn on_order_cancelled(&mut self, _order: &Order) {}
+
+    /// A resting order was pushed off the book to keep its side within the book's level cap,
+    /// see [`OrderBook::with_max_levels_per_side`]. The order carries its open quantity.
+    fn on_order_evicted(&mut self, _order: &Order) {}
+
+    /// The last order at `price` on `side` went away.
+    fn on_level_removed(&mut self, _side: Side, _price: Price) {}
+}
+
+/// Listener that ignores every event; what a book starts with.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopListener;
+
+impl OrderBookListener for NoopListener {}
+
+/// One callback received by a [`RecordingListener`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookEvent {
+    OrderAccepted(Order),
+    Trade(Trade),
+    OrderRested(Order),
+    OrderCancelled(Order),
+    OrderEvicted(Order),
+    LevelRemoved(Side, Price),
+}
+
+/// Listener that records every event. Clones share the same log, so keep one clone to read
+/// the events after handing the other to the book.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingListener {
+    events: Arc<Mutex<Vec<BookEvent>>>,
+}
+
+impl RecordingListener {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every event recorded so far, oldest first.
+    pub fn events(&self) -> Vec<BookEvent> {
+        self.log().clone()
+    }
+
+    /// Returns and forgets the events recorded so far.
+    pub fn take_events(&self) -> Vec<BookEvent> {
+        std::mem::take(&mut *self.log())
+    }
+
+    fn log(&self) -> std::sync::MutexGuard<'_, Vec<BookEvent>> {
+        // A panic while holding the lock can't leave a Vec half-pushed, so poisoning is harmless
+        self.events.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record(&mut self, event: BookEvent) {
+        self.log().push(event);
+    }
+}
+
+impl OrderBookListener for RecordingListener {
+    fn on_order_accepted(&mut self, order: &Order) {
+        self.record(BookEvent::OrderAccepted(order.clone()));
+    }
+
+    fn on_trade(&mut self, trade: &Trade) {
+        self.record(BookEvent::Trade(trade.clone()));
+    }
+
+    fn on_order_rested(&mut self, order: &Order) {
+        self.record(BookEvent::OrderRested(order.clone()));
+    }
+
+    fn on_order_cancelled(&mut self, order: &Order) {
+        self.record(BookEvent::OrderCancelled(order.clone()));
+    }
+
+    fn on_order_evicted(&mut self, order: &Order) {
+        self.record(BookEvent::OrderEvicted(order.clone()));
+    }
+
+    fn on_level_removed(&mut self, side: Side, price: Price) {
+        self.record(BookEvent::LevelRemoved(side, price));
+    }
+}
@@ -0,0 +1,118 @@
+use super::*;
+use std::collections::HashMap;
+
+/// One change to the aggregated (L2) book.
+///
+/// Quantities are the visible size of the level, as `depth()` reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L2Change {
+    LevelAdded { side: Side, price: Price, quantity: u64 },
+    LevelChanged { side: Side, price: Price, new_quantity: u64 },
+    LevelRemoved { side: Side, price: Price },
+}
+
+/// An [`L2Change`] tagged with its position in the feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L2Update {
+    /// Starts at 1 and increases by one with every update.
+    pub sequence: u64,
+    /// Book time when the change was made, see [`OrderBook::now`].
+    pub timestamp: u64,
+    pub change: L2Change,
+}
+
+/// One change to a single resting order (L3), in visible quantity.
+///
+/// Replaying them in order mirrors every level's queue: an added order joins the back of its
+/// level, and an execution or cancellation takes `quantity` off the order's visible size,
+/// removing it once nothing is left. An iceberg whose slice is used up leaves the queue and is
+/// added again at the back with its next slice. Orders an auction uncross takes off the book
+/// are cancelled and added back in their original order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L3Change {
+    OrderAdded { side: Side, price: Price, order_id: u64, quantity: u64 },
+    OrderExecuted { side: Side, price: Price, order_id: u64, quantity: u64 },
+    OrderCancelled { side: Side, price: Price, order_id: u64, quantity: u64 },
+}
+
+/// An [`L3Change`] tagged with its position in the feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L3Update {
+    /// Starts at 1 and increases by one with every update, so a gap means a missed update.
+    pub sequence: u64,
+    /// Book time when the change was made, see [`OrderBook::now`].
+    pub timestamp: u64,
+    pub change: L3Change,
+}
+
+/// Sequences the order-level changes the book's sides record, in the order the book hands
+/// them over.
+#[derive(Debug, Default)]
+pub(crate) struct L3Feed {
+    updates: Vec<L3Update>,
+    last_sequence: u64,
+}
+
+impl L3Feed {
+    /// Sequences the changes `side` has recorded since the last call.
+    pub(crate) fn publish(&mut self, side: &mut BookSide, timestamp: u64) {
+        for change in side.drain_order_changes() {
+            self.last_sequence += 1;
+            self.updates.push(L3Update { sequence: self.last_sequence, timestamp, change });
+        }
+    }
+
+    pub(crate) fn take_updates(&mut self) -> Vec<L3Update> {
+        std::mem::take(&mut self.updates)
+    }
+}
+
+/// Turns the levels an operation touched into L2 updates, one per level whose quantity
+/// actually changed, against the quantities it last published.
+#[derive(Debug, Default)]
+pub(crate) struct L2Feed {
+    published: HashMap<(Side, Price), u64>,
+    updates: Vec<L2Update>,
+    last_sequence: u64,
+}
+
+impl L2Feed {
+    /// A feed whose starting point is the book as it is now; nothing is emitted for existing
+    /// levels.
+    pub(crate) fn new(bids: &BookSide, asks: &BookSide) -> Self {
+        let mut feed = L2Feed::default();
+        for side in [bids, asks] {
+            for (price, level) in side.levels() {
+                feed.published.insert((side.side(), price), level.total_quantity());
+            }
+        }
+        feed
+    }
+
+    /// Emits an update for every touched level of `side` that differs from what was published.
+    pub(crate) fn publish(&mut self, side: &BookSide, timestamp: u64) {
+        for &price in side.touched() {
+            let key = (side.side(), price);
+            let change = match (self.published.get(&key).copied(), side.level_quantity(price)) {
+                (None, None) => continue,
+                (Some(old), Some(new)) if old == new => continue,
+                (None, Some(quantity)) => L2Change::LevelAdded { side: side.side(), price, quantity },
+                (Some(_), Some(new_quantity)) => L2Change::LevelChanged { side: side.side(), price, new_quantity },
+                (Some(_), None) => L2Change::LevelRemoved { side: side.side(), price },
+            };
+
+            match change {
+                L2Change::LevelRemoved { .. } => self.published.remove(&key),
+                L2Change::LevelAdded { quantity, .. } | L2Change::LevelChanged { new_quantity: quantity, .. } => {
+                    self.published.insert(key, quantity)
+                }
+            };
+            self.last_sequence += 1;
+            self.updates.push(L2Update { sequence: self.last_sequence, timestamp, change });
+        }
+    }
+
+    pub(crate) fn take_updates(&mut self) -> Vec<L2Update> {
+        std::mem::take(&mut self.updates)
+    }
+}
@@ -0,0 +1,312 @@
+use super::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Handle for a symbol added to a [`MatchingEngine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SymbolId(pub u32);
+
+impl fmt::Display for SymbolId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Outcome of [`MatchingEngine::submit`]: the id the engine gave the order and its fills.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubmitResult {
+    pub order_id: u64,
+    pub trades: Vec<Trade>,
+}
+
+/// One order book per symbol, with order ids that are unique across all of them.
+///
+/// The engine assigns every submitted order its id, and remembers which symbol each open
+/// order belongs to, so cancelling only needs the id.
+#[derive(Debug, Default)]
+pub struct MatchingEngine {
+    books: HashMap<SymbolId, OrderBook>,
+    /// Lot size of every symbol added with one other than 1.
+    lot_sizes: HashMap<SymbolId, u64>,
+    symbols: HashMap<String, SymbolId>,
+    names: Vec<String>,
+    /// Symbol of every open order.
+    order_symbols: HashMap<u64, SymbolId>,
+    /// Stops waiting for their trigger on each symbol, so the engine notices when they go.
+    pending_stops: HashMap<SymbolId, Vec<u64>>,
+    next_order_id: u64,
+}
+
+impl MatchingEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a symbol with an empty default book. Adding a name twice returns the id it already has.
+    pub fn add_symbol(&mut self, name: &str) -> SymbolId {
+        match self.symbols.get(name) {
+            Some(&symbol) => symbol,
+            None => self.insert_symbol(name, OrderBook::new(), 1),
+        }
+    }
+
+    /// Adds a symbol whose book prices in multiples of `tick_size` and which only accepts
+    /// quantities in multiples of `lot_size`. The book has strict ticks, so its
+    /// [`OrderBook::price`] rejects prices off the tick instead of rounding them. Adding a name
+    /// twice returns the id it already has and leaves its sizes as they were.
+    pub fn add_symbol_with_sizes(
+        &mut self,
+        name: &str,
+        tick_size: f64,
+        lot_size: u64,
+    ) -> Result<SymbolId, EngineError> {
+        if !tick_size.is_finite() || tick_size <= 0.0 {
+            return Err(EngineError::InvalidTickSize);
+        }
+        if lot_size == 0 {
+            return Err(EngineError::ZeroLotSize);
+        }
+        Ok(match self.symbols.get(name) {
+            Some(&symbol) => symbol,
+            None => self.insert_symbol(name, OrderBook::with_tick_size(tick_size).with_strict_ticks(), lot_size),
+        })
+    }
+
+    fn insert_symbol(&mut self, name: &str, book: OrderBook, lot_size: u64) -> SymbolId {
+        let symbol = SymbolId(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.symbols.insert(name.to_string(), symbol);
+        self.books.insert(symbol, book);
+        if lot_size > 1 {
+            self.lot_sizes.insert(symbol, lot_size);
+        }
+        symbol
+    }
+
+    /// Quantity every order on `symbol` must be a multiple of.
+    pub fn lot_size(&self, symbol: SymbolId) -> Result<u64, EngineError> {
+        self.book(symbol)?;
+        Ok(self.lot_sizes.get(&symbol).copied().unwrap_or(1))
+    }
+
+    /// Looks up a symbol by name.
+    pub fn symbol(&self, name: &str) -> Option<SymbolId> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn symbol_name(&self, symbol: SymbolId) -> Option<&str> {
+        self.names.get(symbol.0 as usize).map(String::as_str)
+    }
+
+    pub fn book(&self, symbol: SymbolId) -> Result<&OrderBook, EngineError> {
+        self.books.get(&symbol).ok_or(EngineError::UnknownSymbol(symbol))
+    }
+
+    /// Top `levels` price levels of a symbol's book.
+    pub fn depth(&self, symbol: SymbolId, levels: usize) -> Result<DepthSnapshot, EngineError> {
+        Ok(self.book(symbol)?.depth(levels))
+    }
+
+    /// Trades, volume and price improvement summed over every symbol's book.
+    pub fn stats(&self) -> BookStats {
+        self.books.values().map(OrderBook::stats).fold(BookStats::default(), |total, stats| BookStats {
+            trades: total.trades + stats.trades,
+            volume: total.volume + stats.volume,
+            price_improvement: total.price_improvement + stats.price_improvement,
+        })
+    }
+
+    /// Submits an order to a symbol's book. Whatever id `order` carries is replaced by a fresh
+    /// engine-wide one, which is returned along with the fills. Its quantity, and an iceberg's
+    /// display quantity, must be multiples of the symbol's lot size.
+    pub fn submit(&mut self, symbol: SymbolId, mut order: Order) -> Result<SubmitResult, EngineError> {
+        let lot_size = self.lot_size(symbol)?;
+        if let Some(quantity) = [Some(order.quantity), order.display_quantity]
+            .into_iter()
+            .flatten()
+            .find(|quantity| !quantity.is_multiple_of(lot_size))
+        {
+            return Err(EngineError::OffLot { quantity, lot_size });
+        }
+        let book = self.books.get_mut(&symbol).ok_or(EngineError::UnknownSymbol(symbol))?;
+
+        self.next_order_id += 1;
+        let id = self.next_order_id;
+        order.id = id;
+        let report = book.add_order_with_report(order).map_err(EngineError::Rejected)?;
+
+        // Forget every order this submission closed: filled makers, makers cancelled by
+        // self-trade prevention or evicted by the level cap, stops it triggered, and the order
+        // itself if nothing of it stayed open
+        self.order_symbols.insert(id, symbol);
+        let stops = self.pending_stops.entry(symbol).or_default();
+        if book.is_stop_pending(id) {
+            stops.push(id);
+        }
+        let traded = report.trades.iter().flat_map(|trade| [trade.maker_id, trade.taker_id]);
+        let cancelled = report.self_trade_cancels.iter().map(|cancel| cancel.order_id);
+        let evicted = report.evicted.iter().map(|order| order.id);
+        let mut closed: Vec<u64> = traded.chain(cancelled).chain(evicted).chain([id]).collect();
+        // Only a trade can trigger a stop, and one that finds nothing to trade leaves no trace
+        if !report.trades.is_empty() {
+            let (pending, triggered): (Vec<u64>, _) = stops.iter().partition(|&&stop| book.is_stop_pending(stop));
+            *stops = pending;
+            closed.extend(triggered);
+        }
+        for id in closed {
+            if !is_open(book, id) {
+                self.order_symbols.remove(&id);
+            }
+        }
+
+        Ok(SubmitResult { order_id: id, trades: report.trades })
+    }
+
+    /// Cancels an open order on whichever symbol it belongs to.
+    pub fn cancel(&mut self, order_id: u64) -> Result<Order, EngineError> {
+        let symbol = self.order_symbols.remove(&order_id).ok_or(EngineError::UnknownOrder(order_id))?;
+        let book = self.books.get_mut(&symbol).ok_or(EngineError::UnknownSymbol(symbol))?;
+        let order = book.cancel_order(order_id).map_err(|_| EngineError::UnknownOrder(order_id))?;
+        if order.trigger().is_some()
+            && let Some(stops) = self.pending_stops.get_mut(&symbol)
+        {
+            stops.retain(|&stop| stop != order_id);
+        }
+        Ok(order)
+    }
+
+    /// Symbol an open order belongs to.
+    pub fn order_symbol(&self, order_id: u64) -> Option<SymbolId> {
+        self.order_symbols.get(&order_id).copied()
+    }
+}
+
+fn is_open(book: &OrderBook, id: u64) -> bool {
+    book.bids().contains(id) || book.asks().contains(id) || book.is_stop_pending(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn px(price: f64) -> Price {
+        Price::from_f64(price, DEFAULT_TICK_SIZE)
+    }
+
+    #[test]
+    fn test_books_do_not_bleed_into_each_other() {
+        let mut engine = MatchingEngine::new();
+        let aapl = engine.add_symbol("AAPL");
+        let msft = engine.add_symbol("MSFT");
+        let goog = engine.add_symbol("GOOG");
+        assert_eq!(engine.add_symbol("MSFT"), msft);
+
+        // Interleave identical-looking flow across the three symbols
+        let mut resting = Vec::new();
+        for symbol in [aapl, msft, goog] {
+            resting.push(engine.submit(symbol, Order::limit(0, Side::Sell, px(100.0), 10)).unwrap().order_id);
+        }
+        for symbol in [aapl, msft, goog] {
+            engine.submit(symbol, Order::limit(0, Side::Buy, px(99.0), 5)).unwrap();
+        }
+
+        let fill = engine.submit(msft, Order::limit(0, Side::Buy, px(100.0), 4)).unwrap();
+        assert_eq!(fill.trades.len(), 1);
+        assert_eq!(fill.trades[0].maker_id, resting[1]);
+        assert_eq!(engine.book(msft).unwrap().best_ask(), Some((px(100.0), 6)));
+        assert_eq!(engine.book(aapl).unwrap().best_ask(), Some((px(100.0), 10)));
+        assert_eq!(engine.book(goog).unwrap().best_ask(), Some((px(100.0), 10)));
+
+        // Ids are unique across symbols, and cancelling finds the right book by id alone
+        let cancelled = engine.cancel(resting[2]).unwrap();
+        assert_eq!((cancelled.id, cancelled.quantity), (resting[2], 10));
+        assert!(engine.book(goog).unwrap().best_ask().is_none());
+        assert_eq!(engine.depth(aapl, 5).unwrap().asks, vec![(px(100.0), 10, 1)]);
+        assert_eq!(engine.depth(aapl, 5).unwrap().bids, vec![(px(99.0), 5, 1)]);
+    }
+
+    #[test]
+    fn test_unknown_symbol_and_order() {
+        let mut engine = MatchingEngine::new();
+        let aapl = engine.add_symbol("AAPL");
+        let missing = SymbolId(7);
+
+        assert_eq!(
+            engine.submit(missing, Order::limit(0, Side::Buy, px(1.0), 1)).unwrap_err(),
+            EngineError::UnknownSymbol(missing)
+        );
+        assert_eq!(engine.depth(missing, 1).unwrap_err(), EngineError::UnknownSymbol(missing));
+        assert_eq!(engine.cancel(42).unwrap_err(), EngineError::UnknownOrder(42));
+        assert_eq!(
+            engine.submit(aapl, Order::limit(0, Side::Buy, px(1.0), 0)).unwrap_err(),
+            EngineError::Rejected(LobError::ZeroQuantity)
+        );
+
+        // A fully filled order is no longer cancellable
+        let maker = engine.submit(aapl, Order::limit(0, Side::Sell, px(10.0), 5)).unwrap().order_id;
+        let taker = engine.submit(aapl, Order::limit(0, Side::Buy, px(10.0), 5)).unwrap().order_id;
+        assert_eq!(engine.order_symbol(maker), None);
+        assert_eq!(engine.cancel(taker).unwrap_err(), EngineError::UnknownOrder(taker));
+        assert_eq!(engine.symbol("AAPL"), Some(aapl));
+        assert_eq!(engine.symbol_name(aapl), Some("AAPL"));
+    }
+
+    #[test]
+    fn test_every_closed_order_is_forgotten() {
+        let mut engine = MatchingEngine::new();
+        let stp = engine.add_symbol("STP");
+        let capped = engine.add_symbol("CAPPED");
+        let stops = engine.add_symbol("STOPS");
+        engine.books.insert(stp, OrderBook::new().with_stp_policy(StpPolicy::CancelOldest));
+        let evicting = OrderBook::new().with_max_levels_per_side(1).with_level_cap_policy(LevelCapPolicy::Evict);
+        engine.books.insert(capped, evicting);
+        let mut submit = |symbol, order| engine.submit(symbol, order).unwrap().order_id;
+
+        // A maker cancelled by self-trade prevention
+        let own_quote = submit(stp, Order::limit(0, Side::Sell, px(100.0), 10).with_participant_id(1));
+        let rested = submit(stp, Order::limit(0, Side::Buy, px(100.0), 5).with_participant_id(1));
+
+        // A maker evicted by the level cap
+        let evicted = submit(capped, Order::limit(0, Side::Sell, px(101.0), 10));
+        let better = submit(capped, Order::limit(0, Side::Sell, px(100.0), 10));
+
+        // A stop triggered with nothing left to trade against
+        let stop = submit(stops, Order::stop(0, Side::Buy, px(100.0), 5));
+        submit(stops, Order::limit(0, Side::Sell, px(100.0), 1));
+        submit(stops, Order::limit(0, Side::Buy, px(100.0), 1));
+
+        assert_eq!([own_quote, evicted, stop].map(|id| engine.order_symbol(id)), [None; 3]);
+        assert_eq!((engine.order_symbol(rested), engine.order_symbol(better)), (Some(stp), Some(capped)));
+        assert_eq!(engine.order_symbols.len(), 2);
+    }
+
+    #[test]
+    fn test_symbol_sizes_and_aggregate_stats() {
+        let mut engine = MatchingEngine::new();
+        let bond = engine.add_symbol_with_sizes("BOND", 0.25, 100).unwrap();
+        let aapl = engine.add_symbol("AAPL");
+        assert_eq!(engine.add_symbol_with_sizes("BOND", 0.5, 10), Ok(bond));
+        for tick_size in [0.0, -0.25, f64::NAN, f64::INFINITY] {
+            assert_eq!(engine.add_symbol_with_sizes("BAD", tick_size, 1), Err(EngineError::InvalidTickSize));
+        }
+        assert_eq!(engine.add_symbol_with_sizes("BAD", 0.25, 0), Err(EngineError::ZeroLotSize));
+        assert_eq!(engine.symbol("BAD"), None);
+        assert_eq!((engine.book(bond).unwrap().tick_size(), engine.lot_size(bond)), (0.25, Ok(100)));
+        assert_eq!(engine.lot_size(SymbolId(7)), Err(EngineError::UnknownSymbol(SymbolId(7))));
+
+        let price = engine.book(bond).unwrap().price(99.75).unwrap();
+        assert_eq!(price, Price(399));
+        assert_eq!(engine.book(bond).unwrap().price(99.8), Err(PriceError::OffTick(99.8)));
+        let off_lot = engine.submit(bond, Order::limit(0, Side::Sell, price, 150));
+        assert_eq!(off_lot.unwrap_err(), EngineError::OffLot { quantity: 150, lot_size: 100 });
+        let off_lot_slice = engine.submit(bond, Order::limit(0, Side::Sell, price, 200).with_display_quantity(50));
+        assert_eq!(off_lot_slice.unwrap_err(), EngineError::OffLot { quantity: 50, lot_size: 100 });
+        engine.submit(bond, Order::limit(0, Side::Sell, price, 200)).unwrap();
+        engine.submit(bond, Order::limit(0, Side::Buy, price, 100)).unwrap();
+        engine.submit(aapl, Order::limit(0, Side::Sell, px(10.0), 5)).unwrap();
+        engine.submit(aapl, Order::limit(0, Side::Buy, px(10.0), 3)).unwrap();
+
+        let stats = engine.stats();
+        assert_eq!((stats.trades, stats.volume), (2, 103));
+    }
+}
@@ -0,0 +1,71 @@
+use super::*;
+
+/// Microstructure metrics over the top levels of the book, from [`OrderBook::metrics`].
+///
+/// Every volume only counts the quantity on display; hidden iceberg reserves are left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BookMetrics {
+    /// Visible quantity over the top levels of the bid side.
+    pub bid_volume: u64,
+    /// Visible quantity over the top levels of the ask side.
+    pub ask_volume: u64,
+    /// `(bid_volume - ask_volume) / (bid_volume + ask_volume)`, from -1 (all asks) to 1 (all
+    /// bids). `None` when both volumes are zero.
+    pub imbalance: Option<f64>,
+    /// Best ask weighted by the bid volume plus best bid weighted by the ask volume, over the
+    /// two volumes, as a float price. With one level this is the classic top-of-book
+    /// microprice. `None` unless both sides have liquidity.
+    pub microprice: Option<f64>,
+    /// Sum of price (in ticks) times visible quantity over the top levels of the bid side.
+    pub bid_notional: i128,
+    /// Sum of price (in ticks) times visible quantity over the top levels of the ask side.
+    pub ask_notional: i128,
+}
+
+/// How far from the mid [`OrderBook::depth_within`] looks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepthDistance {
+    Ticks(i64),
+    /// A percentage of the mid price, e.g. `0.5` for levels within 0.5% of it.
+    Percent(f64),
+}
+
+impl OrderBook {
+    /// Imbalance, microprice and resting notional over the top `levels` levels of each side.
+    ///
+    /// Reads the cached per-level totals, so it costs O(`levels`) whatever the queues hold.
+    pub fn metrics(&self, levels: usize) -> BookMetrics {
+        let top = |side: &BookSide| {
+            side.iter_best_first().take(levels).fold((0, 0), |(volume, notional), (price, level)| {
+                (volume + level.total_quantity(), notional + price.0 as i128 * level.total_quantity() as i128)
+            })
+        };
+        let (bid_volume, bid_notional) = top(&self.bids);
+        let (ask_volume, ask_notional) = top(&self.asks);
+
+        let total = (bid_volume + ask_volume) as f64;
+        let imbalance = (total > 0.0).then(|| (bid_volume as f64 - ask_volume as f64) / total);
+        let microprice = self.best_bid().zip(self.best_ask()).filter(|_| total > 0.0).map(|((bid, _), (ask, _))| {
+            let (bid, ask) = (bid.to_f64(self.tick_size), ask.to_f64(self.tick_size));
+            (ask * bid_volume as f64 + bid * ask_volume as f64) / total
+        });
+
+        BookMetrics { bid_volume, ask_volume, imbalance, microprice, bid_notional, ask_notional }
+    }
+
+    /// Visible quantity on `side` at levels no further than `distance` from the mid, boundary
+    /// included. `None` unless both sides have liquidity, since there is no mid to measure
+    /// from.
+    pub fn depth_within(&self, side: Side, distance: DepthDistance) -> Option<u64> {
+        let (bid, ask) = (self.best_bid()?.0, self.best_ask()?.0);
+        let mid = (bid.0 + ask.0) as f64 / 2.0;
+        let distance = match distance {
+            DepthDistance::Ticks(ticks) => ticks as f64,
+            DepthDistance::Percent(pct) => mid * pct / 100.0,
+        };
+
+        let within = |price: Price| (price.0 as f64 - mid).abs() <= distance;
+        let levels = self.side(side).iter_best_first().take_while(|&(price, _)| within(price));
+        Some(levels.map(|(_, level)| level.total_quantity()).sum())
+    }
+}
@@ -1,127 +1,1279 @@
-use std::collections::{BTreeMap, VecDeque};
-use ordered_float::OrderedFloat;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
+use std::fmt;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+mod accounting;
+mod auction;
+mod book_side;
+mod clock;
+mod collar;
+mod error;
+mod fixture;
+mod level_cap;
+mod listener;
+mod market_data;
+mod matching_engine;
+mod metrics;
+mod price;
+mod price_level;
+mod render;
+mod slab;
+mod snapshot;
+mod status;
+mod stops;
+mod tape;
+pub use accounting::Accounting;
+use accounting::Held;
+pub use auction::AuctionResult;
+pub use book_side::{BestFirst, BookSide, Levels};
+use collar::Collar;
+pub use collar::{PriceBand, ReferencePrice};
+pub use error::{AccountingError, CancelError, EngineError, InvariantViolation, LobError, ModifyError, PriceError};
+pub use fixture::FixtureError;
+pub use level_cap::LevelCapPolicy;
+pub use listener::{BookEvent, NoopListener, OrderBookListener, RecordingListener};
+pub use market_data::{L2Change, L2Update, L3Change, L3Update};
+use market_data::{L2Feed, L3Feed};
+pub use matching_engine::{MatchingEngine, SubmitResult, SymbolId};
+pub use metrics::{BookMetrics, DepthDistance};
+pub use price::{Price, DEFAULT_TICK_SIZE};
+pub use price_level::{LevelIter, PriceLevel};
+pub use render::RenderOptions;
+pub use snapshot::BookSnapshot;
+use status::RecentFills;
+pub use status::{OrderState, OrderStatus};
+use stops::StopBook;
+pub use tape::{Candle, Tape};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Side {
     Buy,
     Sell,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OrderType {
+    /// Trades at its price or better, then rests.
+    Limit,
+    /// Trades against whatever liquidity exists and never rests; `price` is ignored.
+    Market,
+    /// Held off the book until the last trade price reaches `trigger`, then becomes a market order.
+    Stop { trigger: Price },
+    /// Held off the book until the last trade price reaches `trigger`, then becomes a limit
+    /// order at `limit`.
+    StopLimit { trigger: Price, limit: Price },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimeInForce {
+    /// Good-till-cancelled: any unfilled remainder rests on the book. Give it an expiry with
+    /// [`Order::with_expiry`] to make it good-till-date.
+    Gtc,
+    /// Immediate-or-cancel: trade what is available now, cancel the remainder.
+    Ioc,
+    /// Fill-or-kill: trade the full quantity immediately or do nothing at all.
+    Fok,
+}
+
+/// What to do when an incoming order would trade against a resting order from the same
+/// participant.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StpPolicy {
+    /// Self-trades execute like any other trade.
+    #[default]
+    None,
+    /// Cancel the rest of the incoming order; the resting order stays.
+    CancelNewest,
+    /// Cancel the resting order and keep matching the incoming one against the orders behind it.
+    CancelOldest,
+    /// Cancel both the resting order and the rest of the incoming order.
+    CancelBoth,
+}
+
+/// How an incoming order's quantity is divided among the resting orders at a price level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatchPolicy {
+    /// Strict time priority: the front of the queue fills first.
+    #[default]
+    Fifo,
+    /// An order too small to clear the level is split across every order there in proportion
+    /// to its visible size, rounded down. Shares below `min_allocation` are dropped, and the
+    /// lots left by rounding and dropped shares go to the front of the queue in time priority.
+    ProRata { min_allocation: u64 },
+}
+
+/// What to do with a post-only order whose price would trade against the opposite side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PostOnlyPolicy {
+    /// Refuse the order with `LobError::PostOnlyWouldCross`.
+    #[default]
+    Reject,
+    /// Move the order's price to one tick inside the opposite best so it rests passively.
+    Reprice,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Order {
-    pub _id: u64,
-    pub price: f64,
+    pub id: u64,
+    pub price: Price,
     pub quantity: u64,
+    /// Total quantity the order was entered with, set by the book when it accepts the order.
+    /// A modify resets it to what has filled so far plus the new open quantity, so the filled
+    /// quantity is always this minus [`Order::remaining`].
+    pub original_quantity: u64,
+    /// How much of the filled quantity the order took as the incoming order, set by the book.
+    /// The rest was filled while it rested, as a maker.
+    pub taker_quantity: u64,
     pub side: Side,
+    /// Caller-supplied logical time of the order, stamped onto the trades it takes. Zero means
+    /// none was given; a book with a clock (see [`OrderBook::set_clock`]) fills it in.
+    pub timestamp: u64,
+    /// Set by the book when it accepts the order, one more than the last order it accepted.
+    /// A modify that costs the order its place in the queue gives it a new one, so orders
+    /// join a level in sequence order. Zero until accepted.
+    pub seq: u64,
+    /// Book time ([`OrderBook::now`]) when the order was given its `seq`, set by the book along
+    /// with it. Unlike `timestamp` it never comes from the caller.
+    pub accepted_at: u64,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    /// Largest slice an iceberg order shows on the book; `None` shows the whole quantity.
+    pub display_quantity: Option<u64>,
+    /// Iceberg reserve not yet shown on the book. While resting, `quantity` is only the
+    /// visible slice.
+    pub hidden_quantity: u64,
+    /// Owner of the order, used for self-trade prevention.
+    pub participant_id: Option<u64>,
+    /// Only ever add liquidity: a limit or stop-limit that would trade on arrival is handled by
+    /// the book's [`PostOnlyPolicy`] instead. Ignored on market and stop orders.
+    pub post_only: bool,
+    /// Book time at which the order stops being live, see [`OrderBook::advance_time`].
+    pub expires_at: Option<u64>,
+    /// Most price levels the order may trade through on arrival. Reaching one more cancels
+    /// the remainder instead of resting it.
+    pub max_sweep_levels: Option<usize>,
+    /// Worst price the order may trade at on arrival. Reaching a level beyond it cancels the
+    /// remainder instead of resting it, so a market order can carry one too.
+    pub protection_price: Option<Price>,
+}
+
+impl Order {
+    /// A good-till-cancelled limit order.
+    pub fn limit(id: u64, side: Side, price: Price, quantity: u64) -> Self {
+        Self {
+            id,
+            price,
+            quantity,
+            original_quantity: quantity,
+            taker_quantity: 0,
+            side,
+            timestamp: 0,
+            seq: 0,
+            accepted_at: 0,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            display_quantity: None,
+            hidden_quantity: 0,
+            participant_id: None,
+            post_only: false,
+            expires_at: None,
+            max_sweep_levels: None,
+            protection_price: None,
+        }
+    }
+
+    /// A market order; it executes immediately and anything left unfilled is cancelled.
+    pub fn market(id: u64, side: Side, quantity: u64) -> Self {
+        Self {
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Ioc,
+            ..Self::limit(id, side, Price(0), quantity)
+        }
+    }
+
+    /// A stop order: becomes a market order once the last trade price reaches `trigger`.
+    pub fn stop(id: u64, side: Side, trigger: Price, quantity: u64) -> Self {
+        Self {
+            order_type: OrderType::Stop { trigger },
+            ..Self::market(id, side, quantity)
+        }
+    }
+
+    /// A stop-limit order: becomes a limit order at `limit` once the last trade price reaches
+    /// `trigger`.
+    pub fn stop_limit(id: u64, side: Side, trigger: Price, limit: Price, quantity: u64) -> Self {
+        Self {
+            order_type: OrderType::StopLimit { trigger, limit },
+            ..Self::limit(id, side, limit, quantity)
+        }
+    }
+
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    /// Makes this an iceberg order that shows at most `display_quantity` on the book at a time.
+    /// A display quantity of zero shows the whole order.
+    pub fn with_display_quantity(mut self, display_quantity: u64) -> Self {
+        self.display_quantity = (display_quantity > 0).then_some(display_quantity);
+        self
+    }
+
+    pub fn with_participant_id(mut self, participant_id: u64) -> Self {
+        self.participant_id = Some(participant_id);
+        self
+    }
+
+    /// Makes the order expire once the book's time reaches `expires_at`.
+    pub fn with_expiry(mut self, expires_at: u64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Caps the levels the order may sweep, see [`Order::max_sweep_levels`].
+    pub fn with_max_sweep_levels(mut self, max_sweep_levels: usize) -> Self {
+        self.max_sweep_levels = Some(max_sweep_levels);
+        self
+    }
+
+    /// Caps the price the order may trade at, see [`Order::protection_price`].
+    pub fn with_protection_price(mut self, protection_price: Price) -> Self {
+        self.protection_price = Some(protection_price);
+        self
+    }
+
+    /// Makes this a post-only order, see [`Order::post_only`].
+    pub fn with_post_only(mut self) -> Self {
+        self.post_only = true;
+        self
+    }
+
+    /// Total open quantity: the visible slice plus any hidden reserve.
+    pub fn remaining(&self) -> u64 {
+        self.quantity + self.hidden_quantity
+    }
+
+    /// Trigger price of a stop or stop-limit order.
+    pub fn trigger(&self) -> Option<Price> {
+        match self.order_type {
+            OrderType::Stop { trigger } | OrderType::StopLimit { trigger, .. } => Some(trigger),
+            OrderType::Limit | OrderType::Market => None,
+        }
+    }
+
+    /// Turns a triggered stop into the market or limit order it stands for.
+    fn activate(&mut self) {
+        match self.order_type {
+            OrderType::Stop { .. } => {
+                self.order_type = OrderType::Market;
+                self.time_in_force = TimeInForce::Ioc;
+            }
+            OrderType::StopLimit { limit, .. } => {
+                self.order_type = OrderType::Limit;
+                self.price = limit;
+            }
+            OrderType::Limit | OrderType::Market => {}
+        }
+    }
+
+    /// Splits an iceberg's open quantity into its visible slice and hidden reserve.
+    fn split_display(&mut self) {
+        if let Some(display_quantity) = self.display_quantity {
+            let remaining = self.remaining();
+            self.quantity = remaining.min(display_quantity);
+            self.hidden_quantity = remaining - self.quantity;
+        }
+    }
+
+    /// Moves the next slice out of the hidden reserve once the visible one is used up.
+    /// Returns `false` if there is no reserve left.
+    fn replenish(&mut self) -> bool {
+        if self.hidden_quantity == 0 {
+            return false;
+        }
+        self.split_display();
+        true
+    }
+}
+
+/// Outcome of a market order: its fills and the quantity left unfilled when liquidity ran out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketOrderResult {
+    pub trades: Vec<Trade>,
+    /// Quantity that found no liquidity and was cancelled.
+    pub unfilled: u64,
+}
+
+/// What an order would get from the book right now, from [`OrderBook::simulate_fill`] or
+/// [`OrderBook::simulate_limit`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FillSimulation {
+    pub filled_quantity: u64,
+    /// Quantity left over once the reachable visible liquidity runs out.
+    pub unfilled_quantity: u64,
+    /// Sum of price (in ticks) times quantity over the fills.
+    pub notional: i128,
+    /// The last and worst level the order reaches, or `None` if nothing fills.
+    pub worst_price: Option<Price>,
+    /// Number of price levels the order trades at.
+    pub levels: usize,
+}
+
+impl FillSimulation {
+    /// Volume-weighted average fill price in ticks, or `None` if nothing fills.
+    pub fn average_price(&self) -> Option<f64> {
+        (self.filled_quantity > 0).then(|| self.notional as f64 / self.filled_quantity as f64)
+    }
+}
+
+/// Everything that happened to an incoming order: its fills and any self-trade cancellations.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecutionReport {
+    pub trades: Vec<Trade>,
+    pub self_trade_cancels: Vec<SelfTradeCancel>,
+    /// How much better than its limit the taker traded, summed over `trades`, in ticks times
+    /// quantity: limit minus execution price for a buy, execution minus limit for a sell.
+    /// Market and stop orders have no limit and add nothing.
+    pub price_improvement: i64,
+    /// Resting orders evicted to keep a side within the book's level cap, each with its open
+    /// quantity, see [`OrderBook::with_max_levels_per_side`].
+    pub evicted: Vec<Order>,
+    /// Whether the order was left resting on the book.
+    pub resting: bool,
+    /// Open quantity the order still has in the book, resting, pending as a stop or collected
+    /// by an auction; 0 once it has filled or been cancelled.
+    pub remaining_quantity: u64,
+    /// Orders that expired when the book read its clock before taking this one, see
+    /// [`OrderBook::set_clock`].
+    pub expired: Vec<ExpiredOrder>,
+}
+
+/// Running totals over everything a book has executed, from [`OrderBook::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BookStats {
+    pub trades: u64,
+    pub volume: u64,
+    /// Sum of every [`ExecutionReport::price_improvement`], in ticks times quantity.
+    pub price_improvement: i64,
+}
+
+/// An order (or the rest of one) cancelled by self-trade prevention instead of trading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTradeCancel {
+    pub order_id: u64,
+    pub participant_id: u64,
+    /// Open quantity that was cancelled.
+    pub quantity: u64,
+    /// Whether the cancelled order was the resting one rather than the incoming one.
+    pub resting: bool,
+}
+
+/// An order removed by [`OrderBook::advance_time`], or by the book's clock, because its expiry
+/// passed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpiredOrder {
+    pub order_id: u64,
+    pub side: Side,
+    pub price: Price,
+    /// Open quantity left when it expired, including any hidden iceberg reserve.
+    pub quantity: u64,
+    pub expires_at: u64,
+}
+
+/// Which side of a trade an order was on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Liquidity {
+    /// Resting on the book, adding liquidity.
+    Maker,
+    /// Incoming, removing liquidity.
+    Taker,
+}
+
+/// A single execution between an incoming (taker) order and a resting (maker) order.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trade {
+    pub maker_id: u64,
+    pub taker_id: u64,
+    /// Execution price: always the resting order's price, never the taker's limit.
+    pub price: Price,
+    pub quantity: u64,
+    /// Timestamp of the taker order that triggered the execution.
+    pub timestamp: u64,
+    /// [`Order::seq`] of the maker and the taker.
+    pub maker_seq: u64,
+    pub taker_seq: u64,
+}
+
+impl Trade {
+    /// Whether `order_id` made or took this trade, or `None` if it wasn't part of it. In an
+    /// auction uncross the buy is reported as the taker.
+    pub fn liquidity(&self, order_id: u64) -> Option<Liquidity> {
+        if order_id == self.maker_id {
+            Some(Liquidity::Maker)
+        } else if order_id == self.taker_id {
+            Some(Liquidity::Taker)
+        } else {
+            None
+        }
+    }
+}
+
+/// Aggregated view of the top levels of the book, best price first on each side.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DepthSnapshot {
+    /// `(price, total_quantity, order_count)` per bid level, highest price first.
+    pub bids: Vec<(Price, u64, usize)>,
+    /// `(price, total_quantity, order_count)` per ask level, lowest price first.
+    pub asks: Vec<(Price, u64, usize)>,
 }
 
 pub struct OrderBook {
-    pub bids: BTreeMap<OrderedFloat<f64>, VecDeque<Order>>,
-    pub asks: BTreeMap<OrderedFloat<f64>, VecDeque<Order>>,
+    bids: BookSide,
+    asks: BookSide,
+    stops: StopBook,
+    /// `(expires_at, order_id)` of every accepted order with an expiry, soonest first. Entries
+    /// for orders that have since filled or been cancelled are skipped when they come up.
+    expiries: BinaryHeap<Reverse<(u64, u64)>>,
+    /// Book time, as last set by `advance_time`.
+    now: u64,
+    last_trade_price: Option<Price>,
+    tick_size: f64,
+    strict_ticks: bool,
+    stp_policy: StpPolicy,
+    match_policy: MatchPolicy,
+    post_only_policy: PostOnlyPolicy,
+    price_band: Option<PriceBand>,
+    /// Most price levels each side may hold, if capped.
+    max_levels: Option<usize>,
+    level_cap_policy: LevelCapPolicy,
+    listener: Box<dyn OrderBookListener>,
+    /// L2 update feed, only kept once `enable_market_data` is called.
+    market_data: Option<L2Feed>,
+    /// L3 update feed, only kept once `enable_order_data` is called.
+    order_data: Option<L3Feed>,
+    /// Orders collected by a running auction, in arrival order; `None` in continuous trading.
+    auction: Option<Vec<Order>>,
+    /// Trade tape, only kept once `enable_tape` is called.
+    tape: Option<Tape>,
+    stats: BookStats,
+    accounting: Accounting,
+    recent_fills: RecentFills,
+    /// Run `validate` after every operation even in release builds.
+    strict_validation: bool,
+    /// `Order::seq` of the last order accepted.
+    last_seq: u64,
+    /// Latest order timestamp accepted.
+    last_timestamp: u64,
+    /// Reject orders timestamped before `last_timestamp`.
+    strict_timestamps: bool,
+    /// Virtual time source, see `set_clock`.
+    clock: Option<Box<dyn Fn() -> u64 + Send>>,
+}
+
+impl fmt::Debug for OrderBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OrderBook")
+            .field("bids", &self.bids)
+            .field("asks", &self.asks)
+            .field("stops", &self.stops)
+            .field("now", &self.now)
+            .field("last_trade_price", &self.last_trade_price)
+            .field("tick_size", &self.tick_size)
+            .field("strict_ticks", &self.strict_ticks)
+            .field("stp_policy", &self.stp_policy)
+            .field("match_policy", &self.match_policy)
+            .field("post_only_policy", &self.post_only_policy)
+            .field("price_band", &self.price_band)
+            .field("max_levels", &self.max_levels)
+            .field("level_cap_policy", &self.level_cap_policy)
+            .field("market_data", &self.market_data)
+            .field("order_data", &self.order_data)
+            .field("auction", &self.auction)
+            .field("tape", &self.tape)
+            .field("stats", &self.stats)
+            .field("accounting", &self.accounting)
+            .field("recent_fills", &self.recent_fills)
+            .field("strict_validation", &self.strict_validation)
+            .field("last_seq", &self.last_seq)
+            .field("last_timestamp", &self.last_timestamp)
+            .field("strict_timestamps", &self.strict_timestamps)
+            .field("clock", &self.clock.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl OrderBook {
     pub fn new() -> Self {
+        Self::with_tick_size(DEFAULT_TICK_SIZE)
+    }
+
+    /// An empty book whose prices are multiples of `tick_size`.
+    pub fn with_tick_size(tick_size: f64) -> Self {
         Self {
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
+            bids: BookSide::new(Side::Buy),
+            asks: BookSide::new(Side::Sell),
+            stops: StopBook::default(),
+            expiries: BinaryHeap::new(),
+            now: 0,
+            last_trade_price: None,
+            tick_size,
+            strict_ticks: false,
+            stp_policy: StpPolicy::None,
+            match_policy: MatchPolicy::Fifo,
+            post_only_policy: PostOnlyPolicy::Reject,
+            price_band: None,
+            max_levels: None,
+            level_cap_policy: LevelCapPolicy::Reject,
+            listener: Box::new(NoopListener),
+            market_data: None,
+            order_data: None,
+            auction: None,
+            tape: None,
+            stats: BookStats::default(),
+            accounting: Accounting::default(),
+            recent_fills: RecentFills::default(),
+            strict_validation: false,
+            last_seq: 0,
+            last_timestamp: 0,
+            strict_timestamps: false,
+            clock: None,
         }
     }
 
-    pub fn add_order(&mut self, mut order: Order) {
-        match order.side {
-            Side::Buy => self.match_bid(&mut order),
-            Side::Sell => self.match_ask(&mut order),
+    /// Makes `price()` reject prices that aren't on a tick instead of rounding them.
+    pub fn with_strict_ticks(mut self) -> Self {
+        self.strict_ticks = true;
+        self
+    }
+
+    /// Runs [`OrderBook::validate`] after every add, cancel and modify and panics on the first
+    /// violation. Debug builds always do this; this turns it on in release builds too.
+    pub fn with_strict_validation(mut self) -> Self {
+        self.strict_validation = true;
+        self
+    }
+
+    /// Sets how orders from the same participant are kept from trading with each other.
+    pub fn with_stp_policy(mut self, stp_policy: StpPolicy) -> Self {
+        self.stp_policy = stp_policy;
+        self
+    }
+
+    pub fn stp_policy(&self) -> StpPolicy {
+        self.stp_policy
+    }
+
+    /// Sets how fills are allocated among the orders resting at a price.
+    pub fn with_match_policy(mut self, match_policy: MatchPolicy) -> Self {
+        self.match_policy = match_policy;
+        self
+    }
+
+    pub fn match_policy(&self) -> MatchPolicy {
+        self.match_policy
+    }
+
+    /// Sets what happens to post-only orders that would cross the book.
+    pub fn with_post_only_policy(mut self, post_only_policy: PostOnlyPolicy) -> Self {
+        self.post_only_policy = post_only_policy;
+        self
+    }
+
+    pub fn post_only_policy(&self) -> PostOnlyPolicy {
+        self.post_only_policy
+    }
+
+    /// Replaces the listener that receives book events; see [`OrderBookListener`] for the order
+    /// they arrive in.
+    pub fn set_listener(&mut self, listener: Box<dyn OrderBookListener>) {
+        self.listener = listener;
+    }
+
+    /// Starts recording L2 updates, to be collected with [`OrderBook::take_updates`]. Levels
+    /// already on the book are the feed's starting point; pair this with a `depth()` snapshot.
+    /// Calling it again restarts the feed (and its sequence numbers) from the current book.
+    pub fn enable_market_data(&mut self) {
+        self.bids.clear_touched();
+        self.asks.clear_touched();
+        self.market_data = Some(L2Feed::new(&self.bids, &self.asks));
+    }
+
+    /// Drains the L2 updates recorded since the last call. Every add, cancel or modify emits at
+    /// most one update per price level it changed, in the order the levels were first touched.
+    /// Always empty unless [`OrderBook::enable_market_data`] was called.
+    pub fn take_updates(&mut self) -> Vec<L2Update> {
+        self.market_data.as_mut().map(L2Feed::take_updates).unwrap_or_default()
+    }
+
+    /// Starts recording L3 updates, one per change to a resting order, to be collected with
+    /// [`OrderBook::take_order_updates`]. Orders already on the book are the feed's starting
+    /// point; pair this with a snapshot of the queues. Calling it again restarts the feed (and
+    /// its sequence numbers) from the current book.
+    pub fn enable_order_data(&mut self) {
+        self.bids.record_order_changes();
+        self.asks.record_order_changes();
+        self.order_data = Some(L3Feed::default());
+    }
+
+    /// Drains the L3 updates recorded since the last call, in the order the changes happened
+    /// across both sides: an incoming order's executions come before it is added, so a mirror
+    /// applying them in sequence is never crossed. Always empty unless
+    /// [`OrderBook::enable_order_data`] was called.
+    pub fn take_order_updates(&mut self) -> Vec<L3Update> {
+        self.order_data.as_mut().map(L3Feed::take_updates).unwrap_or_default()
+    }
+
+    /// Starts recording every execution on a [`Tape`] that keeps the last `capacity` trades.
+    /// Calling it again starts a fresh tape.
+    pub fn enable_tape(&mut self, capacity: usize) {
+        self.tape = Some(Tape::new(capacity));
+    }
+
+    /// The trade tape, if [`OrderBook::enable_tape`] was called.
+    pub fn tape(&self) -> Option<&Tape> {
+        self.tape.as_ref()
+    }
+
+    /// Totals over every trade this book has executed.
+    pub fn stats(&self) -> BookStats {
+        self.stats
+    }
+
+    pub fn tick_size(&self) -> f64 {
+        self.tick_size
+    }
+
+    /// Converts a float price to ticks, rounding to the nearest tick (or, in strict mode,
+    /// rejecting prices that aren't already on one).
+    pub fn price(&self, value: f64) -> Result<Price, PriceError> {
+        if !value.is_finite() {
+            return Err(PriceError::NotFinite(value));
+        }
+        if self.strict_ticks && !Price::is_on_tick(value, self.tick_size) {
+            return Err(PriceError::OffTick(value));
         }
+        Ok(Price::from_f64(value, self.tick_size))
     }
 
-    fn match_bid(&mut self, order: &mut Order) {
-        while order.quantity > 0 {
-            // Use first_entry() because Bids are sorted Low -> High by default.
-            // The "Best" ask is the LOWEST price, which is at the start of the map.
-            if let Some(mut entry) = self.asks.first_entry() {
-                let best_ask_price = *entry.key();
-                let ask_queue = entry.get_mut();
+    pub fn bids(&self) -> &BookSide {
+        &self.bids
+    }
 
-                // If sellers are too expensive, break
-                if best_ask_price.into_inner() > order.price {
-                    break;
-                }
+    pub fn asks(&self) -> &BookSide {
+        &self.asks
+    }
 
-                // Execute the trade
-                let best_ask_order = ask_queue.front_mut().unwrap();
-                let trade_qty = order.quantity.min(best_ask_order.quantity);
-                // println!("Trade! Price: {}, Qty: {}", best_ask_price, trade_qty);
+    /// Price of the most recent execution.
+    pub fn last_trade_price(&self) -> Option<Price> {
+        self.last_trade_price
+    }
 
-                // Update the quantities as per the trade quantity
-                order.quantity -= trade_qty;
-                best_ask_order.quantity -= trade_qty;
+    /// Highest bid price and the total quantity resting there.
+    pub fn best_bid(&self) -> Option<(Price, u64)> {
+        self.bids.best().map(|(price, level)| (price, level.total_quantity()))
+    }
 
-                // Remove completed orders from queue
-                if best_ask_order.quantity == 0 {
-                    ask_queue.pop_front();
-                }
+    /// Lowest ask price and the total quantity resting there.
+    pub fn best_ask(&self) -> Option<(Price, u64)> {
+        self.asks.best().map(|(price, level)| (price, level.total_quantity()))
+    }
+
+    /// Visible quantity resting at `price` on `side`, zero if there is no level there.
+    pub fn volume_at(&self, side: Side, price: Price) -> u64 {
+        self.side(side).level_quantity(price).unwrap_or(0)
+    }
+
+    /// Visible quantity across every bid level.
+    pub fn total_bid_volume(&self) -> u64 {
+        self.bids.total_quantity()
+    }
+
+    /// Visible quantity across every ask level.
+    pub fn total_ask_volume(&self) -> u64 {
+        self.asks.total_quantity()
+    }
+
+    /// Checks the book's invariants and reports the first one broken: no level is empty, every
+    /// resting order has visible quantity and sits at its own side and price, cached totals
+    /// match the orders, ids are unique across both sides and pending stops, and the book is
+    /// not crossed. It walks the whole book.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.bids.validate()?;
+        self.asks.validate()?;
+
+        // Each side's index is now known to match its orders, so it answers for that side
+        let asks = self.asks.levels().flat_map(|(_, level)| level).filter(|order| self.bids.contains(order.id));
+        let stops = self.stops.orders().filter(|order| self.bids.contains(order.id) || self.asks.contains(order.id));
+        if let Some(order) = asks.chain(stops).next() {
+            return Err(InvariantViolation::DuplicateOrderId { order_id: order.id });
+        }
+
+        if let (Some((best_bid, _)), Some((best_ask, _))) = (self.bids.best(), self.asks.best())
+            && best_bid >= best_ask
+        {
+            return Err(InvariantViolation::Crossed { best_bid, best_ask });
+        }
+        Ok(())
+    }
+
+    /// Panics if the book is broken, in debug builds or with strict validation on.
+    fn check_invariants(&self) {
+        if (cfg!(debug_assertions) || self.strict_validation)
+            && let Err(violation) = self.validate()
+        {
+            panic!("order book invariant violated: {}", violation);
+        }
+    }
+
+    /// Best ask minus best bid in ticks, if both sides have liquidity.
+    pub fn spread(&self) -> Option<Price> {
+        Some(Price(self.best_ask()?.0.0 - self.best_bid()?.0.0))
+    }
+
+    /// Midpoint between the best bid and best ask as a float price, if both sides have
+    /// liquidity. It may fall between two ticks.
+    pub fn mid_price(&self) -> Option<f64> {
+        let bid = self.best_bid()?.0.to_f64(self.tick_size);
+        let ask = self.best_ask()?.0.to_f64(self.tick_size);
+        Some((bid + ask) / 2.0)
+    }
+
+    /// Top `levels` price levels of each side.
+    pub fn depth(&self, levels: usize) -> DepthSnapshot {
+        let aggregate = |side: &BookSide| {
+            side.iter_best_first()
+                .take(levels)
+                .map(|(price, level)| (price, level.total_quantity(), level.order_count()))
+                .collect()
+        };
+
+        DepthSnapshot {
+            bids: aggregate(&self.bids),
+            asks: aggregate(&self.asks),
+        }
+    }
+
+    /// Distribution of orders-per-level on one side of the book.
+    ///
+    /// Maps "number of resting orders at a level" to "number of levels with that many orders".
+    pub fn level_histogram(&self, side: Side) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        for (_, level) in self.side(side).levels() {
+            *histogram.entry(level.order_count()).or_insert(0) += 1;
+        }
+        histogram
+    }
 
-                // Cleanup empty price levels
-                if ask_queue.is_empty() {
-                    entry.remove();
+    /// Matches the order against the opposite side and rests any remainder of a limit order.
+    ///
+    /// Returns one `Trade` per resting order it executed against, in execution order. An order
+    /// that rests without trading returns an empty vec. Orders with a zero quantity, a
+    /// non-positive limit or trigger price, or the id of an order that is still open are
+    /// rejected with a [`LobError`] before anything happens. Only GTC limit orders rest: the
+    /// unfilled remainder of a market or IOC order is dropped, and an FOK order that cannot be
+    /// filled in full leaves the book untouched.
+    ///
+    /// Stop and stop-limit orders are held aside until the last trade price reaches their
+    /// trigger. Any stops triggered by this order's executions (or by the executions of other
+    /// triggered stops) run before this returns, and their trades are appended after the
+    /// order's own.
+    pub fn add_order(&mut self, order: Order) -> Result<Vec<Trade>, LobError> {
+        Ok(self.add_order_with_report(order)?.trades)
+    }
+
+    /// Like [`OrderBook::add_order`], but also reports orders cancelled by self-trade prevention.
+    pub fn add_order_with_report(&mut self, mut order: Order) -> Result<ExecutionReport, LobError> {
+        let expired = self.tick();
+        if order.timestamp == 0 && self.clock.is_some() {
+            order.timestamp = self.now;
+        }
+        self.check_order(&order)?;
+        order.original_quantity = order.remaining();
+        self.sequence(&mut order);
+        self.accounting.submitted += order.remaining();
+        let id = order.id;
+        let mut report = if order.trigger().is_none() && self.auction.is_some() {
+            self.collect(order);
+            ExecutionReport::default()
+        } else {
+            self.submit(order)
+        };
+
+        let resting = self.bids.get(id).or_else(|| self.asks.get(id));
+        let pending = || self.stops.get(id).or_else(|| self.auction.iter().flatten().find(|order| order.id == id));
+        report.resting = resting.is_some();
+        report.remaining_quantity = resting.or_else(pending).map_or(0, Order::remaining);
+        report.expired = expired;
+        Ok(report)
+    }
+
+    fn check_order(&self, order: &Order) -> Result<(), LobError> {
+        if order.quantity == 0 {
+            return Err(LobError::ZeroQuantity);
+        }
+
+        let prices = match order.order_type {
+            OrderType::Limit => [Some(order.price), None],
+            OrderType::Market => [None, None],
+            OrderType::Stop { trigger } => [Some(trigger), None],
+            OrderType::StopLimit { trigger, limit } => [Some(trigger), Some(limit)],
+        };
+        if let Some(price) = prices.into_iter().flatten().chain(order.protection_price).find(|price| price.0 <= 0) {
+            return Err(LobError::InvalidPrice(price));
+        }
+
+        let collected = self.auction.iter().flatten().any(|collected| collected.id == order.id);
+        if self.bids.contains(order.id) || self.asks.contains(order.id) || self.stops.contains(order.id) || collected {
+            return Err(LobError::DuplicateOrderId(order.id));
+        }
+        if let Some(expires_at) = order.expires_at
+            && expires_at <= self.now
+        {
+            return Err(LobError::Expired(expires_at));
+        }
+        if self.strict_timestamps && order.timestamp < self.last_timestamp {
+            return Err(LobError::TimestampBehind { timestamp: order.timestamp, last: self.last_timestamp });
+        }
+
+        if order.order_type == OrderType::Limit
+            && let Some((low, high)) = self.outside_price_band(order.price)
+        {
+            return Err(LobError::OutsidePriceBand { price: order.price, low, high });
+        }
+        if order.order_type == OrderType::Limit
+            && order.time_in_force == TimeInForce::Gtc
+            && let Some(max_levels) = self.beyond_level_cap(order.side, order.price, None)
+        {
+            return Err(LobError::LevelCapReached { price: order.price, max_levels });
+        }
+
+        // Stop-limits are checked when they trigger, against the book as it is then
+        if order.post_only && order.order_type == OrderType::Limit && self.post_only_price(order).is_none() {
+            return Err(LobError::PostOnlyWouldCross(order.price));
+        }
+        Ok(())
+    }
+
+    /// Where a post-only order can rest without trading: its own price if that doesn't reach
+    /// the opposite best, one tick inside it under [`PostOnlyPolicy::Reprice`], or `None` if
+    /// the order has to be refused.
+    fn post_only_price(&self, order: &Order) -> Option<Price> {
+        let inside = match order.side {
+            Side::Buy => self.asks.best().map(|(ask, _)| Price(ask.0 - 1)).filter(|&inside| order.price > inside),
+            Side::Sell => self.bids.best().map(|(bid, _)| Price(bid.0 + 1)).filter(|&inside| order.price < inside),
+        };
+        match (inside, self.post_only_policy) {
+            (None, _) => Some(order.price),
+            (Some(inside), PostOnlyPolicy::Reprice) if inside.0 > 0 => Some(inside),
+            _ => None,
+        }
+    }
+
+    /// Runs an order that has already passed `check_order`.
+    fn submit(&mut self, order: Order) -> ExecutionReport {
+        let mut report = ExecutionReport::default();
+
+        self.listener.on_order_accepted(&order);
+        if let Some(expires_at) = order.expires_at {
+            self.expiries.push(Reverse((expires_at, order.id)));
+        }
+        if order.trigger().is_some() {
+            self.accounting.hold(Held::Pending, order.remaining());
+            self.stops.insert(order);
+        } else {
+            self.execute(order, &mut report);
+        }
+        self.trigger_stops(&mut report);
+        self.publish_market_data();
+        self.check_invariants();
+
+        report
+    }
+
+    /// Matches a market or limit order and rests what's left if it's allowed to.
+    fn execute(&mut self, mut order: Order, report: &mut ExecutionReport) {
+        let id = order.id;
+        if order.post_only && order.order_type == OrderType::Limit {
+            // Settled before the sweep so a post-only order never takes liquidity
+            match self.post_only_price(&order) {
+                Some(price) => order.price = price,
+                None => {
+                    self.listener.on_order_cancelled(&order);
+                    self.accounting.cancel(Held::Arriving, order.remaining());
+                    return;
                 }
-            } else {
-                break; // No sellers
             }
         }
 
-        // If not fully filled, rest on the book
-        if order.quantity > 0 {
-            self.bids.entry(OrderedFloat(order.price))
-                .or_default()
-                .push_back(order.clone());
-            // println!("Buy Order rested: {} @ {}", order.quantity, order.price);
+        let mut collar = self.collar(&order);
+        let (opposite, own) = match order.side {
+            Side::Buy => (&mut self.asks, &mut self.bids),
+            Side::Sell => (&mut self.bids, &mut self.asks),
+        };
+
+        // Check FOK liquidity up front so a kill never mutates the book
+        if order.time_in_force == TimeInForce::Fok
+            && opposite.available(&order, self.stp_policy, collar) < order.quantity
+        {
+            self.listener.on_order_cancelled(&order);
+            self.accounting.cancel(Held::Arriving, order.remaining());
+            return;
+        }
+
+        // Cross the opposite side first
+        let (first_trade, first_cancel) = (report.trades.len(), report.self_trade_cancels.len());
+        let arriving = order.quantity;
+        opposite.sweep(&mut order, self.stp_policy, self.match_policy, &mut collar, report, &mut *self.listener);
+        if let Some(feed) = &mut self.order_data {
+            feed.publish(opposite, self.now);
+        }
+        for maker in opposite.take_filled() {
+            self.recent_fills.push(&maker);
+        }
+        if report.trades.len() > first_trade {
+            self.last_trade_price = report.trades.last().map(|trade| trade.price);
+        }
+        if let Some(tape) = &mut self.tape {
+            report.trades[first_trade..].iter().for_each(|trade| tape.record(trade));
+        }
+
+        let fills = &report.trades[first_trade..];
+        let filled: u64 = fills.iter().map(|trade| trade.quantity).sum();
+        order.taker_quantity += filled;
+        let improvement: i64 = match order.order_type {
+            OrderType::Limit => fills.iter().map(|trade| price_improvement(order.side, order.price, trade)).sum(),
+            _ => 0,
+        };
+        report.price_improvement += improvement;
+        self.stats.trades += fills.len() as u64;
+        self.stats.volume += filled;
+        self.stats.price_improvement += improvement;
+        let makers = Held::Resting(opposite.side());
+        self.accounting.trade(makers, filled);
+        for cancel in &report.self_trade_cancels[first_cancel..] {
+            self.accounting.cancel(if cancel.resting { makers } else { Held::Arriving }, cancel.quantity);
+        }
+
+        // If not fully filled, rest on the book; anything else left over is cancelled, as is
+        // whatever the collar stopped short
+        if filled == arriving {
+            self.recent_fills.push(&order);
+            return;
+        }
+        if order.quantity == 0 {
+            return;
+        }
+        // The sweep only stops short of a level the order reaches when the collar trips, so a
+        // remainder that would cross the opposite best means matching went wrong; it is
+        // cancelled rather than left to cross the book
+        let crosses = match order.side {
+            Side::Buy => opposite.best().is_some_and(|(ask, _)| ask <= order.price),
+            Side::Sell => opposite.best().is_some_and(|(bid, _)| bid >= order.price),
+        };
+        debug_assert!(!crosses || collar.tripped, "order {} would rest crossing the book", id);
+        let rests = order.order_type == OrderType::Limit && order.time_in_force == TimeInForce::Gtc;
+        if rests && !collar.tripped && !crosses {
+            let side = order.side;
+            self.accounting.hold(Held::Resting(side), order.remaining());
+            own.insert(order);
+            if let Some(feed) = &mut self.order_data {
+                feed.publish(own, self.now);
+            }
+            if let Some(order) = own.get(id) {
+                self.listener.on_order_rested(order);
+            }
+            report.evicted.extend(self.enforce_level_cap(side));
+        } else {
+            self.listener.on_order_cancelled(&order);
+            self.accounting.cancel(Held::Arriving, order.remaining());
+        }
+    }
+
+    /// Activates stops until the last trade price stops triggering new ones.
+    fn trigger_stops(&mut self, report: &mut ExecutionReport) {
+        while !self.stops.is_empty() {
+            let Some(last_trade_price) = self.last_trade_price else {
+                return;
+            };
+
+            let triggered = self.stops.take_triggered(last_trade_price);
+            if triggered.is_empty() {
+                return;
+            }
+            for order in triggered {
+                self.accounting.release(Held::Pending, order.remaining());
+                self.execute(order, report);
+            }
         }
     }
 
-    fn match_ask(&mut self, order: &mut Order) {
-        while order.quantity > 0 {
-            // Use last_entry() because Bids are sorted Low -> High by default.
-            // The "Best" bid is the HIGHEST price, which is at the end of the map.
-            if let Some(mut entry) = self.bids.last_entry() {
-                let best_bid_price = *entry.key();
-                let bid_queue = entry.get_mut();
+    /// What a market order for `quantity` on `side` would fill right now, without touching the
+    /// book. It walks the opposite side exactly as matching would, price band included, but
+    /// only counts the visible quantity: hidden iceberg reserves and self-trade prevention can
+    /// make the real order's fills differ.
+    pub fn simulate_fill(&self, side: Side, quantity: u64) -> FillSimulation {
+        self.simulate(&Order::market(0, side, quantity))
+    }
 
-                // If buyers are too cheap, break
-                if best_bid_price.into_inner() < order.price {
-                    break;
-                }
+    /// Like [`OrderBook::simulate_fill`], for the marketable part of a limit order at `price`.
+    pub fn simulate_limit(&self, side: Side, price: Price, quantity: u64) -> FillSimulation {
+        self.simulate(&Order::limit(0, side, price, quantity))
+    }
+
+    fn simulate(&self, taker: &Order) -> FillSimulation {
+        let opposite = match taker.side {
+            Side::Buy => &self.asks,
+            Side::Sell => &self.bids,
+        };
+        opposite.simulate(taker, self.collar(taker))
+    }
 
-                // Execute the trade
-                let best_bid_order = bid_queue.front_mut().unwrap();
-                let trade_qty = order.quantity.min(best_bid_order.quantity);
-                // println!("Trade Executed! Price: {}, Qty: {}", best_bid_price, trade_qty);
+    /// Submits a market order, walking as many levels as needed.
+    pub fn add_market_order(
+        &mut self,
+        id: u64,
+        side: Side,
+        quantity: u64,
+        timestamp: u64,
+    ) -> Result<MarketOrderResult, LobError> {
+        let trades = self.add_order(Order::market(id, side, quantity).with_timestamp(timestamp))?;
 
-                // Update the quantities as per the trade quantity
-                order.quantity -= trade_qty;
-                best_bid_order.quantity -= trade_qty;
+        let filled: u64 = trades.iter().map(|trade| trade.quantity).sum();
+        Ok(MarketOrderResult { trades, unfilled: quantity - filled })
+    }
 
-                // Remove completed orders from queue
-                if best_bid_order.quantity == 0 {
-                    bid_queue.pop_front();
-                }
+    /// Removes a resting or pending stop order and returns it with its remaining quantity
+    /// (including any hidden iceberg reserve).
+    pub fn cancel_order(&mut self, id: u64) -> Result<Order, CancelError> {
+        self.tick();
+        if let Some(order) = self.remove_resting(id) {
+            self.listener.on_order_cancelled(&order);
+            self.accounting.cancel(Held::Resting(order.side), order.remaining());
+            self.report_if_level_removed(order.side, order.price);
+            self.publish_market_data();
+            self.check_invariants();
+            return Ok(order);
+        }
 
-                // Cleanup empty price levels
-                if bid_queue.is_empty() {
-                    entry.remove();
+        let order = self.stops.remove(id).or_else(|| self.cancel_collected(id)).ok_or(CancelError::NotFound(id))?;
+        self.listener.on_order_cancelled(&order);
+        self.accounting.cancel(Held::Pending, order.remaining());
+        self.check_invariants();
+        Ok(order)
+    }
+
+    /// Book time, as last set by [`OrderBook::advance_time`] or read off the book's clock.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Moves the book's clock forward to `now` and removes every resting or pending stop order
+    /// whose expiry has been reached. Expiry is exclusive: an order with `expires_at = t` can
+    /// still trade while the book's time is before `t` and is gone once it reaches `t`, and an
+    /// order arriving already expired is rejected. Time never moves backwards.
+    ///
+    /// Each expired order is reported to the listener as cancelled.
+    pub fn advance_time(&mut self, now: u64) -> Vec<ExpiredOrder> {
+        self.now = self.now.max(now);
+
+        let mut expired = Vec::new();
+        while let Some(&Reverse((expires_at, id))) = self.expiries.peek()
+            && expires_at <= self.now
+        {
+            self.expiries.pop();
+
+            // The id may since have closed, or been reused by an order with another expiry
+            let live = |order: &Order| order.expires_at == Some(expires_at);
+            let order = if self.bids.get(id).or_else(|| self.asks.get(id)).is_some_and(live) {
+                let order = self.remove_resting(id);
+                if let Some(order) = &order {
+                    self.listener.on_order_cancelled(order);
+                    self.accounting.expire(Held::Resting(order.side), order.remaining());
+                    self.report_if_level_removed(order.side, order.price);
                 }
+                order
+            } else if self.stops.get(id).is_some_and(live) {
+                let order = self.stops.remove(id);
+                if let Some(order) = &order {
+                    self.listener.on_order_cancelled(order);
+                    self.accounting.expire(Held::Pending, order.remaining());
+                }
+                order
             } else {
-                break; // No buyers
+                None
+            };
+
+            if let Some(order) = order {
+                expired.push(ExpiredOrder {
+                    order_id: order.id,
+                    side: order.side,
+                    price: order.price,
+                    quantity: order.remaining(),
+                    expires_at,
+                });
             }
         }
 
-        // If not fully filled, rest on the book
-        if order.quantity > 0 {
-            self.asks.entry(OrderedFloat(order.price))
-                .or_default()
-                .push_back(order.clone());
-            // println!("Sell Order rested: {} @ {}", order.quantity, order.price);
+        self.publish_market_data();
+        self.check_invariants();
+        expired
+    }
+
+    /// Whether a stop order with `id` is waiting for its trigger.
+    pub fn is_stop_pending(&self, id: u64) -> bool {
+        self.stops.contains(id)
+    }
+
+    /// Amends a resting order's price and/or quantity. For an iceberg, `new_quantity` is
+    /// its total open quantity, visible and hidden.
+    ///
+    /// Reducing the quantity at the same price keeps the order's place in the queue. Any price
+    /// change or quantity increase is a cancel/replace: the order goes to the back of the queue
+    /// at its new price and may trade immediately if that price crosses the book. During an
+    /// auction it goes to the back of the collected orders instead, and orders the auction has
+    /// collected can be amended the same way.
+    pub fn modify_order(&mut self, id: u64, new_price: Price, new_quantity: u64) -> Result<Vec<Trade>, ModifyError> {
+        self.tick();
+        if new_quantity == 0 {
+            return Err(ModifyError::ZeroQuantity);
+        }
+        if new_price.0 <= 0 {
+            return Err(ModifyError::InvalidPrice(new_price));
+        }
+
+        let collected = self.auction.iter().flatten().find(|order| order.id == id);
+        let resting = self.bids.get(id).or_else(|| self.asks.get(id));
+        let current = resting.or(collected).ok_or(ModifyError::NotFound(id))?;
+        if current.post_only && self.post_only_price(&Order { price: new_price, ..current.clone() }).is_none() {
+            return Err(ModifyError::PostOnlyWouldCross(new_price));
+        }
+        if new_price != current.price
+            && let Some((low, high)) = self.outside_price_band(new_price)
+        {
+            return Err(ModifyError::OutsidePriceBand { price: new_price, low, high });
+        }
+        let rests = current.order_type == OrderType::Limit && current.time_in_force == TimeInForce::Gtc;
+        if rests && let Some(max_levels) = self.beyond_level_cap(current.side, new_price, resting) {
+            return Err(ModifyError::LevelCapReached { price: new_price, max_levels });
         }
+        let held = resting.map_or(Held::Pending, |current| Held::Resting(current.side));
+        if current.price == new_price && new_quantity <= current.remaining() {
+            // Priority is preserved, nothing can trade
+            let reduced = current.remaining() - new_quantity;
+            let done = match held {
+                Held::Resting(Side::Buy) => self.bids.reduce(id, new_quantity),
+                Held::Resting(Side::Sell) => self.asks.reduce(id, new_quantity),
+                _ => self.reduce_collected(id, new_quantity),
+            };
+            if done {
+                self.accounting.cancel(held, reduced);
+            }
+            self.publish_market_data();
+            self.check_invariants();
+            return Ok(Vec::new());
+        }
+
+        let mut order = match held {
+            Held::Resting(_) => self.remove_resting(id),
+            _ => self.cancel_collected(id),
+        }
+        .ok_or(ModifyError::NotFound(id))?;
+        if let Held::Resting(side) = held {
+            self.report_if_level_removed(side, order.price);
+        }
+        let open = order.remaining();
+        self.accounting.release(held, open);
+        match new_quantity.checked_sub(open) {
+            Some(added) => self.accounting.submitted += added,
+            None => self.accounting.cancel(Held::Arriving, open - new_quantity),
+        }
+        order.original_quantity = order.original_quantity - open + new_quantity;
+        order.price = new_price;
+        order.quantity = new_quantity;
+        order.hidden_quantity = 0;
+        self.sequence(&mut order);
+        if self.auction.is_some() {
+            self.collect(order);
+            self.publish_market_data();
+            self.check_invariants();
+            return Ok(Vec::new());
+        }
+        Ok(self.submit(order).trades)
     }
-}
\ No newline at end of file
+
+    /// Turns the levels touched since the last call into L2 updates, if the feed is on.
+    fn publish_market_data(&mut self) {
+        if let Some(feed) = &mut self.market_data {
+            feed.publish(&self.bids, self.now);
+            feed.publish(&self.asks, self.now);
+        }
+        self.publish_order_data();
+        self.bids.clear_touched();
+        self.asks.clear_touched();
+    }
+
+    /// Hands the order changes the sides have recorded to the L3 feed, if it is on. Every step
+    /// that changes a side hands its changes over before the other side is touched, so they
+    /// reach the feed in the order they happened.
+    fn publish_order_data(&mut self) {
+        if let Some(feed) = &mut self.order_data {
+            let both = self.bids.has_order_changes() && self.asks.has_order_changes();
+            debug_assert!(!both, "order changes on both sides would lose their order");
+            feed.publish(&mut self.bids, self.now);
+            feed.publish(&mut self.asks, self.now);
+        }
+    }
+
+    /// Takes a resting order off whichever side it is on.
+    fn remove_resting(&mut self, id: u64) -> Option<Order> {
+        let order = self.bids.remove(id).or_else(|| self.asks.remove(id));
+        self.publish_order_data();
+        order
+    }
+
+    fn report_if_level_removed(&mut self, side: Side, price: Price) {
+        if self.side(side).level(price).is_none() {
+            self.listener.on_level_removed(side, price);
+        }
+    }
+
+    fn side(&self, side: Side) -> &BookSide {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+}
+
+/// Ticks times quantity by which a fill beat the taker's `limit`.
+fn price_improvement(side: Side, limit: Price, trade: &Trade) -> i64 {
+    let per_unit = match side {
+        Side::Buy => limit.0 - trade.price.0,
+        Side::Sell => trade.price.0 - limit.0,
+    };
+    per_unit * trade.quantity as i64
+}
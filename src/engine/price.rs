@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// Tick size used by `OrderBook::new()`.
+pub const DEFAULT_TICK_SIZE: f64 = 0.01;
+
+/// Prices closer than this fraction of a tick to a tick boundary count as on the tick, so
+/// ordinary float noise (100.10000000001) doesn't trip strict mode.
+const TICK_TOLERANCE: f64 = 1e-6;
+
+/// A price expressed as an integer number of ticks.
+///
+/// Using integers as level keys means two prices land in the same level exactly when they
+/// round to the same tick, and matching compares plain integers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct Price(pub i64);
+
+impl Price {
+    /// Rounds `value` to the nearest multiple of `tick_size`.
+    pub fn from_f64(value: f64, tick_size: f64) -> Self {
+        Price((value / tick_size).round() as i64)
+    }
+
+    /// Converts back to a float price for display.
+    pub fn to_f64(self, tick_size: f64) -> f64 {
+        // Dividing by a whole number of ticks per unit is exact where multiplying by a
+        // fractional tick (0.01) is not: 10050 / 100 is 100.5, 10050 * 0.01 is not.
+        let ticks_per_unit = (1.0 / tick_size).round();
+        if ((1.0 / tick_size) - ticks_per_unit).abs() < TICK_TOLERANCE {
+            self.0 as f64 / ticks_per_unit
+        } else {
+            self.0 as f64 * tick_size
+        }
+    }
+
+    /// Whether `value` sits on a multiple of `tick_size`, within float noise.
+    pub fn is_on_tick(value: f64, tick_size: f64) -> bool {
+        let ticks = value / tick_size;
+        (ticks - ticks.round()).abs() < TICK_TOLERANCE
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ticks", self.0)
+    }
+}
@@ -0,0 +1,272 @@
+use super::*;
+use super::slab::{Handle, OrderSlab};
+
+/// The FIFO queue of orders resting at one price, with its visible quantity cached so
+/// depth and volume queries don't walk the queue.
+///
+/// A level is a read-only view: the queue itself only links handles into its side's
+/// [`OrderSlab`], and all changes go through `BookSide`, which keeps the cache in step with
+/// the orders.
+#[derive(Clone, Copy)]
+pub struct PriceLevel<'a> {
+    queue: &'a LevelQueue,
+    orders: &'a OrderSlab,
+}
+
+impl<'a> PriceLevel<'a> {
+    pub(crate) fn new(queue: &'a LevelQueue, orders: &'a OrderSlab) -> Self {
+        Self { queue, orders }
+    }
+
+    /// Sum of the visible quantity of the orders at this level.
+    pub fn total_quantity(&self) -> u64 {
+        self.queue.total_quantity
+    }
+
+    pub fn order_count(&self) -> usize {
+        self.queue.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.len == 0
+    }
+
+    /// The order at the front of the queue, next in line to trade.
+    pub fn front(&self) -> Option<&'a Order> {
+        self.queue.head.map(|handle| self.orders.order(handle))
+    }
+
+    /// The order at the back of the queue, the last to join it.
+    pub fn back(&self) -> Option<&'a Order> {
+        self.queue.tail.map(|handle| self.orders.order(handle))
+    }
+
+    /// [`Order::seq`] of the front order, or `None` for an empty level.
+    ///
+    /// Orders join a level in sequence order, but an iceberg that shows its next slice goes to
+    /// the back with the seq it had, so the two ends are not always the smallest and largest
+    /// seq at the level.
+    pub fn oldest_priority_seq(&self) -> Option<u64> {
+        self.front().map(|order| order.seq)
+    }
+
+    /// [`Order::seq`] of the back order, or `None` for an empty level.
+    pub fn newest_priority_seq(&self) -> Option<u64> {
+        self.back().map(|order| order.seq)
+    }
+
+    /// How long the front order has been waiting at `now`, from the book time it was accepted
+    /// at ([`Order::accepted_at`]), whatever timestamp the caller gave it. Age zero if `now` is
+    /// earlier.
+    pub fn age_of_front(&self, now: u64) -> Option<u64> {
+        self.front().map(|order| now.saturating_sub(order.accepted_at))
+    }
+
+    /// Orders front to back, i.e. in time priority.
+    pub fn iter(&self) -> LevelIter<'a> {
+        LevelIter { orders: self.orders, next: self.queue.head, remaining: self.queue.len }
+    }
+
+    /// Visible quantity recomputed from the orders, for checking the cache.
+    pub(crate) fn recomputed_quantity(&self) -> u64 {
+        self.iter().map(|order| order.quantity).sum()
+    }
+}
+
+impl fmt::Debug for PriceLevel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for PriceLevel<'a> {
+    type Item = &'a Order;
+    type IntoIter = LevelIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &PriceLevel<'a> {
+    type Item = &'a Order;
+    type IntoIter = LevelIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over one level's orders in time priority, see [`PriceLevel::iter`].
+#[derive(Clone)]
+pub struct LevelIter<'a> {
+    orders: &'a OrderSlab,
+    next: Option<Handle>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for LevelIter<'a> {
+    type Item = &'a Order;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.orders.node(self.next?);
+        self.next = node.next;
+        self.remaining -= 1;
+        Some(&node.order)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for LevelIter<'_> {}
+
+/// The linked queue behind a [`PriceLevel`]: its first and last handle, its length and its
+/// cached visible quantity. Every method takes the side's slab the handles point into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct LevelQueue {
+    head: Option<Handle>,
+    tail: Option<Handle>,
+    len: usize,
+    total_quantity: u64,
+}
+
+impl LevelQueue {
+    pub(crate) fn total_quantity(&self) -> u64 {
+        self.total_quantity
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn front(&self) -> Option<Handle> {
+        self.head
+    }
+
+    /// Moves `order` into the slab at the back of the queue.
+    pub(crate) fn push_back(&mut self, orders: &mut OrderSlab, order: Order) -> Handle {
+        self.total_quantity += order.quantity;
+        let handle = orders.insert(order);
+        self.link_back(orders, handle);
+        handle
+    }
+
+    pub(crate) fn pop_front(&mut self, orders: &mut OrderSlab) -> Option<Order> {
+        self.remove(orders, self.head?)
+    }
+
+    pub(crate) fn remove(&mut self, orders: &mut OrderSlab, handle: Handle) -> Option<Order> {
+        self.unlink(orders, handle)?;
+        let order = orders.remove(handle)?;
+        self.total_quantity -= order.quantity;
+        Some(order)
+    }
+
+    /// Takes `quantity` off the front order, which must have at least that much visible.
+    pub(crate) fn fill_front(&mut self, orders: &mut OrderSlab, quantity: u64) {
+        if let Some(handle) = self.head {
+            let order = orders.order_mut(handle);
+            let quantity = quantity.min(order.quantity);
+            order.quantity -= quantity;
+            self.total_quantity -= quantity;
+        }
+    }
+
+    /// Shows the next slice of the front order (an iceberg whose visible slice is used up)
+    /// and sends it to the back of the queue. Returns `false` if it has no reserve left.
+    pub(crate) fn replenish_front(&mut self, orders: &mut OrderSlab) -> bool {
+        let Some(handle) = self.head else {
+            return false;
+        };
+        let order = orders.order_mut(handle);
+        let shown = order.quantity;
+        if !order.replenish() {
+            return false;
+        }
+
+        self.total_quantity += order.quantity - shown;
+        if self.unlink(orders, handle).is_some() {
+            self.link_back(orders, handle);
+        }
+        true
+    }
+
+    /// Takes `allocations[i]` off the visible quantity of the `i`-th order in the queue, as in a
+    /// pro-rata round. Each share must be smaller than the order's visible quantity, so every
+    /// order keeps its place.
+    pub(crate) fn fill_each(&mut self, orders: &mut OrderSlab, allocations: &[u64]) {
+        let mut next = self.head;
+        for &quantity in allocations {
+            let Some(handle) = next else {
+                break;
+            };
+            let node = orders.node_mut(handle);
+            node.order.quantity -= quantity;
+            self.total_quantity -= quantity;
+            next = node.next;
+        }
+    }
+
+    /// Shrinks an order's total open quantity in place, see [`BookSide::reduce`]. Returns the
+    /// visible quantity it gave up, or `None` if nothing changed.
+    pub(crate) fn reduce(&mut self, orders: &mut OrderSlab, handle: Handle, quantity: u64) -> Option<u64> {
+        let order = &mut orders.get_mut(handle)?.order;
+        if quantity == 0 || quantity >= order.remaining() {
+            return None;
+        }
+
+        order.original_quantity -= order.remaining() - quantity;
+        let visible = quantity.min(order.quantity);
+        let removed = order.quantity - visible;
+        order.hidden_quantity = quantity - visible;
+        order.quantity = visible;
+        self.total_quantity -= removed;
+        Some(removed)
+    }
+
+    /// Whether walking the links front to back visits exactly `len` live orders, each pointing
+    /// back at the one before, and ends at the tail.
+    pub(crate) fn links_intact(&self, orders: &OrderSlab) -> bool {
+        let (mut prev, mut next, mut visited) = (None, self.head, 0);
+        while let Some(handle) = next {
+            let Some(node) = orders.get(handle).filter(|node| node.prev == prev) else {
+                return false;
+            };
+            visited += 1;
+            if visited > self.len {
+                return false;
+            }
+            (prev, next) = (Some(handle), node.next);
+        }
+        visited == self.len && prev == self.tail
+    }
+
+    fn link_back(&mut self, orders: &mut OrderSlab, handle: Handle) {
+        let node = orders.node_mut(handle);
+        node.prev = self.tail;
+        node.next = None;
+        match self.tail {
+            Some(tail) => orders.node_mut(tail).next = Some(handle),
+            None => self.head = Some(handle),
+        }
+        self.tail = Some(handle);
+        self.len += 1;
+    }
+
+    fn unlink(&mut self, orders: &mut OrderSlab, handle: Handle) -> Option<()> {
+        let node = orders.get_mut(handle)?;
+        let (prev, next) = (node.prev.take(), node.next.take());
+        match prev {
+            Some(prev) => orders.node_mut(prev).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => orders.node_mut(next).prev = prev,
+            None => self.tail = prev,
+        }
+        self.len -= 1;
+        Some(())
+    }
+}
@@ -0,0 +1,120 @@
+use super::*;
+
+/// Levels per side shown when a book is printed with `{}`.
+const DISPLAY_DEPTH: usize = 10;
+
+/// Layout of [`OrderBook::render`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Characters in the bar of the largest level shown; 0 leaves the bars out.
+    pub bar_width: usize,
+    /// List each level's orders under it, front of the queue first.
+    pub show_orders: bool,
+    /// Decimal places of prices; `None` uses as many as the tick size needs.
+    pub precision: Option<usize>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self { bar_width: 20, show_orders: false, precision: None }
+    }
+}
+
+/// The top ten levels of each side as a ladder, see [`OrderBook::render`].
+impl fmt::Display for OrderBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(DISPLAY_DEPTH, RenderOptions::default()))
+    }
+}
+
+impl OrderBook {
+    /// Renders the top `depth` levels of each side as a text ladder: asks from the highest
+    /// shown price down to the best, a spread line, then bids from the best down.
+    ///
+    /// Each level shows its price, visible size, order count and a bar scaled so the largest
+    /// level shown is `bar_width` wide. With `show_orders` every order follows its level as
+    /// `#id` and its visible quantity, plus any iceberg reserve. Levels beyond `depth` are
+    /// counted on a line of their own. Columns are as wide as their widest entry, so the same
+    /// book always renders to the same text.
+    pub fn render(&self, depth: usize, opts: RenderOptions) -> String {
+        let precision = opts.precision.unwrap_or_else(|| tick_decimals(self.tick_size));
+        let format_price = |price: Price| format!("{:.*}", precision, price.to_f64(self.tick_size));
+
+        let asks: Vec<_> = self.asks.iter_best_first().take(depth).collect();
+        let bids: Vec<_> = self.bids.iter_best_first().take(depth).collect();
+        if self.asks.is_empty() && self.bids.is_empty() {
+            return "empty book\n".to_string();
+        }
+
+        let rows = || asks.iter().chain(&bids);
+        let listed = || rows().flat_map(|(_, level)| level.iter()).filter(|_| opts.show_orders);
+        let price_width = column_width("PRICE", rows().map(|&(price, _)| format_price(price).len()));
+        let size_width = column_width("SIZE", rows().map(|(_, level)| digits(level.total_quantity())));
+        let counts = rows().map(|(_, level)| digits(level.order_count() as u64));
+        let count_width = column_width("ORDERS", counts.chain(listed().map(|order| digits(order.id) + 1)));
+        let largest = rows().map(|(_, level)| level.total_quantity()).max().unwrap_or(0);
+
+        let header = format!("{:<4}{:>price_width$}  {:>size_width$}  {:>count_width$}", "", "PRICE", "SIZE", "ORDERS");
+        let mut lines = vec![header];
+        let push_level = |lines: &mut Vec<String>, label: &str, price: Price, level: PriceLevel<'_>| {
+            let bar = "#".repeat(bar_length(level.total_quantity(), largest, opts.bar_width));
+            let (price, size, count) = (format_price(price), level.total_quantity(), level.order_count());
+            lines.push(format!("{label:<4}{price:>price_width$}  {size:>size_width$}  {count:>count_width$}  {bar}"));
+            for order in level.iter().filter(|_| opts.show_orders) {
+                let (id, quantity) = (format!("#{}", order.id), order.quantity);
+                let mut line = format!("{:<4}{:>price_width$}  {quantity:>size_width$}  {id:>count_width$}", "", "");
+                if order.hidden_quantity > 0 {
+                    line += &format!("  +{} hidden", order.hidden_quantity);
+                }
+                lines.push(line);
+            }
+        };
+
+        let more = |side: &BookSide, shown: usize| {
+            let more = side.levels().len() - shown;
+            (more > 0).then(|| format!("... {} more level{}", more, if more == 1 { "" } else { "s" }))
+        };
+        lines.extend(more(&self.asks, asks.len()));
+        for &(price, level) in asks.iter().rev() {
+            push_level(&mut lines, "ASK", price, level);
+        }
+
+        let width = 4 + price_width + 2 + size_width + 2 + count_width;
+        let spread = match self.spread() {
+            Some(spread) => format!(" spread {} ", format_price(spread)),
+            None if self.bids.is_empty() => " no bids ".to_string(),
+            None => " no asks ".to_string(),
+        };
+        lines.push(format!("{spread:-^width$}"));
+
+        for &(price, level) in &bids {
+            push_level(&mut lines, "BID", price, level);
+        }
+        lines.extend(more(&self.bids, bids.len()));
+
+        lines.iter().map(|line| format!("{}\n", line.trim_end())).collect()
+    }
+}
+
+fn digits(value: u64) -> usize {
+    value.to_string().len()
+}
+
+/// Width of a column headed `header` holding entries of the given widths.
+fn column_width(header: &str, entries: impl Iterator<Item = usize>) -> usize {
+    entries.fold(header.len(), usize::max)
+}
+
+/// Bar length for a level of `quantity` when the largest level shown gets `width`; any
+/// non-empty level gets at least one character.
+fn bar_length(quantity: u64, largest: u64, width: usize) -> usize {
+    if largest == 0 {
+        return 0;
+    }
+    (quantity as u128 * width as u128).div_ceil(largest as u128) as usize
+}
+
+/// Fewest decimal places that show every multiple of `tick_size` exactly, up to 8.
+fn tick_decimals(tick_size: f64) -> usize {
+    (0..8).find(|&decimals| Price::is_on_tick(tick_size * 10f64.powi(decimals as i32), 1.0)).unwrap_or(8)
+}
@@ -0,0 +1,133 @@
+use super::*;
+
+/// Handle to an order stored in an [`OrderSlab`].
+///
+/// A slot's generation moves on every time it is freed, so a handle kept past its order's
+/// removal never resolves to whatever order reuses the slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Handle {
+    slot: u32,
+    generation: u32,
+}
+
+/// A resting order plus its links to its neighbours in its level's queue.
+#[derive(Debug)]
+pub(crate) struct OrderNode {
+    pub(crate) order: Order,
+    pub(crate) prev: Option<Handle>,
+    pub(crate) next: Option<Handle>,
+}
+
+#[derive(Debug)]
+struct Slot {
+    generation: u32,
+    /// `None` while the slot is on the free list.
+    node: Option<OrderNode>,
+    /// Next free slot, only meaningful while this one is free.
+    next_free: Option<u32>,
+}
+
+/// Storage for every resting order on one side of the book.
+///
+/// Orders are moved in once and stay put until they leave the book; the queues at each level
+/// only link handles together. Freed slots are reused most recently freed first, so a book
+/// whose size holds steady stops allocating altogether.
+#[derive(Debug, Default)]
+pub(crate) struct OrderSlab {
+    slots: Vec<Slot>,
+    free: Option<u32>,
+    len: usize,
+}
+
+impl OrderSlab {
+    /// Number of orders stored.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Number of slots ever allocated, free or not.
+    #[cfg(test)]
+    pub(crate) fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Moves `order` into a free slot, unlinked.
+    pub(crate) fn insert(&mut self, order: Order) -> Handle {
+        let node = OrderNode { order, prev: None, next: None };
+        self.len += 1;
+        match self.free {
+            Some(slot) => {
+                let entry = &mut self.slots[slot as usize];
+                self.free = entry.next_free.take();
+                entry.node = Some(node);
+                Handle { slot, generation: entry.generation }
+            }
+            None => {
+                let slot = u32::try_from(self.slots.len()).expect("more than u32::MAX resting orders");
+                self.slots.push(Slot { generation: 0, node: Some(node), next_free: None });
+                Handle { slot, generation: 0 }
+            }
+        }
+    }
+
+    /// Takes the order out of `handle`'s slot and frees the slot. The caller unlinks it from
+    /// its queue first.
+    pub(crate) fn remove(&mut self, handle: Handle) -> Option<Order> {
+        let entry = self.slots.get_mut(handle.slot as usize).filter(|entry| entry.generation == handle.generation)?;
+        let node = entry.node.take()?;
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.next_free = self.free.replace(handle.slot);
+        self.len -= 1;
+        Some(node.order)
+    }
+
+    pub(crate) fn get(&self, handle: Handle) -> Option<&OrderNode> {
+        let entry = self.slots.get(handle.slot as usize)?;
+        entry.node.as_ref().filter(|_| entry.generation == handle.generation)
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: Handle) -> Option<&mut OrderNode> {
+        let entry = self.slots.get_mut(handle.slot as usize)?;
+        entry.node.as_mut().filter(|_| entry.generation == handle.generation)
+    }
+
+    /// The order `handle` points at. Panics on a stale handle, which would mean a queue still
+    /// links an order that has left the book.
+    pub(crate) fn order(&self, handle: Handle) -> &Order {
+        &self.node(handle).order
+    }
+
+    pub(crate) fn order_mut(&mut self, handle: Handle) -> &mut Order {
+        &mut self.node_mut(handle).order
+    }
+
+    pub(crate) fn node(&self, handle: Handle) -> &OrderNode {
+        self.get(handle).expect("stale order handle")
+    }
+
+    pub(crate) fn node_mut(&mut self, handle: Handle) -> &mut OrderNode {
+        self.get_mut(handle).expect("stale order handle")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_freed_slots_are_reused_and_old_handles_go_stale() {
+        let mut slab = OrderSlab::default();
+        let first = slab.insert(Order::limit(1, Side::Buy, Price(100), 10));
+        let second = slab.insert(Order::limit(2, Side::Buy, Price(100), 10));
+        assert_eq!(slab.remove(first).unwrap().id, 1);
+        assert!(slab.remove(first).is_none());
+
+        // The most recently freed slot comes back, under a new generation
+        let third = slab.insert(Order::limit(3, Side::Buy, Price(100), 10));
+        assert_eq!(third.slot, first.slot);
+        assert!(slab.get(first).is_none());
+        assert_eq!(slab.order(third).id, 3);
+        assert_eq!(slab.order(second).id, 2);
+        assert_eq!((slab.len(), slab.slot_count()), (2, 2));
+    }
+}
@@ -0,0 +1,114 @@
+use super::*;
+
+/// Everything needed to rebuild a book: its settings and every open order in queue order.
+///
+/// Levels are plain lists of orders rather than maps, so the snapshot serializes to the same
+/// text for the same book and restoring it reproduces each level's FIFO queue exactly.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookSnapshot {
+    pub tick_size: f64,
+    pub strict_ticks: bool,
+    pub stp_policy: StpPolicy,
+    pub match_policy: MatchPolicy,
+    pub post_only_policy: PostOnlyPolicy,
+    pub price_band: Option<PriceBand>,
+    pub max_levels_per_side: Option<usize>,
+    pub level_cap_policy: LevelCapPolicy,
+    pub strict_timestamps: bool,
+    /// Book time, see `OrderBook::advance_time`.
+    pub now: u64,
+    /// See `OrderBook::last_sequence`; restored so sequence numbers keep increasing.
+    pub last_seq: u64,
+    /// Latest order timestamp accepted, checked against under strict timestamps.
+    pub last_timestamp: u64,
+    pub last_trade_price: Option<Price>,
+    /// Resting bids, best level first and front of the queue first within a level.
+    pub bids: Vec<Order>,
+    /// Resting asks, best level first and front of the queue first within a level.
+    pub asks: Vec<Order>,
+    /// Stop orders waiting for their trigger, in the order they would fire.
+    pub stops: Vec<Order>,
+    /// Orders collected by a running auction, in arrival order, or `None` outside one.
+    pub auction: Option<Vec<Order>>,
+}
+
+impl OrderBook {
+    /// Captures the book's settings and open orders. The listener is not part of the snapshot.
+    pub fn snapshot(&self) -> BookSnapshot {
+        let resting = |side: &BookSide| side.iter_best_first().flat_map(|(_, level)| level.iter().cloned()).collect();
+
+        BookSnapshot {
+            tick_size: self.tick_size,
+            strict_ticks: self.strict_ticks,
+            stp_policy: self.stp_policy,
+            match_policy: self.match_policy,
+            post_only_policy: self.post_only_policy,
+            price_band: self.price_band,
+            max_levels_per_side: self.max_levels,
+            level_cap_policy: self.level_cap_policy,
+            strict_timestamps: self.strict_timestamps,
+            now: self.now,
+            last_seq: self.last_seq,
+            last_timestamp: self.last_timestamp,
+            last_trade_price: self.last_trade_price,
+            bids: resting(&self.bids),
+            asks: resting(&self.asks),
+            stops: self.stops.orders().cloned().collect(),
+            auction: self.auction.clone(),
+        }
+    }
+
+    /// Rebuilds a book from [`OrderBook::snapshot`], with the default no-op listener.
+    ///
+    /// The rebuilt book is checked with [`OrderBook::validate`], so a snapshot that is crossed,
+    /// holds an id twice or an order with nothing visible is refused rather than restored.
+    pub fn from_snapshot(snapshot: BookSnapshot) -> Result<Self, InvariantViolation> {
+        let mut book = OrderBook::with_tick_size(snapshot.tick_size)
+            .with_stp_policy(snapshot.stp_policy)
+            .with_match_policy(snapshot.match_policy)
+            .with_post_only_policy(snapshot.post_only_policy);
+        book.strict_ticks = snapshot.strict_ticks;
+        book.price_band = snapshot.price_band;
+        book.max_levels = snapshot.max_levels_per_side;
+        book.level_cap_policy = snapshot.level_cap_policy;
+        book.strict_timestamps = snapshot.strict_timestamps;
+        book.now = snapshot.now;
+        book.last_seq = snapshot.last_seq;
+        book.last_timestamp = snapshot.last_timestamp;
+        book.auction = snapshot.auction;
+        book.last_trade_price = snapshot.last_trade_price;
+
+        for order in snapshot.bids {
+            book.bids.insert(order);
+        }
+        for order in snapshot.asks {
+            book.asks.insert(order);
+        }
+        for order in snapshot.stops {
+            book.stops.insert(order);
+        }
+
+        let open = [&book.bids, &book.asks].into_iter().flat_map(|side| side.levels().flat_map(|(_, level)| level));
+        let expiries = open.chain(book.stops.orders()).filter_map(|order| Some(Reverse((order.expires_at?, order.id))));
+        book.expiries = expiries.collect();
+        book.reset_accounting();
+        book.validate()?;
+        Ok(book)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for OrderBook {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for OrderBook {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = BookSnapshot::deserialize(deserializer)?;
+        OrderBook::from_snapshot(snapshot).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,134 @@
+use super::*;
+use std::collections::HashMap;
+
+/// Where an order is in its life, as seen by [`OrderBook::order_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderState {
+    /// On the book, possibly partially filled.
+    Resting,
+    /// A stop or stop-limit waiting for its trigger.
+    PendingStop,
+    /// Collected by a running auction, waiting for the uncross.
+    InAuction,
+    /// Completely filled; only reported while the book retains filled orders.
+    Filled,
+}
+
+/// The state of one order, from [`OrderBook::order_status`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderStatus {
+    pub order_id: u64,
+    pub side: Side,
+    /// Limit price; meaningless for market and stop orders, as on [`Order::price`].
+    pub price: Price,
+    /// See [`Order::seq`] and [`Order::timestamp`].
+    pub seq: u64,
+    pub timestamp: u64,
+    /// See [`Order::original_quantity`].
+    pub original_quantity: u64,
+    /// Open quantity, including any hidden iceberg reserve.
+    pub remaining_quantity: u64,
+    pub filled_quantity: u64,
+    /// The part of `filled_quantity` taken as the incoming order, see [`Order::taker_quantity`].
+    pub taker_quantity: u64,
+    /// The part of `filled_quantity` filled while resting on the book.
+    pub maker_quantity: u64,
+    /// Number of orders ahead of this one in its level's queue, 0 at the front. `None`
+    /// unless the order is resting.
+    pub queue_position: Option<usize>,
+    pub state: OrderState,
+}
+
+impl OrderStatus {
+    fn new(order: &Order, state: OrderState, queue_position: Option<usize>) -> Self {
+        Self {
+            order_id: order.id,
+            side: order.side,
+            price: order.price,
+            seq: order.seq,
+            timestamp: order.timestamp,
+            original_quantity: order.original_quantity,
+            remaining_quantity: order.remaining(),
+            filled_quantity: order.original_quantity - order.remaining(),
+            taker_quantity: order.taker_quantity,
+            maker_quantity: order.original_quantity - order.remaining() - order.taker_quantity,
+            queue_position,
+            state,
+        }
+    }
+}
+
+impl OrderBook {
+    /// The state of the open order with `id`, or of a recently filled one if the book retains
+    /// them (see [`OrderBook::retain_filled_orders`]). `None` means the book knows of no such
+    /// order: never accepted, cancelled, expired, or filled too long ago.
+    ///
+    /// Finding a resting order is a hash lookup; its queue position costs a walk of its level.
+    pub fn order_status(&self, id: u64) -> Option<OrderStatus> {
+        for side in [&self.bids, &self.asks] {
+            if let Some((position, order)) = side.queue_position(id) {
+                return Some(OrderStatus::new(order, OrderState::Resting, Some(position)));
+            }
+        }
+        if let Some(order) = self.stops.get(id) {
+            return Some(OrderStatus::new(order, OrderState::PendingStop, None));
+        }
+        if let Some(order) = self.auction.iter().flatten().find(|order| order.id == id) {
+            return Some(OrderStatus::new(order, OrderState::InAuction, None));
+        }
+        self.recent_fills.get(id).cloned()
+    }
+
+    /// Keeps the status of the last `capacity` completely filled orders for
+    /// [`OrderBook::order_status`], oldest forgotten first. Off (zero) by default.
+    pub fn retain_filled_orders(&mut self, capacity: usize) {
+        self.recent_fills.set_capacity(capacity);
+    }
+}
+
+/// Bounded history of filled orders, see [`OrderBook::retain_filled_orders`].
+#[derive(Debug, Default)]
+pub(crate) struct RecentFills {
+    capacity: usize,
+    /// Status by id, tagged with the sequence number it was recorded under.
+    statuses: HashMap<u64, (u64, OrderStatus)>,
+    /// `(sequence, id)` in the order they were recorded. An id reused by a later order that
+    /// also filled only loses its status when its newest entry is evicted.
+    order: VecDeque<(u64, u64)>,
+    next_sequence: u64,
+}
+
+impl RecentFills {
+    fn get(&self, id: u64) -> Option<&OrderStatus> {
+        self.statuses.get(&id).map(|(_, status)| status)
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Remembers `order`, whose whole quantity has just traded.
+    pub(crate) fn push(&mut self, order: &Order) {
+        if self.capacity == 0 {
+            return;
+        }
+        let status = OrderStatus::new(order, OrderState::Filled, None);
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.order.push_back((sequence, status.order_id));
+        self.statuses.insert(status.order_id, (sequence, status));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.order.len() > self.capacity {
+            let Some((sequence, id)) = self.order.pop_front() else {
+                return;
+            };
+            if self.statuses.get(&id).is_some_and(|&(latest, _)| latest == sequence) {
+                self.statuses.remove(&id);
+            }
+        }
+    }
+}
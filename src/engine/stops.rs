@@ -0,0 +1,94 @@
+use super::*;
+use std::collections::HashMap;
+
+/// Pending stop and stop-limit orders, waiting for the last trade price to reach their trigger.
+///
+/// Stops are kept off the visible book. Buy stops fire when the last trade is at or above
+/// their trigger, sell stops when it is at or below it.
+#[derive(Debug, Default)]
+pub(crate) struct StopBook {
+    buy_stops: BTreeMap<Price, VecDeque<Order>>,
+    sell_stops: BTreeMap<Price, VecDeque<Order>>,
+    index: HashMap<u64, (Side, Price)>,
+}
+
+impl StopBook {
+    pub(crate) fn insert(&mut self, order: Order) {
+        let Some(trigger) = order.trigger() else {
+            return;
+        };
+
+        let stops = match order.side {
+            Side::Buy => &mut self.buy_stops,
+            Side::Sell => &mut self.sell_stops,
+        };
+        self.index.insert(order.id, (order.side, trigger));
+        stops.entry(trigger).or_default().push_back(order);
+    }
+
+    pub(crate) fn contains(&self, id: u64) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    pub(crate) fn get(&self, id: u64) -> Option<&Order> {
+        let &(side, trigger) = self.index.get(&id)?;
+        let stops = match side {
+            Side::Buy => &self.buy_stops,
+            Side::Sell => &self.sell_stops,
+        };
+        stops.get(&trigger)?.iter().find(|order| order.id == id)
+    }
+
+    pub(crate) fn remove(&mut self, id: u64) -> Option<Order> {
+        let (side, trigger) = self.index.remove(&id)?;
+        let stops = match side {
+            Side::Buy => &mut self.buy_stops,
+            Side::Sell => &mut self.sell_stops,
+        };
+
+        let queue = stops.get_mut(&trigger)?;
+        let position = queue.iter().position(|order| order.id == id)?;
+        let order = queue.remove(position);
+        if queue.is_empty() {
+            stops.remove(&trigger);
+        }
+        order
+    }
+
+    /// Every pending stop: buy stops then sell stops, each by trigger price and then arrival.
+    pub(crate) fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.buy_stops.values().chain(self.sell_stops.values()).flatten()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Removes and returns every stop whose trigger `last_trade` has reached, already converted
+    /// into the order it becomes once active.
+    ///
+    /// Buy stops come first, lowest trigger first, then sell stops, highest trigger first;
+    /// stops sharing a trigger keep their arrival order.
+    pub(crate) fn take_triggered(&mut self, last_trade: Price) -> Vec<Order> {
+        let mut triggered = Vec::new();
+
+        while let Some(entry) = self.buy_stops.first_entry() {
+            if *entry.key() > last_trade {
+                break;
+            }
+            triggered.extend(entry.remove());
+        }
+        while let Some(entry) = self.sell_stops.last_entry() {
+            if *entry.key() < last_trade {
+                break;
+            }
+            triggered.extend(entry.remove());
+        }
+
+        for order in &mut triggered {
+            self.index.remove(&order.id);
+            order.activate();
+        }
+        triggered
+    }
+}
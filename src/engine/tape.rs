@@ -0,0 +1,137 @@
+use super::*;
+use std::collections::vec_deque;
+
+/// One time bucket of trades, as returned by [`Tape::bars`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candle {
+    /// First timestamp of the bucket: a multiple of the bucket size.
+    pub start: u64,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    pub volume: u64,
+    pub trades: usize,
+}
+
+/// Running record of every execution on a book.
+///
+/// The totals (volume, VWAP, high, low) cover every trade ever recorded and are updated as
+/// each trade arrives. Only the last `capacity` trades themselves are kept, oldest dropped
+/// first, so the window and bar queries only see those.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tape {
+    recent: VecDeque<Trade>,
+    capacity: usize,
+    trade_count: u64,
+    total_volume: u64,
+    /// Sum of price (in ticks) times quantity, for VWAP.
+    notional: i128,
+    last_price: Option<Price>,
+    high: Option<Price>,
+    low: Option<Price>,
+}
+
+impl Tape {
+    /// A tape that keeps the last `capacity` trades.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            recent: VecDeque::with_capacity(capacity),
+            capacity,
+            trade_count: 0,
+            total_volume: 0,
+            notional: 0,
+            last_price: None,
+            high: None,
+            low: None,
+        }
+    }
+
+    pub fn record(&mut self, trade: &Trade) {
+        self.trade_count += 1;
+        self.total_volume += trade.quantity;
+        self.notional += trade.price.0 as i128 * trade.quantity as i128;
+        self.last_price = Some(trade.price);
+        self.high = self.high.max(Some(trade.price));
+        self.low = Some(self.low.map_or(trade.price, |low| low.min(trade.price)));
+
+        if self.capacity == 0 {
+            return;
+        }
+        if self.recent.len() == self.capacity {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(trade.clone());
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of trades ever recorded, including ones no longer kept.
+    pub fn trade_count(&self) -> u64 {
+        self.trade_count
+    }
+
+    pub fn last_price(&self) -> Option<Price> {
+        self.last_price
+    }
+
+    pub fn total_volume(&self) -> u64 {
+        self.total_volume
+    }
+
+    /// Sum of price times quantity over every trade, in ticks.
+    pub fn notional(&self) -> i128 {
+        self.notional
+    }
+
+    /// Volume-weighted average price in ticks (fractional, so not a [`Price`]), or `None`
+    /// before the first trade.
+    pub fn vwap(&self) -> Option<f64> {
+        (self.total_volume > 0).then(|| self.notional as f64 / self.total_volume as f64)
+    }
+
+    pub fn high(&self) -> Option<Price> {
+        self.high
+    }
+
+    pub fn low(&self) -> Option<Price> {
+        self.low
+    }
+
+    /// The trades still kept, oldest first.
+    pub fn trades(&self) -> vec_deque::Iter<'_, Trade> {
+        self.recent.iter()
+    }
+
+    /// The last `n_last` trades still kept (fewer if fewer are), oldest first.
+    pub fn trades_in_window(&self, n_last: usize) -> vec_deque::Iter<'_, Trade> {
+        self.recent.range(self.recent.len().saturating_sub(n_last)..)
+    }
+
+    /// OHLCV bars over the kept trades, bucketed by trade timestamp into `bucket_size`-wide
+    /// buckets, earliest first. Buckets without trades are left out.
+    pub fn bars(&self, bucket_size: u64) -> Vec<Candle> {
+        let bucket_size = bucket_size.max(1);
+        let mut bars: BTreeMap<u64, Candle> = BTreeMap::new();
+        for trade in &self.recent {
+            let start = trade.timestamp - trade.timestamp % bucket_size;
+            let candle = bars.entry(start).or_insert(Candle {
+                start,
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                close: trade.price,
+                volume: 0,
+                trades: 0,
+            });
+            candle.high = candle.high.max(trade.price);
+            candle.low = candle.low.min(trade.price);
+            candle.close = trade.price;
+            candle.volume += trade.quantity;
+            candle.trades += 1;
+        }
+        bars.into_values().collect()
+    }
+}
@@ -0,0 +1,2061 @@
+use super::*;
+use super::price_level::LevelQueue;
+
+/// Price in ticks at the default tick size.
+fn px(value: f64) -> Price {
+    Price::from_f64(value, DEFAULT_TICK_SIZE)
+}
+
+#[test]
+fn test_simple_match() {
+    let mut book = OrderBook::new();
+    // Sell 100 @ 150
+    book.add_order(Order::limit(1, Side::Sell, px(150.0), 100)).unwrap();
+
+    // Buy 50 @ 150 (Should match)
+    book.add_order(Order::limit(2, Side::Buy, px(150.0), 50)).unwrap();
+
+    // Verify state: The Sell order should have 50 left
+    let best_ask = book.asks().levels().next().unwrap().1.front().unwrap();
+    assert_eq!(best_ask.quantity, 50);
+}
+
+#[test]
+fn test_price_priority() {
+    let mut book = OrderBook::new();
+    // Sell 100 @ 150
+    book.add_order(Order::limit(1, Side::Sell, px(150.0), 100)).unwrap();
+    // Sell 100 @ 140 (Better price!)
+    book.add_order(Order::limit(2, Side::Sell, px(140.0), 100)).unwrap();
+
+    // Buy 100 @ 150. Should match the 140 sell first because it's cheaper.
+    book.add_order(Order::limit(3, Side::Buy, px(150.0), 100)).unwrap();
+
+    // The 140 ask should be gone. The 150 ask should remain.
+    assert!(book.asks().level(px(140.0)).is_none());
+    assert!(book.asks().level(px(150.0)).is_some());
+}
+
+#[test]
+fn test_level_histogram() {
+    let mut book = OrderBook::new();
+    assert!(book.level_histogram(Side::Sell).is_empty());
+
+    // Three asks @ 150, one ask @ 151, one bid @ 140
+    book.add_order(Order::limit(1, Side::Sell, px(150.0), 10)).unwrap();
+    book.add_order(Order::limit(2, Side::Sell, px(150.0), 10)).unwrap();
+    book.add_order(Order::limit(3, Side::Sell, px(150.0), 10)).unwrap();
+    book.add_order(Order::limit(4, Side::Sell, px(151.0), 10)).unwrap();
+    book.add_order(Order::limit(5, Side::Buy, px(140.0), 10)).unwrap();
+
+    assert_eq!(book.level_histogram(Side::Sell), BTreeMap::from([(1, 1), (3, 1)]));
+    assert_eq!(book.level_histogram(Side::Buy), BTreeMap::from([(1, 1)]));
+
+    // Buy 15 @ 150 consumes the first ask and half of the second
+    book.add_order(Order::limit(6, Side::Buy, px(150.0), 15)).unwrap();
+    assert_eq!(book.level_histogram(Side::Sell), BTreeMap::from([(1, 1), (2, 1)]));
+
+    // Buy 25 @ 151 clears the 150 level and takes the 151 ask
+    book.add_order(Order::limit(7, Side::Buy, px(151.0), 25)).unwrap();
+    assert!(book.level_histogram(Side::Sell).is_empty());
+    assert_eq!(book.level_histogram(Side::Buy), BTreeMap::from([(1, 1)]));
+}
+
+#[test]
+fn test_level_queue_metadata_through_churn() {
+    // Caller timestamps that say nothing about when the book took the orders
+    let mut book = OrderBook::new();
+    book.advance_time(10);
+    book.add_order(Order::limit(1, Side::Sell, px(150.0), 10).with_timestamp(500)).unwrap();
+    book.add_order(Order::limit(2, Side::Sell, px(150.0), 10)).unwrap();
+    book.advance_time(20);
+    book.add_order(Order::limit(3, Side::Sell, px(150.0), 10).with_timestamp(7)).unwrap();
+    let ends = |book: &OrderBook| {
+        let level = book.asks().level(px(150.0)).unwrap();
+        (level.order_count(), level.oldest_priority_seq(), level.newest_priority_seq(), level.age_of_front(100))
+    };
+    assert_eq!(ends(&book), (3, Some(1), Some(3), Some(90)));
+
+    // Filling the front order moves the front on to the next one, accepted at 10 as well
+    book.advance_time(40);
+    book.add_order(Order::limit(4, Side::Buy, px(150.0), 10)).unwrap();
+    assert_eq!(ends(&book), (2, Some(2), Some(3), Some(90)));
+
+    // Cancelling the back order, then adding one, moves the back
+    book.cancel_order(3).unwrap();
+    assert_eq!(ends(&book), (1, Some(2), Some(2), Some(90)));
+    book.advance_time(50);
+    book.add_order(Order::limit(5, Side::Sell, px(150.0), 10).with_timestamp(1)).unwrap();
+    assert_eq!(ends(&book), (2, Some(2), Some(5), Some(90)));
+
+    // A partial fill keeps the front order's place and age
+    book.add_order(Order::limit(6, Side::Buy, px(150.0), 4)).unwrap();
+    assert_eq!(ends(&book), (2, Some(2), Some(5), Some(90)));
+    let level = book.asks().level(px(150.0)).unwrap();
+    assert_eq!(level.age_of_front(5), Some(0));
+    assert_eq!(level.back().unwrap().id, 5);
+
+    // An iceberg showing its next slice goes to the back with its old seq
+    book.cancel_order(2).unwrap();
+    book.cancel_order(5).unwrap();
+    book.advance_time(70);
+    book.add_order(Order::limit(7, Side::Sell, px(150.0), 20).with_display_quantity(5)).unwrap();
+    book.advance_time(80);
+    book.add_order(Order::limit(8, Side::Sell, px(150.0), 10).with_timestamp(99)).unwrap();
+    book.add_order(Order::limit(9, Side::Buy, px(150.0), 5)).unwrap();
+    assert_eq!(ends(&book), (2, Some(8), Some(7), Some(20)));
+
+    // The level is gone once its last order leaves
+    book.add_order(Order::limit(10, Side::Buy, px(150.0), 25)).unwrap();
+    assert!(book.asks().level(px(150.0)).is_none());
+}
+
+#[test]
+fn test_fixture_round_trip() {
+    let fixture = "ASK 101.0x200(2) | 100.5x50 ; BID 100.0x300 | 99.5x100(3)";
+    let book = OrderBook::from_fixture(fixture).unwrap();
+
+    let level = book.asks().level(px(101.0)).unwrap();
+    assert_eq!(level.order_count(), 2);
+    assert!(level.iter().all(|order| order.quantity == 100));
+    let level = book.bids().level(px(99.5)).unwrap();
+    assert_eq!(level.iter().map(|order| order.id).collect::<Vec<_>>(), vec![5, 6, 7]);
+    assert_eq!(level.iter().map(|order| order.quantity).collect::<Vec<_>>(), vec![34, 33, 33]);
+
+    assert_eq!(book.to_fixture(), fixture);
+    assert_eq!(OrderBook::from_fixture(&book.to_fixture()).unwrap().to_fixture(), fixture);
+}
+
+#[test]
+fn test_fixture_after_partial_fill() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x30(3)").unwrap();
+    book.add_order(Order::limit(10, Side::Buy, px(100.0), 15)).unwrap();
+
+    // One order consumed, the next partially filled, the last untouched
+    assert_eq!(book.to_fixture(), "ASK 100.0x5 | 100.0x10");
+    assert_eq!(OrderBook::from_fixture("ASK 100.0x5 | 100.0x10").unwrap().to_fixture(), book.to_fixture());
+    assert_eq!(OrderBook::new().to_fixture(), "");
+}
+
+#[test]
+fn test_fixture_errors() {
+    let err = OrderBook::from_fixture("ASK 101.0x200 | 100.5y50").unwrap_err();
+    assert_eq!(err.position, 16);
+
+    let err = OrderBook::from_fixture("ASK 101.0x200 ; BUY 100.0x10").unwrap_err();
+    assert_eq!(err.position, 16);
+
+    let err = OrderBook::from_fixture("BID 100.0x2(3)").unwrap_err();
+    assert_eq!(err.position, 10);
+
+    let err = OrderBook::from_fixture("BID 100.0x0").unwrap_err();
+    assert_eq!(err.position, 10);
+
+    let err = OrderBook::from_fixture("ASK 100.0x10 ; BID 100.0x10").unwrap_err();
+    assert!(err.message.contains("crossed"));
+}
+
+#[test]
+fn test_report_gives_resting_status_and_remaining_quantity() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x10").unwrap();
+
+    let report = book.add_order_with_report(Order::limit(10, Side::Buy, px(101.0), 25)).unwrap();
+    assert_eq!((report.trades.len(), report.resting, report.remaining_quantity), (1, true, 15));
+    let report = book.add_order_with_report(Order::limit(11, Side::Sell, px(101.0), 15)).unwrap();
+    assert_eq!((report.resting, report.remaining_quantity), (false, 0));
+    let report = book.add_order_with_report(Order::stop(12, Side::Buy, px(105.0), 7)).unwrap();
+    assert_eq!((report.resting, report.remaining_quantity), (false, 7));
+}
+
+#[test]
+fn test_add_order_returns_trades() {
+    let mut book = OrderBook::new();
+    // Resting orders never trade
+    assert!(book.add_order(Order::limit(1, Side::Sell, px(100.0), 10).with_timestamp(1)).unwrap().is_empty());
+    assert!(book.add_order(Order::limit(2, Side::Sell, px(100.0), 20).with_timestamp(2)).unwrap().is_empty());
+    assert!(book.add_order(Order::limit(3, Side::Sell, px(101.0), 30).with_timestamp(3)).unwrap().is_empty());
+
+    // Buy 45 @ 101 sweeps both levels: one trade per resting order consumed
+    let trades = book.add_order(Order::limit(4, Side::Buy, px(101.0), 45).with_timestamp(4)).unwrap();
+    assert_eq!(trades, vec![
+        Trade { maker_id: 1, taker_id: 4, price: px(100.0), quantity: 10, timestamp: 4, maker_seq: 1, taker_seq: 4 },
+        Trade { maker_id: 2, taker_id: 4, price: px(100.0), quantity: 20, timestamp: 4, maker_seq: 2, taker_seq: 4 },
+        Trade { maker_id: 3, taker_id: 4, price: px(101.0), quantity: 15, timestamp: 4, maker_seq: 3, taker_seq: 4 },
+    ]);
+}
+
+#[test]
+fn test_cancel_only_order_at_level() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Buy, px(100.0), 10)).unwrap();
+    book.add_order(Order::limit(2, Side::Buy, px(99.0), 10)).unwrap();
+
+    let cancelled = book.cancel_order(1).unwrap();
+    assert_eq!((cancelled.id, cancelled.quantity), (1, 10));
+    assert!(book.bids().level(px(100.0)).is_none());
+    assert_eq!(book.bids().best().unwrap().0, px(99.0));
+}
+
+#[test]
+fn test_cancel_from_middle_of_queue() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x30(3)").unwrap();
+    assert_eq!(book.cancel_order(2).unwrap().id, 2);
+
+    let level = book.asks().level(px(100.0)).unwrap();
+    assert_eq!(level.iter().map(|order| order.id).collect::<Vec<_>>(), vec![1, 3]);
+
+    // The remaining orders still fill in their original sequence
+    let trades = book.add_order(Order::limit(4, Side::Buy, px(100.0), 20)).unwrap();
+    assert_eq!(trades.iter().map(|trade| trade.maker_id).collect::<Vec<_>>(), vec![1, 3]);
+}
+
+#[test]
+fn test_cancel_after_partial_fill() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 10)).unwrap();
+    book.add_order(Order::limit(2, Side::Buy, px(100.0), 4)).unwrap();
+
+    let cancelled = book.cancel_order(1).unwrap();
+    assert_eq!(cancelled.quantity, 6);
+    assert!(book.asks().is_empty());
+    assert_eq!(book.cancel_order(1).unwrap_err(), CancelError::NotFound(1));
+}
+
+#[test]
+fn test_cancel_unknown_or_filled_order() {
+    let mut book = OrderBook::new();
+    assert_eq!(book.cancel_order(7).unwrap_err(), CancelError::NotFound(7));
+
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 10)).unwrap();
+    book.add_order(Order::limit(2, Side::Buy, px(100.0), 10)).unwrap();
+    assert_eq!(book.cancel_order(1).unwrap_err(), CancelError::NotFound(1));
+    // The fully-filled taker never rested either
+    assert_eq!(book.cancel_order(2).unwrap_err(), CancelError::NotFound(2));
+}
+
+fn queue_ids(book: &OrderBook, side: Side, price: Price) -> Vec<u64> {
+    let side = match side {
+        Side::Buy => book.bids(),
+        Side::Sell => book.asks(),
+    };
+    side.level(price).unwrap().iter().map(|order| order.id).collect()
+}
+
+#[test]
+fn test_modify_size_down_keeps_priority() {
+    let mut book = OrderBook::from_fixture("BID 100.0x30(3)").unwrap();
+
+    assert!(book.modify_order(1, px(100.0), 4).unwrap().is_empty());
+    assert_eq!(queue_ids(&book, Side::Buy, px(100.0)), vec![1, 2, 3]);
+    assert_eq!(book.bids().get(1).unwrap().quantity, 4);
+    assert_eq!(book.bids().total_quantity(), 24);
+}
+
+#[test]
+fn test_modify_price_or_size_up_loses_priority() {
+    let mut book = OrderBook::from_fixture("BID 100.0x30(3)").unwrap();
+
+    // Size up at the same price goes to the back of the queue
+    book.modify_order(1, px(100.0), 20).unwrap();
+    assert_eq!(queue_ids(&book, Side::Buy, px(100.0)), vec![2, 3, 1]);
+
+    // Moving away and back again also loses priority
+    book.modify_order(2, px(99.0), 10).unwrap();
+    assert_eq!(queue_ids(&book, Side::Buy, px(99.0)), vec![2]);
+    book.modify_order(2, px(100.0), 10).unwrap();
+    assert_eq!(queue_ids(&book, Side::Buy, px(100.0)), vec![3, 1, 2]);
+}
+
+#[test]
+fn test_modify_crossing_price_trades() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x10 ; BID 100.0x15").unwrap();
+
+    // Improve the bid through the ask: 10 trade at the ask's price, 5 rest at 101
+    let trades = book.modify_order(2, px(101.0), 15).unwrap();
+    // The bid moved, so it trades on a fresh seq
+    let (maker_seq, taker_seq) = (1, 3);
+    let trade = Trade { maker_id: 1, taker_id: 2, price: px(101.0), quantity: 10, timestamp: 0, maker_seq, taker_seq };
+    assert_eq!(trades, vec![trade]);
+    assert!(book.asks().is_empty());
+    assert_eq!(book.bids().best().unwrap().0, px(101.0));
+    assert_eq!(book.bids().get(2).unwrap().quantity, 5);
+}
+
+#[test]
+fn test_modify_errors() {
+    let mut book = OrderBook::from_fixture("BID 100.0x10").unwrap();
+    assert_eq!(book.modify_order(9, px(100.0), 5).unwrap_err(), ModifyError::NotFound(9));
+    assert_eq!(book.modify_order(1, px(100.0), 0).unwrap_err(), ModifyError::ZeroQuantity);
+    assert_eq!(book.bids().get(1).unwrap().quantity, 10);
+}
+
+#[test]
+fn test_market_order_sweeps_levels() {
+    let mut book = OrderBook::from_fixture("ASK 120.0x10 | 110.0x10 | 100.0x10").unwrap();
+
+    let result = book.add_market_order(10, Side::Buy, 25, 0).unwrap();
+    assert_eq!(result.unfilled, 0);
+    assert_eq!(result.trades.iter().map(|trade| trade.price).collect::<Vec<_>>(), vec![px(100.0), px(110.0), px(120.0)]);
+    assert_eq!(book.asks().total_quantity(), 5);
+}
+
+#[test]
+fn test_market_order_remainder_is_cancelled() {
+    let mut book = OrderBook::from_fixture("BID 100.0x10 | 99.0x5").unwrap();
+
+    let result = book.add_market_order(10, Side::Sell, 40, 0).unwrap();
+    assert_eq!(result.trades.len(), 2);
+    assert_eq!(result.unfilled, 25);
+    assert!(book.bids().is_empty());
+    // Nothing rested at a made-up price
+    assert!(book.asks().is_empty());
+
+    // Against an empty book nothing trades at all
+    let result = book.add_market_order(11, Side::Buy, 5, 0).unwrap();
+    assert_eq!(result, MarketOrderResult { trades: vec![], unfilled: 5 });
+}
+
+#[test]
+fn test_fok_fills_across_levels() {
+    let mut book = OrderBook::from_fixture("ASK 102.0x10 | 101.0x10 | 100.0x10").unwrap();
+
+    // 25 is only available by reaching into the 102 level
+    let trades = book.add_order(Order::limit(10, Side::Buy, px(102.0), 25).with_time_in_force(TimeInForce::Fok)).unwrap();
+    assert_eq!(trades.iter().map(|trade| trade.quantity).sum::<u64>(), 25);
+    assert_eq!(book.to_fixture(), "ASK 102.0x5");
+}
+
+#[test]
+fn test_fok_kill_leaves_book_untouched() {
+    let fixture = "ASK 102.0x10 | 101.0x10 | 100.0x10 ; BID 99.0x10";
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+
+    // Enough size exists overall, but not within the 101 limit
+    assert!(book.add_order(Order::limit(10, Side::Buy, px(101.0), 25).with_time_in_force(TimeInForce::Fok)).unwrap().is_empty());
+    assert_eq!(book.to_fixture(), fixture);
+    assert!(!book.bids().contains(10));
+}
+
+#[test]
+fn test_ioc_partial_fill_does_not_rest() {
+    let mut book = OrderBook::from_fixture("BID 100.0x10 | 99.0x10").unwrap();
+
+    let trades = book.add_order(Order::limit(10, Side::Sell, px(100.0), 25).with_time_in_force(TimeInForce::Ioc)).unwrap();
+    assert_eq!(trades.iter().map(|trade| trade.quantity).sum::<u64>(), 10);
+    assert!(book.asks().is_empty());
+    assert_eq!(book.to_fixture(), "BID 99.0x10");
+}
+
+#[test]
+fn test_queries_on_empty_book() {
+    let book = OrderBook::new();
+    assert_eq!(book.best_bid(), None);
+    assert_eq!(book.best_ask(), None);
+    assert_eq!(book.spread(), None);
+    assert_eq!(book.mid_price(), None);
+    assert_eq!(book.depth(5), DepthSnapshot::default());
+}
+
+#[test]
+fn test_queries_on_one_sided_book() {
+    let book = OrderBook::from_fixture("BID 100.0x30(2) | 99.0x5").unwrap();
+    assert_eq!(book.best_bid(), Some((px(100.0), 30)));
+    assert_eq!(book.best_ask(), None);
+    assert_eq!(book.spread(), None);
+    assert_eq!(book.mid_price(), None);
+    assert_eq!(book.depth(5).bids, vec![(px(100.0), 30, 2), (px(99.0), 5, 1)]);
+    assert!(book.depth(5).asks.is_empty());
+}
+
+#[test]
+fn test_queries_on_two_sided_book() {
+    let book = OrderBook::from_fixture("ASK 102.0x10 | 101.0x20(4) ; BID 100.0x30(2) | 99.0x5 | 98.0x1").unwrap();
+    assert_eq!(book.best_bid(), Some((px(100.0), 30)));
+    assert_eq!(book.best_ask(), Some((px(101.0), 20)));
+    assert_eq!(book.spread(), Some(Price(100)));
+    assert_eq!(book.mid_price(), Some(100.5));
+
+    let depth = book.depth(2);
+    assert_eq!(depth.bids, vec![(px(100.0), 30, 2), (px(99.0), 5, 1)]);
+    assert_eq!(depth.asks, vec![(px(101.0), 20, 4), (px(102.0), 10, 1)]);
+}
+
+#[test]
+fn test_prices_within_half_a_tick_share_a_level() {
+    let mut book = OrderBook::with_tick_size(0.01);
+    let a = book.price(100.1).unwrap();
+    let b = book.price(100.10000000001).unwrap();
+    let c = book.price(100.104).unwrap();
+    assert_eq!(a, Price(10010));
+    assert_eq!((a, a), (b, c));
+
+    book.add_order(Order::limit(1, Side::Buy, a, 10)).unwrap();
+    book.add_order(Order::limit(2, Side::Buy, b, 10)).unwrap();
+    book.add_order(Order::limit(3, Side::Buy, c, 10)).unwrap();
+    assert_eq!(book.depth(5).bids, vec![(Price(10010), 30, 3)]);
+    assert_eq!(book.best_bid().unwrap().0.to_f64(book.tick_size()), 100.1);
+}
+
+#[test]
+fn test_strict_ticks_reject_off_tick_prices() {
+    let book = OrderBook::with_tick_size(0.05).with_strict_ticks();
+    assert_eq!(book.price(100.05), Ok(Price(2001)));
+    // Float noise is still on the tick
+    assert_eq!(book.price(100.05000000001), Ok(Price(2001)));
+    assert_eq!(book.price(100.07), Err(PriceError::OffTick(100.07)));
+    assert!(matches!(book.price(f64::NAN), Err(PriceError::NotFinite(_))));
+
+    // Non-strict books round instead
+    let book = OrderBook::with_tick_size(0.05);
+    assert_eq!(book.price(100.07), Ok(Price(2001)));
+}
+
+
+#[test]
+fn test_fills_within_level_are_fifo() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x50(5)").unwrap();
+
+    let trades = book.add_order(Order::limit(10, Side::Buy, px(100.0), 45)).unwrap();
+    assert_eq!(trades.iter().map(|trade| trade.maker_id).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    assert_eq!(trades.iter().map(|trade| trade.quantity).collect::<Vec<_>>(), vec![10, 10, 10, 10, 5]);
+    assert_eq!(book.to_fixture(), "ASK 100.0x5");
+}
+
+#[test]
+fn test_sweep_across_levels_after_draining_each() {
+    let mut book = OrderBook::from_fixture("BID 100.0x20(2) | 99.0x20(2) | 98.0x10").unwrap();
+
+    // Sell 35 @ 99 drains 100 in FIFO order, then 99, and stops before 98
+    let trades = book.add_order(Order::limit(10, Side::Sell, px(99.0), 35)).unwrap();
+    let fills: Vec<_> = trades.iter().map(|trade| (trade.maker_id, trade.price, trade.quantity)).collect();
+    assert_eq!(fills, vec![(1, px(100.0), 10), (2, px(100.0), 10), (3, px(99.0), 10), (4, px(99.0), 5)]);
+    assert_eq!(book.to_fixture(), "BID 99.0x5 | 98.0x10");
+    assert!(book.asks().is_empty());
+}
+
+#[test]
+fn test_stop_cascade() {
+    let mut book = OrderBook::from_fixture("BID 99.0x10 | 98.0x10 | 97.0x10 | 96.0x10 ; ASK 101.0x10").unwrap();
+
+    // Sell stops at 99 and 97: each fires once the last price falls to its trigger
+    book.add_order(Order::stop(20, Side::Sell, px(99.0), 15)).unwrap();
+    book.add_order(Order::stop(21, Side::Sell, px(97.0), 10)).unwrap();
+    // A buy stop far above the market stays pending throughout
+    book.add_order(Order::stop(22, Side::Buy, px(105.0), 10)).unwrap();
+    assert!(book.is_stop_pending(20) && book.is_stop_pending(21));
+
+    // Selling 5 @ 99 prints at 99 and fires stop 20, which sweeps down to 98; that doesn't
+    // reach 97, so stop 21 waits
+    let trades = book.add_order(Order::limit(30, Side::Sell, px(99.0), 5)).unwrap();
+    let fills: Vec<_> = trades.iter().map(|trade| (trade.taker_id, trade.price, trade.quantity)).collect();
+    assert_eq!(fills, vec![(30, px(99.0), 5), (20, px(99.0), 5), (20, px(98.0), 10)]);
+    assert!(book.is_stop_pending(21));
+
+    // Another sell into 97 fires stop 21, whose fill at 96 cascades no further
+    let trades = book.add_order(Order::limit(31, Side::Sell, px(97.0), 5)).unwrap();
+    let fills: Vec<_> = trades.iter().map(|trade| (trade.taker_id, trade.price, trade.quantity)).collect();
+    assert_eq!(fills, vec![(31, px(97.0), 5), (21, px(97.0), 5), (21, px(96.0), 5)]);
+    assert_eq!(book.last_trade_price(), Some(px(96.0)));
+    assert!(!book.is_stop_pending(21));
+    assert!(book.is_stop_pending(22));
+    assert_eq!(book.to_fixture(), "ASK 101.0x10 ; BID 96.0x5");
+}
+
+#[test]
+fn test_stop_triggered_by_another_stop() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x10 | 102.0x10 | 103.0x10").unwrap();
+    book.add_order(Order::stop(20, Side::Buy, px(101.0), 10)).unwrap();
+    book.add_order(Order::stop(21, Side::Buy, px(102.0), 10)).unwrap();
+
+    // One lot at 101 fires stop 20, whose fill at 102 fires stop 21
+    let trades = book.add_order(Order::limit(30, Side::Buy, px(101.0), 1)).unwrap();
+    let takers: Vec<_> = trades.iter().map(|trade| trade.taker_id).collect();
+    assert_eq!(takers, vec![30, 20, 20, 21, 21]);
+    assert_eq!(book.last_trade_price(), Some(px(103.0)));
+    assert_eq!(book.to_fixture(), "ASK 103.0x9");
+}
+
+#[test]
+fn test_stop_limit_rests_when_not_fully_filled() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x5 | 101.0x5 | 103.0x10").unwrap();
+    book.add_order(Order::stop_limit(20, Side::Buy, px(100.0), px(101.0), 20)).unwrap();
+    assert!(book.best_bid().is_none());
+
+    // The print at 100 activates the stop-limit as a buy limit at 101
+    let trades = book.add_order(Order::limit(30, Side::Buy, px(100.0), 1)).unwrap();
+    assert_eq!(trades.iter().filter(|trade| trade.taker_id == 20).map(|trade| trade.quantity).sum::<u64>(), 9);
+
+    // 11 lots couldn't fill inside the limit and rest at 101
+    assert_eq!(book.best_bid(), Some((px(101.0), 11)));
+    assert_eq!(book.bids().get(20).unwrap().order_type, OrderType::Limit);
+    assert_eq!(book.best_ask(), Some((px(103.0), 10)));
+}
+
+#[test]
+fn test_cancel_pending_stop() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10").unwrap();
+    book.add_order(Order::stop(20, Side::Buy, px(100.0), 5)).unwrap();
+
+    assert_eq!(book.cancel_order(20).unwrap().id, 20);
+    assert!(!book.is_stop_pending(20));
+    assert_eq!(book.add_order(Order::limit(30, Side::Buy, px(100.0), 1)).unwrap().len(), 1);
+    assert_eq!(book.best_ask(), Some((px(100.0), 9)));
+}
+
+#[test]
+fn test_iceberg_shows_only_display_quantity() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 10_000).with_display_quantity(500)).unwrap();
+
+    assert_eq!(book.best_ask(), Some((px(100.0), 500)));
+    assert_eq!(book.depth(1).asks, vec![(px(100.0), 500, 1)]);
+    assert_eq!(book.asks().total_quantity(), 500);
+}
+
+#[test]
+fn test_iceberg_refresh_loses_priority() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 250).with_display_quantity(100)).unwrap();
+    book.add_order(Order::limit(2, Side::Sell, px(100.0), 50)).unwrap();
+
+    // 150 takes the iceberg's visible 100, then order 2 trades before the refreshed slice
+    let trades = book.add_order(Order::limit(3, Side::Buy, px(100.0), 150)).unwrap();
+    let fills: Vec<_> = trades.iter().map(|trade| (trade.maker_id, trade.quantity)).collect();
+    assert_eq!(fills, vec![(1, 100), (2, 50)]);
+    assert_eq!(queue_ids(&book, Side::Sell, px(100.0)), vec![1]);
+    assert_eq!(book.best_ask(), Some((px(100.0), 100)));
+
+    // Alone at the level, the iceberg keeps refreshing; the last slice is the 50 left over
+    let trades = book.add_order(Order::limit(4, Side::Buy, px(100.0), 200)).unwrap();
+    let fills: Vec<_> = trades.iter().map(|trade| (trade.maker_id, trade.quantity)).collect();
+    assert_eq!(fills, vec![(1, 100), (1, 50)]);
+    assert!(book.asks().is_empty());
+    assert!(!book.asks().contains(1));
+    assert_eq!(book.best_bid(), Some((px(100.0), 50)));
+}
+
+#[test]
+fn test_iceberg_refresh_goes_behind_later_arrivals() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Buy, px(100.0), 300).with_display_quantity(100)).unwrap();
+    book.add_order(Order::limit(2, Side::Buy, px(100.0), 100)).unwrap();
+
+    book.add_order(Order::limit(3, Side::Sell, px(100.0), 100)).unwrap();
+    assert_eq!(queue_ids(&book, Side::Buy, px(100.0)), vec![2, 1]);
+    assert_eq!(book.best_bid(), Some((px(100.0), 200)));
+}
+
+#[test]
+fn test_cancel_iceberg_returns_hidden_quantity() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 1_000).with_display_quantity(200)).unwrap();
+    book.add_order(Order::limit(2, Side::Buy, px(100.0), 150)).unwrap();
+
+    let cancelled = book.cancel_order(1).unwrap();
+    assert_eq!((cancelled.quantity, cancelled.hidden_quantity), (850, 0));
+    assert_eq!(book.asks().total_quantity(), 0);
+}
+
+#[test]
+fn test_fok_counts_hidden_quantity() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 1_000).with_display_quantity(100)).unwrap();
+
+    let trades = book.add_order(Order::limit(2, Side::Buy, px(100.0), 600).with_time_in_force(TimeInForce::Fok)).unwrap();
+    assert_eq!(trades.iter().map(|trade| trade.quantity).sum::<u64>(), 600);
+    assert_eq!(book.asks().get(1).unwrap().remaining(), 400);
+}
+
+/// Ask book at 100 with participant 7's order sitting between two other participants' orders.
+fn stp_book(policy: StpPolicy) -> OrderBook {
+    let mut book = OrderBook::new().with_stp_policy(policy);
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 10).with_participant_id(1)).unwrap();
+    book.add_order(Order::limit(2, Side::Sell, px(100.0), 10).with_participant_id(7)).unwrap();
+    book.add_order(Order::limit(3, Side::Sell, px(100.0), 10).with_participant_id(2)).unwrap();
+    book
+}
+
+#[test]
+fn test_stp_cancel_oldest_continues_behind_own_quote() {
+    let mut book = stp_book(StpPolicy::CancelOldest);
+
+    let report = book.add_order_with_report(Order::limit(4, Side::Buy, px(100.0), 20).with_participant_id(7)).unwrap();
+    let makers: Vec<_> = report.trades.iter().map(|trade| trade.maker_id).collect();
+    assert_eq!(makers, vec![1, 3]);
+    assert_eq!(
+        report.self_trade_cancels,
+        vec![SelfTradeCancel { order_id: 2, participant_id: 7, quantity: 10, resting: true }]
+    );
+    assert!(book.asks().is_empty());
+    assert!(book.best_bid().is_none());
+}
+
+#[test]
+fn test_stp_cancel_newest_keeps_resting_quote() {
+    let mut book = stp_book(StpPolicy::CancelNewest);
+
+    let report = book.add_order_with_report(Order::limit(4, Side::Buy, px(100.0), 20).with_participant_id(7)).unwrap();
+    assert_eq!(report.trades.len(), 1);
+    assert_eq!(
+        report.self_trade_cancels,
+        vec![SelfTradeCancel { order_id: 4, participant_id: 7, quantity: 10, resting: false }]
+    );
+    assert_eq!(queue_ids(&book, Side::Sell, px(100.0)), vec![2, 3]);
+    assert!(book.best_bid().is_none());
+}
+
+#[test]
+fn test_stp_cancel_both() {
+    let mut book = stp_book(StpPolicy::CancelBoth);
+
+    let report = book.add_order_with_report(Order::limit(4, Side::Buy, px(100.0), 20).with_participant_id(7)).unwrap();
+    assert_eq!(report.trades.len(), 1);
+    let cancelled: Vec<_> = report.self_trade_cancels.iter().map(|cancel| (cancel.order_id, cancel.resting)).collect();
+    assert_eq!(cancelled, vec![(2, true), (4, false)]);
+    assert_eq!(queue_ids(&book, Side::Sell, px(100.0)), vec![3]);
+}
+
+#[test]
+fn test_stp_none_allows_self_trade() {
+    let mut book = stp_book(StpPolicy::None);
+
+    let report = book.add_order_with_report(Order::limit(4, Side::Buy, px(100.0), 20).with_participant_id(7)).unwrap();
+    let makers: Vec<_> = report.trades.iter().map(|trade| trade.maker_id).collect();
+    assert_eq!(makers, vec![1, 2]);
+    assert!(report.self_trade_cancels.is_empty());
+}
+
+#[test]
+fn test_fok_stops_counting_at_own_quote() {
+    let mut book = stp_book(StpPolicy::CancelNewest);
+
+    // Only order 1 trades before the own quote would cancel the taker, so 20 can't be filled
+    let order = Order::limit(4, Side::Buy, px(100.0), 20).with_participant_id(7).with_time_in_force(TimeInForce::Fok);
+    assert!(book.add_order(order).unwrap().is_empty());
+    assert_eq!(book.asks().total_quantity(), 30);
+
+    // Under CancelOldest the own quote is skipped and the rest is enough
+    let mut book = stp_book(StpPolicy::CancelOldest);
+    let order = Order::limit(4, Side::Buy, px(100.0), 20).with_participant_id(7).with_time_in_force(TimeInForce::Fok);
+    assert_eq!(book.add_order(order).unwrap().len(), 2);
+}
+
+fn recorded_book(fixture: &str) -> (OrderBook, RecordingListener) {
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    let recorder = RecordingListener::new();
+    book.set_listener(Box::new(recorder.clone()));
+    (book, recorder)
+}
+
+#[test]
+fn test_listener_accept_trades_then_rest() {
+    let (mut book, recorder) = recorded_book("ASK 101.0x10 | 100.0x10");
+
+    let order = Order::limit(10, Side::Buy, px(101.0), 25);
+    let trades = book.add_order(order.clone()).unwrap();
+
+    // Accepted third, after the fixture's two asks
+    let order = Order { seq: 3, ..order };
+    let mut rested = order.clone();
+    (rested.quantity, rested.taker_quantity) = (5, 20);
+    assert_eq!(
+        recorder.events(),
+        vec![
+            BookEvent::OrderAccepted(order),
+            BookEvent::Trade(trades[0].clone()),
+            BookEvent::LevelRemoved(Side::Sell, px(100.0)),
+            BookEvent::Trade(trades[1].clone()),
+            BookEvent::LevelRemoved(Side::Sell, px(101.0)),
+            BookEvent::OrderRested(rested),
+        ]
+    );
+}
+
+#[test]
+fn test_listener_ioc_remainder_is_cancelled() {
+    let (mut book, recorder) = recorded_book("ASK 100.0x10(2)");
+
+    let order = Order::limit(10, Side::Buy, px(100.0), 15).with_time_in_force(TimeInForce::Ioc);
+    book.add_order(order).unwrap();
+
+    let events = recorder.take_events();
+    assert!(matches!(events[0], BookEvent::OrderAccepted(Order { id: 10, .. })));
+    assert!(matches!(events[1], BookEvent::Trade(Trade { maker_id: 1, quantity: 5, .. })));
+    assert!(matches!(events[2], BookEvent::Trade(Trade { maker_id: 2, quantity: 5, .. })));
+    assert_eq!(events[3], BookEvent::LevelRemoved(Side::Sell, px(100.0)));
+    assert!(matches!(events[4], BookEvent::OrderCancelled(Order { id: 10, quantity: 5, .. })));
+    assert_eq!(events.len(), 5);
+
+    // A killed FOK order is accepted then cancelled with nothing in between
+    book.add_order(Order::limit(11, Side::Sell, px(200.0), 1).with_time_in_force(TimeInForce::Fok)).unwrap();
+    let events = recorder.take_events();
+    assert!(matches!(events[..], [BookEvent::OrderAccepted(_), BookEvent::OrderCancelled(Order { id: 11, .. })]));
+}
+
+#[test]
+fn test_listener_cancel_and_level_removed() {
+    let (mut book, recorder) = recorded_book("BID 99.0x10(2)");
+
+    book.cancel_order(1).unwrap();
+    assert!(matches!(recorder.take_events()[..], [BookEvent::OrderCancelled(Order { id: 1, .. })]));
+
+    book.cancel_order(2).unwrap();
+    let events = recorder.take_events();
+    assert!(matches!(events[0], BookEvent::OrderCancelled(Order { id: 2, quantity: 5, .. })));
+    assert_eq!(events[1], BookEvent::LevelRemoved(Side::Buy, px(99.0)));
+
+    assert!(book.cancel_order(2).is_err());
+    assert!(recorder.events().is_empty());
+}
+
+/// Partially filled orders across several levels on both sides, an iceberg mid-slice and a
+/// pending stop.
+fn checkpoint_book() -> OrderBook {
+    let mut book = OrderBook::from_fixture("ASK 101.0x30(3) | 100.5x20 | 100.0x10(2) ; BID 99.5x40(2) | 99.0x25").unwrap();
+    book.add_order(Order::limit(20, Side::Sell, px(100.0), 500).with_display_quantity(50)).unwrap();
+    book.add_order(Order::stop(21, Side::Buy, px(101.0), 5)).unwrap();
+    book.add_order(Order::limit(30, Side::Buy, px(100.0), 37)).unwrap();
+    book.add_order(Order::limit(31, Side::Sell, px(99.5), 25)).unwrap();
+    book
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let mut original = checkpoint_book();
+    let mut restored = OrderBook::from_snapshot(original.snapshot()).unwrap();
+
+    assert_eq!(restored.snapshot(), original.snapshot());
+    assert_eq!(restored.to_fixture(), original.to_fixture());
+    assert!(restored.is_stop_pending(21));
+
+    let sweep = Order::limit(40, Side::Buy, px(101.0), 200);
+    assert_eq!(restored.add_order(sweep.clone()).unwrap(), original.add_order(sweep).unwrap());
+    assert_eq!(restored.snapshot(), original.snapshot());
+}
+
+#[test]
+fn test_snapshot_refuses_broken_books() {
+    let snapshot = checkpoint_book().snapshot();
+
+    let mut crossed = snapshot.clone();
+    crossed.bids.insert(0, Order::limit(50, Side::Buy, px(102.0), 5));
+    assert!(matches!(OrderBook::from_snapshot(crossed.clone()), Err(InvariantViolation::Crossed { .. })));
+    #[cfg(feature = "serde")]
+    assert!(serde_json::from_str::<OrderBook>(&serde_json::to_string(&crossed).unwrap()).is_err());
+
+    // The second copy would take the first one's place in the id index
+    let mut duplicate = snapshot.clone();
+    duplicate.bids.push(snapshot.bids[0].clone());
+    assert!(matches!(OrderBook::from_snapshot(duplicate), Err(InvariantViolation::Index { .. })));
+
+    let mut empty = snapshot;
+    empty.asks[0].quantity = 0;
+    assert!(matches!(OrderBook::from_snapshot(empty), Err(InvariantViolation::ZeroQuantity { .. })));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_json_round_trip_trades_identically() {
+    let mut original = checkpoint_book();
+    let json = serde_json::to_string(&original).unwrap();
+    let mut restored: OrderBook = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+
+    let sweep = Order::limit(40, Side::Buy, px(101.0), 200);
+    let original_fills = serde_json::to_string(&original.add_order(sweep.clone()).unwrap()).unwrap();
+    let restored_fills = serde_json::to_string(&restored.add_order(sweep).unwrap()).unwrap();
+    assert_eq!(restored_fills, original_fills);
+
+    let sweep = Order::market(41, Side::Sell, 70);
+    assert_eq!(restored.add_order(sweep.clone()).unwrap(), original.add_order(sweep).unwrap());
+}
+
+#[test]
+fn test_l2_updates_coalesce_per_level() {
+    let mut book = OrderBook::from_fixture("ASK 100.5x30 | 100.0x30(3)").unwrap();
+    book.enable_market_data();
+
+    // Eating all three orders at 100 and part of 100.5 is two updates, not four
+    book.add_order(Order::limit(10, Side::Buy, px(100.5), 40)).unwrap();
+    let updates = book.take_updates();
+    let changes: Vec<_> = updates.iter().map(|update| update.change).collect();
+    assert_eq!(
+        changes,
+        vec![
+            L2Change::LevelRemoved { side: Side::Sell, price: px(100.0) },
+            L2Change::LevelChanged { side: Side::Sell, price: px(100.5), new_quantity: 20 },
+        ]
+    );
+    assert_eq!(updates.iter().map(|update| update.sequence).collect::<Vec<_>>(), vec![1, 2]);
+
+    // Resting, then cancelling the only order at a level
+    book.add_order(Order::limit(11, Side::Buy, px(99.0), 5)).unwrap();
+    book.cancel_order(11).unwrap();
+    let updates = book.take_updates();
+    assert_eq!(updates[0].change, L2Change::LevelAdded { side: Side::Buy, price: px(99.0), quantity: 5 });
+    assert_eq!(updates[1].change, L2Change::LevelRemoved { side: Side::Buy, price: px(99.0) });
+    assert_eq!(updates[1].sequence, 4);
+
+    // A no-op modify and a book without the feed emit nothing
+    book.modify_order(1, px(100.5), 20).unwrap();
+    assert!(book.take_updates().is_empty());
+    assert!(OrderBook::new().take_updates().is_empty());
+}
+
+#[test]
+fn test_l2_shadow_book_matches_depth() {
+    use crate::flow::{self, FlowConfig, FlowEvent};
+
+    let mut book = OrderBook::new();
+    let mut shadow: BTreeMap<(bool, Price), u64> = BTreeMap::new();
+
+    let config = FlowConfig { min_price: 99.0, max_price: 101.0, cancel_probability: 0.3, ..FlowConfig::default() };
+    let events = flow::generate(11, 5_000, &config);
+    let apply = |book: &mut OrderBook, event: &FlowEvent| match event.clone() {
+        FlowEvent::Add(order) => {
+            book.add_order(order).unwrap();
+        }
+        FlowEvent::Cancel(id) => {
+            let _ = book.cancel_order(id);
+        }
+    };
+
+    // Start the feed part way through, seeding the shadow from a depth snapshot
+    let (warm_up, rest) = events.split_at(500);
+    for event in warm_up {
+        apply(&mut book, event);
+    }
+    book.enable_market_data();
+    let depth = book.depth(usize::MAX);
+    for (is_bid, levels) in [(true, depth.bids), (false, depth.asks)] {
+        for (price, quantity, _) in levels {
+            shadow.insert((is_bid, price), quantity);
+        }
+    }
+
+    for event in rest {
+        apply(&mut book, event);
+        for update in book.take_updates() {
+            match update.change {
+                L2Change::LevelAdded { side, price, quantity } => {
+                    assert!(shadow.insert((side == Side::Buy, price), quantity).is_none());
+                }
+                L2Change::LevelChanged { side, price, new_quantity } => {
+                    assert!(shadow.insert((side == Side::Buy, price), new_quantity).is_some());
+                }
+                L2Change::LevelRemoved { side, price } => {
+                    assert!(shadow.remove(&(side == Side::Buy, price)).is_some());
+                }
+            }
+        }
+    }
+
+    let depth = book.depth(usize::MAX);
+    let bids: Vec<_> = shadow.iter().rev().filter(|((is_bid, _), _)| *is_bid).map(|((_, price), qty)| (*price, *qty)).collect();
+    let asks: Vec<_> = shadow.iter().filter(|((is_bid, _), _)| !*is_bid).map(|((_, price), qty)| (*price, *qty)).collect();
+    assert_eq!(bids, depth.bids.iter().map(|&(price, qty, _)| (price, qty)).collect::<Vec<_>>());
+    assert_eq!(asks, depth.asks.iter().map(|&(price, qty, _)| (price, qty)).collect::<Vec<_>>());
+}
+
+/// Every resting order as (id, visible quantity), queue by queue.
+fn queues(book: &OrderBook) -> BTreeMap<(bool, Price), Vec<(u64, u64)>> {
+    let levels = [book.bids(), book.asks()].into_iter().flat_map(|side| {
+        let queue = |level: PriceLevel| level.iter().map(|order| (order.id, order.quantity)).collect();
+        side.levels().map(move |(price, level)| ((side.side() == Side::Buy, price), queue(level)))
+    });
+    levels.collect()
+}
+
+#[test]
+fn test_l3_mirror_matches_every_queue() {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    let pro_rata = MatchPolicy::ProRata { min_allocation: 2 };
+    for (stp_policy, match_policy) in [(StpPolicy::CancelOldest, MatchPolicy::Fifo), (StpPolicy::None, pro_rata)] {
+        let mut book = OrderBook::from_fixture("ASK 101.0x10 | 100.5x20(2) ; BID 99.5x15").unwrap();
+        book = OrderBook::from_snapshot(BookSnapshot { stp_policy, match_policy, ..book.snapshot() }).unwrap();
+        book.enable_order_data();
+        let mut mirror = queues(&book);
+        let (mut rng, mut sequence) = (StdRng::seed_from_u64(31), 0);
+
+        for step in 0..5_000u64 {
+            let (id, side) = (step + 10, if rng.random_bool(0.5) { Side::Buy } else { Side::Sell });
+            let (price, quantity) = (Price(rng.random_range(9_940..10_060)), rng.random_range(1..60));
+            let order = Order::limit(id, side, price, quantity).with_participant_id(rng.random_range(0..3));
+            let target = rng.random_range(1..id);
+            match rng.random_range(0..10) {
+                0 => drop(book.add_order(order.with_display_quantity(7))),
+                1 => drop(book.add_order(order.with_expiry(book.now() + 20))),
+                2 => drop(book.cancel_order(target)),
+                3 => drop(book.modify_order(target, price, quantity)),
+                4 if step % 500 == 4 => book.start_auction(),
+                5 if book.in_auction() => drop(book.uncross()),
+                6 => drop(book.advance_time(book.now() + 1)),
+                _ => drop(book.add_order(order)),
+            }
+
+            for update in book.take_order_updates() {
+                sequence += 1;
+                assert_eq!(update.sequence, sequence);
+                match update.change {
+                    L3Change::OrderAdded { side, price, order_id, quantity } => {
+                        mirror.entry((side == Side::Buy, price)).or_default().push((order_id, quantity));
+                    }
+                    L3Change::OrderExecuted { side, price, order_id, quantity }
+                    | L3Change::OrderCancelled { side, price, order_id, quantity } => {
+                        let queue = mirror.get_mut(&(side == Side::Buy, price)).unwrap();
+                        let position = queue.iter().position(|&(id, _)| id == order_id).unwrap();
+                        queue[position].1 -= quantity;
+                        if queue[position].1 == 0 {
+                            queue.remove(position);
+                        }
+                    }
+                }
+                // Applied in sequence the mirror never crosses, not even part way through a step
+                let best = |buy: bool| {
+                    let prices = mirror.iter().filter(|(key, queue)| key.0 == buy && !queue.is_empty());
+                    let prices = prices.map(|(key, _)| key.1);
+                    if buy { prices.max() } else { prices.min() }
+                };
+                if let (Some(bid), Some(ask)) = (best(true), best(false)) {
+                    assert!(bid < ask, "mirror crossed at update {}", update.sequence);
+                }
+            }
+            mirror.retain(|_, queue| !queue.is_empty());
+            assert_eq!(mirror, queues(&book), "step {} under {:?}", step, match_policy);
+        }
+        assert!(sequence > 5_000);
+    }
+}
+
+#[test]
+fn test_l3_executions_come_before_the_takers_add() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10").unwrap();
+    let ask = book.asks().best().unwrap().1.front().unwrap().id;
+    book.enable_order_data();
+    book.add_order(Order::limit(10, Side::Buy, px(101.0), 15)).unwrap();
+
+    let updates = book.take_order_updates().into_iter().map(|update| (update.sequence, update.change));
+    let updates: Vec<_> = updates.collect();
+    assert_eq!(
+        updates,
+        vec![
+            (1, L3Change::OrderExecuted { side: Side::Sell, price: px(100.0), order_id: ask, quantity: 10 }),
+            (2, L3Change::OrderAdded { side: Side::Buy, price: px(101.0), order_id: 10, quantity: 5 }),
+        ]
+    );
+}
+
+#[test]
+fn test_rejects_leave_book_untouched() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10 ; BID 99.0x10").unwrap();
+    book.add_order(Order::stop(20, Side::Buy, px(105.0), 5)).unwrap();
+    let before = book.snapshot();
+
+    let rejects = [
+        (Order::limit(30, Side::Buy, px(100.0), 0), LobError::ZeroQuantity),
+        (Order::market(30, Side::Sell, 0), LobError::ZeroQuantity),
+        (Order::limit(30, Side::Buy, Price(0), 5), LobError::InvalidPrice(Price(0))),
+        (Order::limit(30, Side::Sell, Price(-100), 5), LobError::InvalidPrice(Price(-100))),
+        (Order::stop(30, Side::Sell, Price(-1), 5), LobError::InvalidPrice(Price(-1))),
+        (Order::stop_limit(30, Side::Buy, px(101.0), Price(0), 5), LobError::InvalidPrice(Price(0))),
+        // Ids of resting orders and pending stops are taken, even if the order would cross
+        (Order::limit(1, Side::Buy, px(100.0), 5), LobError::DuplicateOrderId(1)),
+        (Order::market(2, Side::Sell, 5), LobError::DuplicateOrderId(2)),
+        (Order::limit(20, Side::Sell, px(101.0), 5), LobError::DuplicateOrderId(20)),
+    ];
+    for (order, err) in rejects {
+        assert_eq!(book.add_order(order).unwrap_err(), err);
+    }
+    assert_eq!(book.add_market_order(1, Side::Buy, 5, 0).unwrap_err(), LobError::DuplicateOrderId(1));
+    assert_eq!(book.modify_order(2, Price(0), 5).unwrap_err(), ModifyError::InvalidPrice(Price(0)));
+    assert_eq!(book.snapshot(), before);
+
+    // Once an order is gone its id can be used again
+    book.add_order(Order::limit(30, Side::Buy, px(100.0), 10)).unwrap();
+    book.add_order(Order::limit(1, Side::Buy, px(98.0), 10)).unwrap();
+}
+
+#[test]
+fn test_nan_price_never_reaches_the_tree() {
+    let mut book = OrderBook::new();
+    assert!(matches!(book.price(f64::NAN), Err(PriceError::NotFinite(_))));
+
+    // Bypassing `price()` still can't get it in: NaN rounds to tick 0, which is rejected
+    let nan = Price::from_f64(f64::NAN, book.tick_size());
+    assert_eq!(book.add_order(Order::limit(1, Side::Buy, nan, 10)).unwrap_err(), LobError::InvalidPrice(nan));
+    let infinite = Price::from_f64(f64::NEG_INFINITY, book.tick_size());
+    assert!(book.add_order(Order::limit(2, Side::Buy, infinite, 10)).is_err());
+    assert!(book.bids().is_empty());
+}
+
+#[test]
+fn test_volume_queries() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x30(3) | 100.5x20 ; BID 100.0x40(2) | 99.5x25").unwrap();
+    assert_eq!(book.volume_at(Side::Sell, px(101.0)), 30);
+    assert_eq!(book.volume_at(Side::Buy, px(101.0)), 0);
+    assert_eq!((book.total_bid_volume(), book.total_ask_volume()), (65, 50));
+
+    book.add_order(Order::limit(10, Side::Sell, px(100.0), 25)).unwrap();
+    assert_eq!(book.volume_at(Side::Buy, px(100.0)), 15);
+    assert_eq!(book.bids().best().unwrap().1.order_count(), 1);
+    assert_eq!(book.total_bid_volume(), 40);
+    assert_eq!(book.validate(), Ok(()));
+}
+
+#[test]
+fn test_cached_totals_never_drift() {
+    use crate::flow::{self, FlowConfig, FlowEvent};
+
+    let config = FlowConfig { min_price: 99.5, max_price: 100.5, cancel_probability: 0.25, ..FlowConfig::default() };
+    for seed in 0..10 {
+        let mut book = OrderBook::new();
+        for (i, event) in flow::generate(seed, 2_000, &config).into_iter().enumerate() {
+            match event {
+                // Mix in icebergs and in-place size-downs so every cache update path runs
+                FlowEvent::Add(order) if i % 7 == 0 => {
+                    book.add_order(order.with_display_quantity(10)).unwrap();
+                }
+                FlowEvent::Add(order) => {
+                    book.add_order(order).unwrap();
+                }
+                FlowEvent::Cancel(id) if i % 2 == 0 => {
+                    let _ = book.cancel_order(id);
+                }
+                FlowEvent::Cancel(id) => {
+                    let resting = book.bids().get(id).or_else(|| book.asks().get(id)).cloned();
+                    if let Some(order) = resting.filter(|order| order.remaining() > 1) {
+                        book.modify_order(id, order.price, order.remaining() / 2).unwrap();
+                    }
+                }
+            }
+            assert_eq!(book.validate(), Ok(()), "seed {} event {}", seed, i);
+        }
+    }
+}
+
+#[test]
+fn test_validate_catches_corruption() {
+    let fixture = "ASK 101.0x30(3) | 100.5x20 ; BID 100.0x40(2) | 99.5x25";
+
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    book.bids.levels_mut().0.insert(px(99.0), LevelQueue::default());
+    assert_eq!(book.validate(), Err(InvariantViolation::EmptyLevel { side: Side::Buy, price: px(99.0) }));
+
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    let (levels, orders) = book.asks.levels_mut();
+    levels.get_mut(&px(101.0)).unwrap().push_back(orders, Order::limit(50, Side::Sell, px(101.0), 0));
+    assert_eq!(
+        book.validate(),
+        Err(InvariantViolation::ZeroQuantity { side: Side::Sell, price: px(101.0), order_id: 50 })
+    );
+
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    let (levels, orders) = book.asks.levels_mut();
+    levels.get_mut(&px(101.0)).unwrap().push_back(orders, Order::limit(50, Side::Buy, px(101.0), 5));
+    assert_eq!(
+        book.validate(),
+        Err(InvariantViolation::MisplacedOrder { side: Side::Sell, price: px(101.0), order_id: 50 })
+    );
+
+    // Freeing an order's slot behind its level's back leaves the queue linking a stale handle
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    let (levels, orders) = book.asks.levels_mut();
+    orders.remove(levels[&px(101.0)].front().unwrap());
+    assert_eq!(book.validate(), Err(InvariantViolation::BrokenQueue { side: Side::Sell, price: px(101.0) }));
+
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    let id = book.bids().best().unwrap().1.front().unwrap().id;
+    book.asks.insert(Order::limit(id, Side::Sell, px(102.0), 5));
+    assert_eq!(book.validate(), Err(InvariantViolation::DuplicateOrderId { order_id: id }));
+
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    book.bids.insert(Order::limit(50, Side::Buy, px(100.5), 5));
+    assert_eq!(book.validate(), Err(InvariantViolation::Crossed { best_bid: px(100.5), best_ask: px(100.5) }));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "invariant violated")]
+fn test_debug_builds_validate_after_every_add() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x30 ; BID 100.0x40").unwrap();
+    book.bids.insert(Order::limit(50, Side::Buy, px(102.0), 5));
+    let _ = book.add_order(Order::limit(51, Side::Buy, px(90.0), 5));
+}
+
+#[test]
+#[should_panic(expected = "invariant violated")]
+fn test_strict_validation_checks_cancels() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x30 ; BID 100.0x40").unwrap().with_strict_validation();
+    book.asks.levels_mut().0.insert(px(105.0), LevelQueue::default());
+    let id = book.bids().best().unwrap().1.front().unwrap().id;
+    let _ = book.cancel_order(id);
+}
+
+#[test]
+fn test_post_only_reject() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x30 ; BID 100.0x40").unwrap();
+    let before = book.snapshot();
+
+    let crossing = Order::limit(10, Side::Buy, px(101.0), 5).with_post_only();
+    assert_eq!(book.add_order(crossing).unwrap_err(), LobError::PostOnlyWouldCross(px(101.0)));
+    let through = Order::limit(11, Side::Sell, px(99.0), 5).with_post_only();
+    assert_eq!(book.add_order(through).unwrap_err(), LobError::PostOnlyWouldCross(px(99.0)));
+    assert_eq!(book.snapshot(), before);
+
+    book.add_order(Order::limit(12, Side::Buy, px(100.99), 5).with_post_only()).unwrap();
+    assert_eq!(queue_ids(&book, Side::Buy, px(100.99)), vec![12]);
+    assert_eq!(book.modify_order(12, px(101.5), 5), Err(ModifyError::PostOnlyWouldCross(px(101.5))));
+    assert_eq!(book.bids().get(12).unwrap().price, px(100.99));
+}
+
+#[test]
+fn test_post_only_reprice() {
+    let book = OrderBook::from_fixture("ASK 101.0x30 ; BID 100.0x40").unwrap();
+    let mut book = book.with_post_only_policy(PostOnlyPolicy::Reprice);
+
+    let trades = book.add_order(Order::limit(10, Side::Buy, px(102.0), 5).with_post_only()).unwrap();
+    assert!(trades.is_empty());
+    assert_eq!(book.best_bid(), Some((px(100.99), 5)));
+    assert_eq!(book.best_ask(), Some((px(101.0), 30)));
+
+    // One tick above the repriced bid is the ask itself, so it joins the back of that queue
+    book.add_order(Order::limit(11, Side::Sell, px(100.0), 5).with_post_only()).unwrap();
+    assert_eq!(book.best_bid(), Some((px(100.99), 5)));
+    assert_eq!(book.best_ask(), Some((px(101.0), 35)));
+    assert_eq!(queue_ids(&book, Side::Sell, px(101.0)).last(), Some(&11));
+}
+
+#[test]
+fn test_post_only_into_empty_side_rests_at_its_price() {
+    for policy in [PostOnlyPolicy::Reject, PostOnlyPolicy::Reprice] {
+        let mut book = OrderBook::from_fixture("BID 100.0x40").unwrap().with_post_only_policy(policy);
+        book.add_order(Order::limit(10, Side::Buy, px(105.0), 5).with_post_only()).unwrap();
+        assert_eq!(book.best_bid(), Some((px(105.0), 5)));
+    }
+}
+
+#[test]
+fn test_partial_fill_then_expiry_reports_leftover() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Sell, px(101.0), 30).with_expiry(10)).unwrap();
+    book.add_order(Order::limit(2, Side::Sell, px(101.0), 5)).unwrap();
+    book.add_order(Order::limit(3, Side::Buy, px(99.0), 8).with_expiry(20)).unwrap();
+
+    assert!(book.advance_time(9).is_empty());
+    book.add_order(Order::limit(4, Side::Buy, px(101.0), 12).with_timestamp(9)).unwrap();
+
+    assert_eq!(
+        book.advance_time(10),
+        vec![ExpiredOrder { order_id: 1, side: Side::Sell, price: px(101.0), quantity: 18, expires_at: 10 }]
+    );
+    assert_eq!(book.best_ask(), Some((px(101.0), 5)));
+    assert_eq!(book.best_bid(), Some((px(99.0), 8)));
+
+    // Time only moves forward, and the next expiry comes due in turn
+    assert!(book.advance_time(5).is_empty());
+    assert_eq!(book.now(), 10);
+    assert_eq!(book.advance_time(25).len(), 1);
+    assert!(book.bids().is_empty());
+}
+
+#[test]
+fn test_expiry_boundary_is_exclusive() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Sell, px(101.0), 10).with_expiry(10)).unwrap();
+    book.advance_time(10);
+
+    // Expired at 10, so a buy arriving at 10 finds nothing and an order expiring at 10 is refused
+    assert!(book.add_order(Order::limit(2, Side::Buy, px(101.0), 10).with_timestamp(10)).unwrap().is_empty());
+    let late = Order::limit(3, Side::Sell, px(102.0), 10).with_expiry(10);
+    assert_eq!(book.add_order(late).unwrap_err(), LobError::Expired(10));
+    book.add_order(Order::limit(4, Side::Sell, px(102.0), 10).with_expiry(11)).unwrap();
+    assert_eq!(book.best_ask(), Some((px(102.0), 10)));
+}
+
+#[test]
+fn test_stale_expiries_are_skipped() {
+    let mut book = OrderBook::new();
+    book.add_order(Order::limit(1, Side::Sell, px(101.0), 10).with_expiry(10)).unwrap();
+    book.add_order(Order::limit(2, Side::Sell, px(102.0), 10).with_expiry(10)).unwrap();
+    book.add_order(Order::stop(3, Side::Buy, px(105.0), 10).with_expiry(10)).unwrap();
+    book.cancel_order(1).unwrap();
+    book.add_order(Order::limit(5, Side::Buy, px(102.0), 10)).unwrap();
+
+    // Id 1 was cancelled and id 2 filled; the reused id 1 carries a later expiry
+    book.add_order(Order::limit(1, Side::Buy, px(99.0), 10).with_expiry(30)).unwrap();
+    let expired = book.advance_time(10);
+    assert_eq!(expired.iter().map(|expired| expired.order_id).collect::<Vec<_>>(), vec![3]);
+    assert!(!book.is_stop_pending(3));
+    assert_eq!(book.best_bid(), Some((px(99.0), 10)));
+}
+
+#[test]
+fn test_seqs_increase_and_survive_snapshot() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x10").unwrap();
+    book.add_order(Order::limit(10, Side::Buy, px(99.0), 10)).unwrap();
+    assert_eq!(book.add_order(Order::limit(11, Side::Buy, px(99.0), 0)), Err(LobError::ZeroQuantity));
+    book.add_order(Order::limit(12, Side::Buy, px(98.0), 10)).unwrap();
+
+    // Rejected orders take no seq, and losing priority takes a new one
+    let seq = |book: &OrderBook, id| book.order_status(id).unwrap().seq;
+    assert_eq!((seq(&book, 1), seq(&book, 10), seq(&book, 12)), (1, 2, 3));
+    book.modify_order(10, px(98.5), 10).unwrap();
+    assert_eq!((seq(&book, 10), book.last_sequence()), (4, 4));
+
+    let mut restored = OrderBook::from_snapshot(book.snapshot()).unwrap();
+    assert_eq!((seq(&restored, 10), restored.last_sequence()), (4, 4));
+    restored.add_order(Order::limit(13, Side::Buy, px(98.0), 10)).unwrap();
+    assert_eq!(seq(&restored, 13), 5);
+}
+
+#[test]
+fn test_same_price_fills_in_seq_order() {
+    let mut book = OrderBook::new();
+    for id in [10, 11, 12] {
+        book.add_order(Order::limit(id, Side::Sell, px(100.0), 10)).unwrap();
+    }
+    // Shrinking keeps the seq; re-adding the front order sends it to the back
+    book.modify_order(11, px(100.0), 5).unwrap();
+    let cancelled = book.cancel_order(10).unwrap();
+    book.add_order(cancelled).unwrap();
+
+    let trades = book.add_order(Order::limit(20, Side::Buy, px(100.0), 25)).unwrap();
+    let seqs: Vec<_> = trades.iter().map(|trade| (trade.maker_id, trade.maker_seq, trade.taker_seq)).collect();
+    assert_eq!(seqs, vec![(11, 2, 5), (12, 3, 5), (10, 4, 5)]);
+}
+
+#[test]
+fn test_strict_timestamps_reject_backwards_orders() {
+    let mut book = OrderBook::new().with_strict_timestamps();
+    book.add_order(Order::limit(10, Side::Buy, px(99.0), 10).with_timestamp(5)).unwrap();
+    book.add_order(Order::limit(11, Side::Buy, px(99.0), 10).with_timestamp(5)).unwrap();
+
+    let late = Order::limit(12, Side::Buy, px(99.0), 10).with_timestamp(4);
+    assert_eq!(book.add_order(late), Err(LobError::TimestampBehind { timestamp: 4, last: 5 }));
+    assert_eq!(book.last_sequence(), 2);
+
+    // Without strict mode the same replay is accepted
+    let mut lenient = OrderBook::new();
+    lenient.add_order(Order::limit(10, Side::Buy, px(99.0), 10).with_timestamp(5)).unwrap();
+    assert!(lenient.add_order(Order::limit(12, Side::Buy, px(99.0), 10).with_timestamp(4)).is_ok());
+}
+
+#[test]
+fn test_clock_stamps_orders_and_drives_expiry() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let time = Arc::new(AtomicU64::new(3));
+    let mut book = OrderBook::new();
+    let reading = time.clone();
+    book.set_clock(Box::new(move || reading.load(Ordering::SeqCst)));
+    book.enable_market_data();
+
+    book.add_order(Order::limit(10, Side::Sell, px(101.0), 10).with_expiry(10)).unwrap();
+    book.add_order(Order::limit(11, Side::Sell, px(102.0), 10).with_timestamp(1)).unwrap();
+    assert_eq!((book.order_status(10).unwrap().timestamp, book.order_status(11).unwrap().timestamp), (3, 1));
+
+    // Reaching the expiry on the clock removes the order before the buy arrives
+    time.store(10, Ordering::SeqCst);
+    let report = book.add_order_with_report(Order::limit(20, Side::Buy, px(102.0), 5)).unwrap();
+    let trades = report.trades;
+    assert_eq!((trades[0].maker_id, trades[0].timestamp, book.now()), (11, 10, 10));
+    assert_eq!(report.expired.iter().map(|expired| expired.order_id).collect::<Vec<_>>(), vec![10]);
+    let stamps: Vec<_> = book.take_updates().iter().map(|update| update.timestamp).collect();
+    assert_eq!(stamps, vec![3, 3, 10, 10]);
+
+    // Sweeping expires without any other call, at the clock's time when that is ahead
+    book.add_order(Order::limit(12, Side::Buy, px(99.0), 5).with_expiry(15)).unwrap();
+    time.store(15, Ordering::SeqCst);
+    let expired = book.expire_orders(12);
+    assert_eq!((expired.len(), expired[0].order_id, book.now()), (1, 12, 15));
+
+    // Without the clock it goes by the time it is given
+    book.clear_clock();
+    time.store(30, Ordering::SeqCst);
+    book.add_order(Order::limit(13, Side::Buy, px(99.0), 5).with_expiry(20)).unwrap();
+    assert!(book.expire_orders(19).is_empty());
+    assert_eq!(book.expire_orders(20)[0].order_id, 13);
+    book.cancel_order(11).unwrap();
+    assert_eq!(book.now(), 20);
+}
+
+#[test]
+fn test_accounting_follows_every_exit() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x10 | 102.0x20 ; BID 99.0x10").unwrap();
+    assert_eq!((book.accounting().submitted, book.accounting().resting_asks), (40, 30));
+
+    // 15 trade, 10 of the IOC are cancelled, one bid shrinks by 4 and another expires
+    book.add_order(Order::limit(10, Side::Buy, px(101.0), 20).with_time_in_force(TimeInForce::Ioc)).unwrap();
+    book.add_order(Order::limit(11, Side::Sell, px(99.0), 5)).unwrap();
+    book.modify_order(3, px(99.0), 1).unwrap();
+    book.add_order(Order::limit(12, Side::Buy, px(98.0), 7).with_expiry(5)).unwrap();
+    book.add_order(Order::stop(13, Side::Buy, px(110.0), 3)).unwrap();
+    book.advance_time(5);
+
+    let accounting = book.accounting();
+    assert_eq!((accounting.submitted, accounting.executed, accounting.cancelled, accounting.expired), (75, 15, 14, 7));
+    assert_eq!((accounting.resting_bids, accounting.resting_asks, accounting.pending), (1, 20, 3));
+    assert_eq!(accounting.check(), Ok(()));
+}
+
+#[test]
+fn test_accounting_catches_a_dropped_update() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x10 | 102.0x20").unwrap();
+    book.skip_next_cancel_accounting();
+    book.cancel_order(2).unwrap();
+
+    let error = book.accounting().check().unwrap_err();
+    assert_eq!(error, AccountingError::Unbalanced { submitted: 30, accounted: 10 });
+    assert_eq!(error.to_string(), "30 submitted but 10 accounted for");
+}
+
+#[test]
+fn test_accounting_balances_over_random_operations() {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Debug builds validate the whole book after every operation, so they get a shorter run
+    let ops = if cfg!(debug_assertions) { 20_000 } else { 1_000_000 };
+
+    let mut book = OrderBook::new()
+        .with_stp_policy(StpPolicy::CancelBoth)
+        .with_max_levels_per_side(12)
+        .with_level_cap_policy(LevelCapPolicy::Evict);
+    let mut rng = StdRng::seed_from_u64(29);
+    let mut open: Vec<u64> = Vec::new();
+
+    for step in 0..ops {
+        let (id, side) = (step + 1, if rng.random_bool(0.5) { Side::Buy } else { Side::Sell });
+        let (price, quantity) = (Price(rng.random_range(9_980..10_020)), rng.random_range(1..100));
+        let order = Order::limit(id, side, price, quantity).with_participant_id(rng.random_range(0..4));
+        let order = match rng.random_range(0..20) {
+            0 => Order::market(id, side, quantity),
+            1 => order.with_time_in_force(TimeInForce::Ioc),
+            2 => order.with_time_in_force(TimeInForce::Fok),
+            3 => order.with_display_quantity(10),
+            4 => order.with_post_only(),
+            5 => order.with_expiry(book.now() + rng.random_range(1..50)),
+            6 => Order::stop_limit(id, side, price, price, quantity),
+            7 if !open.is_empty() => {
+                let _ = book.cancel_order(open.swap_remove(rng.random_range(0..open.len())));
+                continue;
+            }
+            8 if !open.is_empty() => {
+                let target = open[rng.random_range(0..open.len())];
+                let _ = book.modify_order(target, price, quantity);
+                continue;
+            }
+            9 => {
+                book.advance_time(book.now() + 1);
+                continue;
+            }
+            10 if step % 1_000 == 10 => {
+                book.start_auction();
+                continue;
+            }
+            11 if book.in_auction() => {
+                book.uncross();
+                continue;
+            }
+            _ => order,
+        };
+        if book.add_order(order).is_ok() {
+            open.push(id);
+        }
+        // Forget ids that have long since left the book
+        if open.len() > 200 {
+            open.drain(..100);
+        }
+        if step % 1_000 == 0 {
+            assert_eq!(book.accounting().check(), Ok(()), "step {}", step);
+        }
+    }
+
+    let accounting = book.accounting();
+    assert_eq!(accounting.check(), Ok(()));
+    assert!(accounting.executed > 0 && accounting.cancelled > 0 && accounting.expired > 0);
+    let open = |side: &BookSide| side.levels().flat_map(|(_, level)| level).map(Order::remaining).sum::<u64>();
+    assert_eq!((accounting.resting_bids, accounting.resting_asks), (open(book.bids()), open(book.asks())));
+}
+
+#[test]
+fn test_tape_vwap_is_exact() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x10 | 102.0x20 | 103.0x10").unwrap();
+    book.enable_tape(100);
+
+    book.add_order(Order::limit(10, Side::Buy, px(102.0), 30).with_timestamp(1)).unwrap();
+    book.add_order(Order::limit(11, Side::Buy, px(103.0), 10).with_timestamp(2)).unwrap();
+
+    // (101 * 10 + 102 * 20 + 103 * 10) / 40 = 102
+    let tape = book.tape().unwrap();
+    assert_eq!(tape.trade_count(), 3);
+    assert_eq!(tape.total_volume(), 40);
+    assert_eq!(tape.vwap(), Some(px(102.0).0 as f64));
+    assert_eq!((tape.high(), tape.low(), tape.last_price()), (Some(px(103.0)), Some(px(101.0)), Some(px(103.0))));
+}
+
+#[test]
+fn test_tape_eviction_keeps_cumulative_totals() {
+    let mut book = OrderBook::new();
+    book.enable_tape(3);
+    for i in 0..10u64 {
+        book.add_order(Order::limit(2 * i, Side::Sell, px(100.0 + i as f64), i + 1).with_timestamp(i)).unwrap();
+        book.add_order(Order::limit(2 * i + 1, Side::Buy, px(100.0 + i as f64), i + 1).with_timestamp(i)).unwrap();
+    }
+
+    let tape = book.tape().unwrap();
+    assert_eq!(tape.trades().count(), 3);
+    assert_eq!(tape.trade_count(), 10);
+    assert_eq!(tape.total_volume(), (1..=10).sum::<u64>());
+    assert_eq!(tape.notional(), (0..10).map(|i| (px(100.0).0 + 100 * i) as i128 * (i + 1) as i128).sum::<i128>());
+    assert_eq!((tape.low(), tape.high()), (Some(px(100.0)), Some(px(109.0))));
+    let window: Vec<u64> = tape.trades_in_window(2).map(|trade| trade.taker_id).collect();
+    assert_eq!(window, vec![17, 19]);
+    assert_eq!(tape.trades_in_window(50).count(), 3);
+}
+
+#[test]
+fn test_tape_bars() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10 | 101.0x10 | 102.0x10").unwrap();
+    book.enable_tape(16);
+    let takers = [(0, 100.0, 5), (3, 101.0, 10), (4, 101.0, 5), (12, 102.0, 10)];
+    for (id, (timestamp, price, quantity)) in (10..).zip(takers) {
+        book.add_order(Order::limit(id, Side::Buy, px(price), quantity).with_timestamp(timestamp)).unwrap();
+    }
+
+    let bars = book.tape().unwrap().bars(5);
+    assert_eq!(bars.len(), 2);
+    assert_eq!(
+        bars[0],
+        Candle { start: 0, open: px(100.0), high: px(101.0), low: px(100.0), close: px(101.0), volume: 20, trades: 4 }
+    );
+    assert_eq!((bars[1].start, bars[1].open, bars[1].close, bars[1].volume), (10, px(102.0), px(102.0), 10));
+}
+
+#[test]
+fn test_price_improvement_through_levels() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10 | 101.0x10 | 102.0x10 | 106.0x10").unwrap();
+
+    let report = book.add_order_with_report(Order::limit(10, Side::Buy, px(105.0), 35)).unwrap();
+    let prices: Vec<Price> = report.trades.iter().map(|trade| trade.price).collect();
+    assert_eq!(prices, vec![px(100.0), px(101.0), px(102.0)]);
+    assert_eq!(report.price_improvement, (500 + 400 + 300) * 10);
+    assert_eq!(book.best_bid(), Some((px(105.0), 5)));
+
+    let sell = book.add_order_with_report(Order::limit(11, Side::Sell, px(104.0), 5)).unwrap();
+    assert_eq!(sell.price_improvement, 100 * 5);
+    assert_eq!(book.stats(), BookStats { trades: 4, volume: 35, price_improvement: 12_000 + 500 });
+}
+
+#[test]
+fn test_no_price_improvement_at_the_touch() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10 | 101.0x10").unwrap();
+    let report = book.add_order_with_report(Order::limit(10, Side::Buy, px(100.0), 10)).unwrap();
+    assert_eq!(report.trades.len(), 1);
+    assert_eq!(report.price_improvement, 0);
+
+    // Market orders have no limit to improve on
+    book.add_market_order(11, Side::Buy, 5, 0).unwrap();
+    assert_eq!(book.stats(), BookStats { trades: 2, volume: 15, price_improvement: 0 });
+}
+
+fn pro_rata_book(min_allocation: u64) -> OrderBook {
+    let mut book = OrderBook::new().with_match_policy(MatchPolicy::ProRata { min_allocation });
+    for (id, quantity) in [(1, 60), (2, 30), (3, 10)] {
+        book.add_order(Order::limit(id, Side::Sell, px(100.0), quantity)).unwrap();
+    }
+    book
+}
+
+fn fills(trades: &[Trade]) -> Vec<(u64, u64)> {
+    trades.iter().map(|trade| (trade.maker_id, trade.quantity)).collect()
+}
+
+#[test]
+fn test_pro_rata_allocation() {
+    let mut book = pro_rata_book(0);
+    let trades = book.add_order(Order::limit(10, Side::Buy, px(100.0), 100)).unwrap();
+    assert_eq!(fills(&trades), vec![(1, 60), (2, 30), (3, 10)]);
+    assert!(book.asks().is_empty());
+
+    let mut book = pro_rata_book(0);
+    let trades = book.add_order(Order::limit(10, Side::Buy, px(100.0), 10)).unwrap();
+    assert_eq!(fills(&trades), vec![(1, 6), (2, 3), (3, 1)]);
+    let remaining: Vec<u64> = [1, 2, 3].iter().map(|&id| book.asks().get(id).unwrap().quantity).collect();
+    assert_eq!(remaining, vec![54, 27, 9]);
+    assert_eq!(queue_ids(&book, Side::Sell, px(100.0)), vec![1, 2, 3]);
+    assert_eq!(book.best_ask(), Some((px(100.0), 90)));
+}
+
+#[test]
+fn test_pro_rata_rounding_leftover_goes_fifo() {
+    // 4.2 / 2.1 / 0.7 round down to 4 / 2 / 0, and the lot left over goes to the front
+    let mut book = pro_rata_book(0);
+    let trades = book.add_order(Order::limit(10, Side::Buy, px(100.0), 7)).unwrap();
+    assert_eq!(fills(&trades), vec![(1, 4), (2, 2), (1, 1)]);
+
+    // A share below the minimum is dropped rather than filled
+    let mut book = pro_rata_book(2);
+    let trades = book.add_order(Order::limit(10, Side::Buy, px(100.0), 10)).unwrap();
+    assert_eq!(fills(&trades), vec![(1, 6), (2, 3), (1, 1)]);
+    assert_eq!(book.asks().get(3).unwrap().quantity, 10);
+    assert_eq!(book.validate(), Ok(()));
+}
+
+#[test]
+fn test_pro_rata_shares_only_the_visible_slice() {
+    let mut book = OrderBook::new().with_match_policy(MatchPolicy::ProRata { min_allocation: 0 });
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 50).with_display_quantity(5)).unwrap();
+    book.add_order(Order::limit(2, Side::Sell, px(100.0), 15)).unwrap();
+
+    // 10 against 5 + 15 visible shares out 2 / 7, then the leftover lot fills the front order
+    let trades = book.add_order(Order::limit(10, Side::Buy, px(100.0), 10)).unwrap();
+    assert_eq!(fills(&trades), vec![(1, 2), (2, 7), (1, 1)]);
+    assert_eq!(book.asks().get(1).unwrap().remaining(), 47);
+    assert_eq!(book.best_ask(), Some((px(100.0), 10)));
+}
+
+fn trade_legs(trades: &[Trade]) -> Vec<(u64, u64, u64)> {
+    trades.iter().map(|trade| (trade.taker_id, trade.maker_id, trade.quantity)).collect()
+}
+
+#[test]
+fn test_auction_without_crossing_interest() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x10 ; BID 99.0x10").unwrap();
+    book.start_auction();
+    assert!(book.add_order(Order::limit(10, Side::Buy, px(100.0), 10)).unwrap().is_empty());
+    book.add_order(Order::limit(11, Side::Sell, px(100.5), 5)).unwrap();
+    book.add_order(Order::market(12, Side::Buy, 5)).unwrap();
+    assert_eq!(book.best_bid(), Some((px(99.0), 10)));
+    assert_eq!(book.cancel_order(12).unwrap().id, 12);
+
+    assert_eq!(book.indicative_auction(), AuctionResult::default());
+    let result = book.uncross();
+    assert_eq!(result, AuctionResult::default());
+    assert!(!book.in_auction());
+    assert_eq!(book.best_bid(), Some((px(100.0), 10)));
+    assert_eq!(book.best_ask(), Some((px(100.5), 5)));
+}
+
+#[test]
+fn test_auction_maximises_volume_then_minimises_imbalance() {
+    let mut book = OrderBook::new();
+    book.start_auction();
+    for (id, side, price, quantity) in [
+        (1, Side::Buy, 102.0, 10),
+        (2, Side::Buy, 101.0, 20),
+        (3, Side::Buy, 100.0, 30),
+        (4, Side::Sell, 99.0, 15),
+        (5, Side::Sell, 100.0, 15),
+        (6, Side::Sell, 101.0, 25),
+    ] {
+        book.add_order(Order::limit(id, side, px(price), quantity)).unwrap();
+    }
+
+    // 100 and 101 both match 30; 101 leaves the smaller imbalance (25 sold short of 30 bought)
+    let indicative = book.indicative_auction();
+    let indicative = (indicative.clearing_price, indicative.matched_volume, indicative.imbalance);
+    assert_eq!(indicative, (Some(px(101.0)), 30, -25));
+
+    let result = book.uncross();
+    assert_eq!(result.clearing_price, Some(px(101.0)));
+    assert_eq!(trade_legs(&result.trades), vec![(1, 4, 10), (2, 4, 5), (2, 5, 15)]);
+    assert!(result.trades.iter().all(|trade| trade.price == px(101.0)));
+    assert_eq!(book.last_trade_price(), Some(px(101.0)));
+
+    // What's left trades continuously from here
+    assert_eq!(book.best_bid(), Some((px(100.0), 30)));
+    assert_eq!(book.best_ask(), Some((px(101.0), 25)));
+    let trades = book.add_order(Order::limit(7, Side::Buy, px(101.0), 5)).unwrap();
+    assert_eq!((trades[0].maker_id, trades[0].price), (6, px(101.0)));
+    assert_eq!(book.validate(), Ok(()));
+}
+
+#[test]
+fn test_auction_tie_goes_to_last_price() {
+    let uncross = |last_trade: Option<f64>| {
+        let mut book = OrderBook::new();
+        if let Some(price) = last_trade {
+            book.add_order(Order::limit(90, Side::Sell, px(price), 1)).unwrap();
+            book.add_order(Order::limit(91, Side::Buy, px(price), 1)).unwrap();
+        }
+        book.start_auction();
+        book.add_order(Order::limit(1, Side::Buy, px(101.0), 10)).unwrap();
+        book.add_order(Order::limit(2, Side::Sell, px(100.0), 10)).unwrap();
+        book.uncross().clearing_price
+    };
+
+    // Any price from 100 to 101 matches all 10 with no imbalance, the last trade price included
+    assert_eq!(uncross(Some(102.0)), Some(px(101.0)));
+    assert_eq!(uncross(Some(99.0)), Some(px(100.0)));
+    assert_eq!(uncross(Some(100.8)), Some(px(100.8)));
+    assert_eq!(uncross(None), Some(px(100.0)));
+}
+
+#[test]
+fn test_auction_includes_resting_orders() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10 ; BID 99.0x10").unwrap();
+    let resting_ask = book.asks().best().unwrap().1.front().unwrap().id;
+    book.start_auction();
+    book.add_order(Order::limit(10, Side::Buy, px(101.0), 15)).unwrap();
+    book.add_order(Order::limit(11, Side::Sell, px(99.0), 5).with_time_in_force(TimeInForce::Ioc)).unwrap();
+
+    // The resting ask sits ahead of the collected one in price priority
+    let result = book.uncross();
+    assert_eq!(result.clearing_price, Some(px(100.0)));
+    assert_eq!(trade_legs(&result.trades), vec![(10, 11, 5), (10, resting_ask, 10)]);
+    assert!(book.asks().is_empty());
+    assert_eq!(book.best_bid(), Some((px(99.0), 10)));
+}
+
+#[test]
+fn test_auction_leaves_untraded_resting_orders_in_place() {
+    use std::collections::BTreeSet;
+
+    let mut book = OrderBook::from_fixture("ASK 102.0x10 | 101.0x20(2) ; BID 99.0x10 | 98.0x10").unwrap();
+    let queue = |book: &OrderBook| -> Vec<(u64, u64)> {
+        book.asks().level(px(101.0)).unwrap().iter().map(|order| (order.id, order.quantity)).collect()
+    };
+    let (front, behind) = (queue(&book)[0].0, queue(&book)[1].0);
+    book.enable_order_data();
+    book.start_auction();
+    book.add_order(Order::limit(20, Side::Buy, px(101.0), 5)).unwrap();
+
+    // Only the part-filled front order and the one queued behind it come off and go back
+    let result = book.uncross();
+    assert_eq!(trade_legs(&result.trades), vec![(20, front, 5)]);
+    assert_eq!(queue(&book), vec![(front, 5), (behind, 10)]);
+    let touched: BTreeSet<u64> = book
+        .take_order_updates()
+        .iter()
+        .map(|update| match update.change {
+            L3Change::OrderAdded { order_id, .. }
+            | L3Change::OrderExecuted { order_id, .. }
+            | L3Change::OrderCancelled { order_id, .. } => order_id,
+        })
+        .collect();
+    assert_eq!(touched, [front, behind].into());
+    assert_eq!((book.best_bid(), book.asks().order_count()), (Some((px(99.0), 10)), 3));
+    assert_eq!(book.accounting().check(), Ok(()));
+}
+
+#[test]
+fn test_auction_kills_part_filled_fok() {
+    let mut book = OrderBook::new();
+    book.start_auction();
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 10)).unwrap();
+    let fok = |id, price, quantity| {
+        Order::limit(id, Side::Buy, px(price), quantity).with_time_in_force(TimeInForce::Fok)
+    };
+    book.add_order(fok(2, 102.0, 2)).unwrap();
+    book.add_order(Order::limit(3, Side::Buy, px(101.0), 6)).unwrap();
+    book.add_order(fok(4, 100.0, 8)).unwrap();
+
+    // At 100 the 10 sold would leave order 4 with 2 of its 8, so it goes and 8 trade instead
+    let result = book.uncross();
+    assert_eq!((result.clearing_price, result.matched_volume), (Some(px(100.0)), 8));
+    assert_eq!(trade_legs(&result.trades), vec![(2, 1, 2), (3, 1, 6)]);
+    assert_eq!(book.best_bid(), None);
+    assert_eq!(book.best_ask(), Some((px(100.0), 2)));
+    assert_eq!(book.accounting().check(), Ok(()));
+}
+
+#[test]
+fn test_modify_during_auction_collects_instead_of_trading() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10 ; BID 99.0x10").unwrap();
+    let resting_ask = book.asks().best().unwrap().1.front().unwrap().id;
+    let resting_bid = book.bids().best().unwrap().1.front().unwrap().id;
+    book.start_auction();
+    book.add_order(Order::limit(10, Side::Buy, px(98.0), 8)).unwrap();
+
+    // Repricing through the ask collects the bid instead of trading it
+    assert!(book.modify_order(resting_bid, px(100.0), 6).unwrap().is_empty());
+    assert!(book.in_auction());
+    assert_eq!(book.best_bid(), None);
+    assert_eq!(book.best_ask(), Some((px(100.0), 10)));
+
+    // Collected orders can be amended too: replaced behind the others, or reduced in place
+    assert!(book.modify_order(10, px(100.0), 8).unwrap().is_empty());
+    assert!(book.modify_order(resting_bid, px(100.0), 4).unwrap().is_empty());
+    assert_eq!(book.modify_order(11, px(100.0), 4), Err(ModifyError::NotFound(11)));
+
+    let result = book.uncross();
+    assert_eq!(trade_legs(&result.trades), vec![(resting_bid, resting_ask, 4), (10, resting_ask, 6)]);
+    assert_eq!(book.best_bid(), Some((px(100.0), 2)));
+    assert_eq!(book.accounting().check(), Ok(()));
+}
+
+#[test]
+fn test_price_band_follows_the_sweep() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10 | 104.0x10 | 108.0x10 | 115.0x10").unwrap();
+    book.add_order(Order::limit(11, Side::Buy, px(100.0), 5)).unwrap();
+    book.set_price_bands(ReferencePrice::LastTrade, 5.0);
+    assert_eq!(book.price_band_limits(), Some((px(95.0), px(105.0))));
+
+    // 108 is outside the band around the first fills at 100, but inside the one around 104;
+    // 115 is outside the band around 108, so the sweep stops there and the rest is cancelled
+    let trades = book.add_order(Order::market(12, Side::Buy, 50)).unwrap();
+    let fills: Vec<_> = trades.iter().map(|trade| (trade.price, trade.quantity)).collect();
+    assert_eq!(fills, vec![(px(100.0), 5), (px(104.0), 10), (px(108.0), 10)]);
+    assert_eq!(book.best_ask(), Some((px(115.0), 10)));
+    assert_eq!(book.last_trade_price(), Some(px(108.0)));
+
+    let rejected = book.add_order(Order::limit(13, Side::Buy, px(115.0), 10));
+    let band = LobError::OutsidePriceBand { price: px(115.0), low: px(102.6), high: px(113.4) };
+    assert_eq!(rejected, Err(band));
+
+    book.clear_price_bands();
+    assert_eq!(book.add_order(Order::limit(13, Side::Buy, px(115.0), 10)).unwrap().len(), 1);
+}
+
+#[test]
+fn test_price_band_from_mid_before_any_trade() {
+    let mut book = OrderBook::from_fixture("ASK 101.0x10 | 110.0x10 ; BID 99.0x10").unwrap();
+    book.set_price_bands(ReferencePrice::LastTrade, 5.0);
+    assert_eq!(book.price_band_limits(), Some((px(95.0), px(105.0))));
+    let band = LobError::OutsidePriceBand { price: px(94.0), low: px(95.0), high: px(105.0) };
+    assert_eq!(book.add_order(Order::limit(11, Side::Sell, px(94.0), 10)), Err(band));
+
+    // Trading at 101 moves the band up to 95.95-106.05, still short of 110
+    let trades = book.add_order(Order::market(12, Side::Buy, 20)).unwrap();
+    assert_eq!((trades.len(), trades[0].price), (1, px(101.0)));
+    assert_eq!(book.best_ask(), Some((px(110.0), 10)));
+
+    // A mid band ignores the trade; with the asks at 110 the mid is 104.5
+    book.set_price_bands(ReferencePrice::Mid, 5.0);
+    assert_eq!(book.price_band_limits(), Some((px(99.28), px(109.72))));
+    let bid = book.bids().best().unwrap().1.front().unwrap().id;
+    let outside = ModifyError::OutsidePriceBand { price: px(99.27), low: px(99.28), high: px(109.72) };
+    assert_eq!(book.modify_order(bid, px(99.27), 10), Err(outside));
+    assert_eq!(book.modify_order(bid, px(99.0), 5), Ok(Vec::new()));
+}
+
+#[test]
+fn test_order_sweep_protection() {
+    let mut book = OrderBook::from_fixture("ASK 100.0x10 | 101.0x10 | 102.0x10").unwrap();
+
+    let trades = book.add_order(Order::limit(11, Side::Buy, px(102.0), 30).with_max_sweep_levels(2)).unwrap();
+    assert_eq!(trades.iter().map(|trade| trade.quantity).sum::<u64>(), 20);
+    assert_eq!(book.best_bid(), None);
+
+    // A fill-or-kill counts only what it may reach
+    let fok = Order::market(12, Side::Buy, 5).with_time_in_force(TimeInForce::Fok).with_protection_price(px(101.0));
+    assert!(book.add_order(fok).unwrap().is_empty());
+
+    let trades = book.add_order(Order::market(13, Side::Buy, 5).with_protection_price(px(102.0))).unwrap();
+    assert_eq!((trades.len(), trades[0].price), (1, px(102.0)));
+    assert_eq!(book.best_ask(), Some((px(102.0), 5)));
+    let rejected = book.add_order(Order::market(14, Side::Buy, 5).with_protection_price(Price(0)));
+    assert_eq!(rejected, Err(LobError::InvalidPrice(Price(0))));
+}
+
+#[test]
+fn test_order_status_through_partial_fills() {
+    let mut book = OrderBook::new();
+    book.retain_filled_orders(2);
+    book.add_order(Order::limit(1, Side::Sell, px(100.0), 100).with_display_quantity(40)).unwrap();
+    book.add_order(Order::limit(2, Side::Buy, px(100.0), 30)).unwrap();
+    book.add_order(Order::limit(3, Side::Buy, px(100.0), 25)).unwrap();
+
+    let status = book.order_status(1).unwrap();
+    assert_eq!((status.side, status.price, status.state), (Side::Sell, px(100.0), OrderState::Resting));
+    assert_eq!((status.original_quantity, status.remaining_quantity, status.filled_quantity), (100, 45, 55));
+
+    // Reducing takes quantity away without counting it as filled
+    book.modify_order(1, px(100.0), 40).unwrap();
+    let status = book.order_status(1).unwrap();
+    assert_eq!((status.original_quantity, status.remaining_quantity, status.filled_quantity), (95, 40, 55));
+
+    // Moving it re-enters the remainder but keeps the fills
+    book.modify_order(1, px(100.5), 45).unwrap();
+    let status = book.order_status(1).unwrap();
+    assert_eq!((status.original_quantity, status.remaining_quantity, status.filled_quantity), (100, 45, 55));
+
+    book.add_order(Order::market(4, Side::Buy, 45)).unwrap();
+    let status = book.order_status(1).unwrap();
+    assert_eq!((status.state, status.remaining_quantity, status.filled_quantity), (OrderState::Filled, 0, 100));
+    assert_eq!(book.order_status(4).unwrap().state, OrderState::Filled);
+
+    // Only the last two fills are kept; 2 and 3 filled before them
+    assert_eq!(book.order_status(2), None);
+    assert_eq!(book.order_status(3), None);
+    assert_eq!(book.order_status(99), None);
+}
+
+#[test]
+fn test_order_status_queue_position() {
+    let mut book = OrderBook::new();
+    for id in 1..=4 {
+        book.add_order(Order::limit(id, Side::Buy, px(100.0), 10)).unwrap();
+    }
+    let position = |book: &OrderBook| book.order_status(4).unwrap().queue_position;
+    assert_eq!(position(&book), Some(3));
+
+    book.cancel_order(2).unwrap();
+    assert_eq!(position(&book), Some(2));
+    book.add_order(Order::limit(5, Side::Sell, px(100.0), 5)).unwrap();
+    assert_eq!(position(&book), Some(2));
+    book.add_order(Order::limit(6, Side::Sell, px(100.0), 5)).unwrap();
+    assert_eq!(position(&book), Some(1));
+
+    // Without retention a filled order is just gone
+    assert_eq!(book.order_status(1), None);
+
+    book.add_order(Order::stop(7, Side::Sell, px(99.0), 10)).unwrap();
+    assert_eq!(book.order_status(7).unwrap().state, OrderState::PendingStop);
+    book.start_auction();
+    book.add_order(Order::limit(8, Side::Sell, px(101.0), 10)).unwrap();
+    let status = book.order_status(8).unwrap();
+    assert_eq!((status.state, status.queue_position), (OrderState::InAuction, None));
+}
+
+#[test]
+fn test_maker_and_taker_fills_across_an_orders_life() {
+    let mut book = OrderBook::new();
+    book.retain_filled_orders(2);
+    book.add_order(Order::limit(10, Side::Sell, px(100.0), 10)).unwrap();
+    book.add_order(Order::limit(11, Side::Sell, px(100.5), 20)).unwrap();
+
+    // Emptying the asks mid-sweep leaves nothing for the remainder to cross
+    let taken = book.add_order(Order::limit(12, Side::Buy, px(101.0), 50)).unwrap();
+    assert!(taken.iter().all(|trade| trade.liquidity(12) == Some(Liquidity::Taker)));
+    assert_eq!((taken[0].liquidity(10), taken[0].liquidity(11)), (Some(Liquidity::Maker), None));
+    assert_eq!((book.best_bid(), book.best_ask()), (Some((px(101.0), 20)), None));
+    assert_eq!(book.validate(), Ok(()));
+
+    // The same order then fills passively
+    let made = book.add_order(Order::limit(13, Side::Sell, px(100.0), 15)).unwrap();
+    assert_eq!(made[0].liquidity(12), Some(Liquidity::Maker));
+    let status = book.order_status(12).unwrap();
+    assert_eq!((status.filled_quantity, status.taker_quantity, status.maker_quantity), (45, 30, 15));
+    assert_eq!((book.order_status(13).unwrap().taker_quantity, status.state), (15, OrderState::Resting));
+
+    book.add_order(Order::market(14, Side::Sell, 5)).unwrap();
+    let status = book.order_status(12).unwrap();
+    assert_eq!((status.state, status.taker_quantity, status.maker_quantity), (OrderState::Filled, 30, 20));
+}
+
+/// `FillSimulation` totals of real trades, to hold simulations against.
+fn executed(trades: &[Trade], unfilled: u64) -> FillSimulation {
+    let mut prices: Vec<Price> = trades.iter().map(|trade| trade.price).collect();
+    prices.dedup();
+    FillSimulation {
+        filled_quantity: trades.iter().map(|trade| trade.quantity).sum(),
+        unfilled_quantity: unfilled,
+        notional: trades.iter().map(|trade| trade.price.0 as i128 * trade.quantity as i128).sum(),
+        worst_price: trades.last().map(|trade| trade.price),
+        levels: prices.len(),
+    }
+}
+
+#[test]
+fn test_simulated_fills_match_execution() {
+    let fixture = "ASK 100.0x30(3) | 100.5x20 | 101.0x50 ; BID 99.0x10";
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    let before = book.snapshot();
+
+    let simulation = book.simulate_fill(Side::Buy, 80);
+    assert_eq!((simulation.filled_quantity, simulation.levels, simulation.worst_price), (80, 3, Some(px(101.0))));
+    assert_eq!(book.snapshot(), before);
+    let trades = book.add_order(Order::market(100, Side::Buy, 80)).unwrap();
+    assert_eq!(simulation, executed(&trades, 0));
+    assert_eq!(simulation.average_price(), Some((30.0 * 10_000.0 + 20.0 * 10_050.0 + 30.0 * 10_100.0) / 80.0));
+
+    // A limit stops at its price, and whatever the book can't fill is reported
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    let simulation = book.simulate_limit(Side::Buy, px(100.5), 60);
+    assert_eq!((simulation.filled_quantity, simulation.unfilled_quantity), (50, 10));
+    let trades = book.add_order(Order::limit(100, Side::Buy, px(100.5), 60)).unwrap();
+    assert_eq!(simulation, executed(&trades, 10));
+
+    let simulation = book.simulate_fill(Side::Buy, 500);
+    assert_eq!((simulation.filled_quantity, simulation.unfilled_quantity, simulation.levels), (50, 450, 1));
+    let nothing = FillSimulation { unfilled_quantity: 5, ..Default::default() };
+    assert_eq!(book.simulate_limit(Side::Sell, px(101.0), 5), nothing);
+}
+
+#[test]
+fn test_simulated_fill_against_empty_side() {
+    let book = OrderBook::from_fixture("BID 99.0x10").unwrap();
+    let simulation = book.simulate_fill(Side::Buy, 10);
+    assert_eq!(simulation, FillSimulation { unfilled_quantity: 10, ..Default::default() });
+    assert_eq!((simulation.average_price(), simulation.worst_price), (None, None));
+}
+
+fn assert_close(actual: Option<f64>, expected: f64) {
+    let actual = actual.unwrap();
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+}
+
+#[test]
+fn test_metrics_hand_computed() {
+    let book = OrderBook::from_fixture("ASK 101.0x200(2) | 100.5x50 ; BID 100.0x300 | 99.5x100(3)").unwrap();
+
+    // Top of book: 300 bid against 50 ask
+    let top = book.metrics(1);
+    assert_eq!((top.bid_volume, top.ask_volume), (300, 50));
+    assert_close(top.imbalance, 250.0 / 350.0);
+    assert_close(top.microprice, (100.5 * 300.0 + 100.0 * 50.0) / 350.0);
+    assert_eq!((top.bid_notional, top.ask_notional), (10_000 * 300, 10_050 * 50));
+
+    // Both levels, and asking for more than there are changes nothing
+    let all = book.metrics(2);
+    assert_eq!((all.bid_volume, all.ask_volume), (400, 250));
+    assert_close(all.imbalance, 150.0 / 650.0);
+    assert_close(all.microprice, (100.5 * 400.0 + 100.0 * 250.0) / 650.0);
+    assert_eq!((all.bid_notional, all.ask_notional), (3_000_000 + 995_000, 502_500 + 2_020_000));
+    assert_eq!(book.metrics(10), all);
+
+    // The mid is 100.25; boundaries count
+    assert_eq!(book.depth_within(Side::Buy, DepthDistance::Ticks(24)), Some(0));
+    assert_eq!(book.depth_within(Side::Buy, DepthDistance::Ticks(25)), Some(300));
+    assert_eq!(book.depth_within(Side::Sell, DepthDistance::Ticks(25)), Some(50));
+    assert_eq!(book.depth_within(Side::Buy, DepthDistance::Ticks(75)), Some(400));
+    assert_eq!(book.depth_within(Side::Sell, DepthDistance::Ticks(75)), Some(250));
+    assert_eq!(book.depth_within(Side::Buy, DepthDistance::Percent(0.5)), Some(300));
+    assert_eq!(book.depth_within(Side::Sell, DepthDistance::Percent(1.0)), Some(250));
+}
+
+#[test]
+fn test_metrics_one_sided_and_empty() {
+    let book = OrderBook::from_fixture("BID 100.0x300 | 99.5x100(3)").unwrap();
+    let metrics = book.metrics(5);
+    assert_eq!((metrics.bid_volume, metrics.ask_volume, metrics.ask_notional), (400, 0, 0));
+    assert_eq!((metrics.imbalance, metrics.microprice), (Some(1.0), None));
+    assert_eq!(book.depth_within(Side::Buy, DepthDistance::Percent(10.0)), None);
+
+    assert_eq!(OrderBook::new().metrics(5), BookMetrics::default());
+    assert_eq!(book.metrics(0), BookMetrics::default());
+}
+
+#[test]
+fn test_render_ladder() {
+    let fixture = "ASK 101.5x200(2) | 101.0x50 | 100.5x120 ; BID 100.0x400 | 99.5x100(3)";
+    let book = OrderBook::from_fixture(fixture).unwrap();
+    let expected = "     PRICE  SIZE  ORDERS
+ASK 101.50   200       2  ##########
+ASK 101.00    50       1  ###
+ASK 100.50   120       1  ######
+----- spread 0.50 ------
+BID 100.00   400       1  ####################
+BID  99.50   100       3  #####
+";
+    assert_eq!(book.render(10, RenderOptions::default()), expected);
+    assert_eq!(book.to_string(), expected);
+}
+
+#[test]
+fn test_render_orders_with_depth_and_precision() {
+    let fixture = "ASK 101.5x200(2) | 101.0x50 | 100.5x120 ; BID 100.0x300 | 99.5x100(3) | 99.0x10";
+    let mut book = OrderBook::from_fixture(fixture).unwrap();
+    book.add_order(Order::limit(10, Side::Buy, px(100.0), 1000).with_display_quantity(100)).unwrap();
+
+    // Two levels a side, the rest counted; the iceberg shows its slice and its reserve
+    let options = RenderOptions { bar_width: 10, show_orders: true, precision: Some(3) };
+    assert_eq!(book.render(2, options), "      PRICE  SIZE  ORDERS
+... 1 more level
+ASK 101.000    50       1  ##
+               50      #3
+ASK 100.500   120       1  ###
+              120      #4
+----- spread 0.500 ------
+BID 100.000   400       2  ##########
+              300      #5
+              100     #10  +900 hidden
+BID  99.500   100       3  ###
+               34      #6
+               33      #7
+               33      #8
+... 1 more level
+");
+}
+
+#[test]
+fn test_render_empty_and_one_sided() {
+    assert_eq!(OrderBook::new().to_string(), "empty book\n");
+
+    let bids = OrderBook::from_fixture("BID 100.0x300").unwrap();
+    assert_eq!(bids.to_string(), "     PRICE  SIZE  ORDERS
+------- no asks --------
+BID 100.00   300       1  ####################
+");
+
+    // Decimals follow the tick size; depth 0 leaves only the counts and the spread line
+    let mut asks = OrderBook::with_tick_size(0.25);
+    asks.add_order(Order::limit(1, Side::Sell, asks.price(10.25).unwrap(), 5)).unwrap();
+    asks.add_order(Order::limit(2, Side::Sell, asks.price(10.5).unwrap(), 5)).unwrap();
+    let options = RenderOptions { bar_width: 0, ..RenderOptions::default() };
+    assert_eq!(asks.render(1, options), "    PRICE  SIZE  ORDERS
+... 1 more level
+ASK 10.25     5       1
+------- no bids -------
+");
+    assert_eq!(asks.render(0, options), "    PRICE  SIZE  ORDERS
+... 2 more levels
+------- no bids -------
+");
+}
+
+#[test]
+fn test_level_cap_evicts_worst_level_on_both_sides() {
+    let mut book = OrderBook::new().with_max_levels_per_side(2);
+    let listener = RecordingListener::new();
+    book.set_listener(Box::new(listener.clone()));
+    let orders = [(10, Side::Buy, 99.0), (11, Side::Buy, 99.5), (12, Side::Buy, 99.0), (13, Side::Sell, 101.0)];
+    for (id, side, price) in orders.into_iter().chain([(14, Side::Sell, 101.5)]) {
+        book.add_order(Order::limit(id, side, px(price), 10)).unwrap();
+    }
+
+    // A better price pushes out the whole worst level, front of the queue first
+    let report = book.add_order_with_report(Order::limit(15, Side::Buy, px(100.0), 10)).unwrap();
+    assert_eq!(report.evicted.iter().map(|order| order.id).collect::<Vec<_>>(), vec![10, 12]);
+    let report = book.add_order_with_report(Order::limit(16, Side::Sell, px(100.5), 10)).unwrap();
+    assert_eq!(report.evicted.iter().map(|order| (order.id, order.quantity)).collect::<Vec<_>>(), vec![(14, 10)]);
+    let depth = book.depth(5);
+    assert_eq!(depth.bids, vec![(px(100.0), 10, 1), (px(99.5), 10, 1)]);
+    assert_eq!(depth.asks, vec![(px(100.5), 10, 1), (px(101.0), 10, 1)]);
+
+    let events = listener.take_events();
+    let tail = &events[events.len() - 4..];
+    assert!(matches!(tail[0], BookEvent::OrderAccepted(ref order) if order.id == 16));
+    assert!(matches!(tail[1], BookEvent::OrderRested(ref order) if order.id == 16));
+    assert!(matches!(tail[2], BookEvent::OrderEvicted(ref order) if order.id == 14));
+    assert_eq!(tail[3], BookEvent::LevelRemoved(Side::Sell, px(101.5)));
+
+    // Evicted orders are gone from the index, and joining a full side's level is always fine
+    assert_eq!(book.cancel_order(10), Err(CancelError::NotFound(10)));
+    assert_eq!(book.cancel_order(14), Err(CancelError::NotFound(14)));
+    assert!(book.add_order_with_report(Order::limit(17, Side::Sell, px(101.0), 5)).unwrap().evicted.is_empty());
+    assert_eq!(book.validate(), Ok(()));
+}
+
+#[test]
+fn test_level_cap_policy_for_orders_worse_than_every_level() {
+    let fixture = "ASK 101.0x10 | 100.5x10 ; BID 100.0x10 | 99.5x10";
+    let mut book = OrderBook::from_fixture(fixture).unwrap().with_max_levels_per_side(2);
+    let cap_reached = |price| LobError::LevelCapReached { price, max_levels: 2 };
+    assert_eq!(book.add_order(Order::limit(10, Side::Buy, px(99.0), 5)), Err(cap_reached(px(99.0))));
+    assert_eq!(book.add_order(Order::limit(10, Side::Sell, px(101.5), 5)), Err(cap_reached(px(101.5))));
+    book.add_order(Order::limit(10, Side::Buy, px(99.5), 5)).unwrap();
+    book.add_order(Order::limit(11, Side::Buy, px(99.0), 5).with_time_in_force(TimeInForce::Ioc)).unwrap();
+
+    // A modify may not open a worse level, unless it leaves one behind by doing so
+    let error = ModifyError::LevelCapReached { price: px(99.0), max_levels: 2 };
+    assert_eq!(book.modify_order(10, px(99.0), 5), Err(error));
+    book.cancel_order(10).unwrap();
+    book.add_order(Order::limit(12, Side::Sell, px(102.0), 5)).unwrap_err();
+    let fixture_ask = book.asks().worst().unwrap().1.front().unwrap().id;
+    book.modify_order(fixture_ask, px(102.0), 10).unwrap();
+    assert_eq!(book.asks().worst().unwrap().0, px(102.0));
+
+    // Evicting instead takes the newcomer's own level straight back off
+    let mut book = OrderBook::from_fixture(fixture).unwrap().with_max_levels_per_side(2);
+    book = book.with_level_cap_policy(LevelCapPolicy::Evict);
+    let report = book.add_order_with_report(Order::limit(10, Side::Buy, px(99.0), 5)).unwrap();
+    assert_eq!(report.evicted.iter().map(|order| order.id).collect::<Vec<_>>(), vec![10]);
+    assert_eq!(book.to_fixture(), OrderBook::from_fixture(fixture).unwrap().to_fixture());
+    assert_eq!(book.cancel_order(10), Err(CancelError::NotFound(10)));
+}
@@ -0,0 +1,278 @@
+//! Deterministic random order flow for benchmarks, tests and the demo binary.
+//!
+//! [`generate`] draws prices uniformly from a fixed range, which keeps a thick book with
+//! plenty of crossing. [`FlowGenerator::new`] instead walks a mid price and quotes around it
+//! under a [`MarketRegime`].
+
+use crate::{Order, Price, Side, DEFAULT_TICK_SIZE};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// One row of order flow.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlowEvent {
+    Add(Order),
+    Cancel(u64),
+}
+
+/// Shape of the uniform flow [`generate`] produces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowConfig {
+    /// Limit prices are drawn uniformly from `min_price..max_price`, so `min_price` must be the
+    /// lower of the two.
+    pub min_price: f64,
+    pub max_price: f64,
+    /// Quantities are drawn uniformly from `min_quantity..=max_quantity`; equal bounds give
+    /// every order the same size.
+    pub min_quantity: u64,
+    pub max_quantity: u64,
+    /// Chance that an added order is a buy.
+    pub buy_probability: f64,
+    /// Chance that an event cancels an earlier order instead of adding one. The target is
+    /// picked at random among all earlier adds, so it may already be filled or cancelled.
+    pub cancel_probability: f64,
+    pub tick_size: f64,
+}
+
+impl Default for FlowConfig {
+    /// The 50/50 limit-order mix around 100 that the benchmark has always run.
+    fn default() -> Self {
+        Self {
+            min_price: 90.0,
+            max_price: 110.0,
+            min_quantity: 1,
+            max_quantity: 99,
+            buy_probability: 0.5,
+            cancel_probability: 0.0,
+            tick_size: DEFAULT_TICK_SIZE,
+        }
+    }
+}
+
+/// A market to simulate with [`FlowGenerator::new`]: a mid price on a random walk, with
+/// orders placed around it.
+///
+/// Prices are in price units (not ticks) and every per-event figure applies once per added
+/// order, so a regime's character doesn't depend on its cancel rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketRegime {
+    /// Where the mid starts.
+    pub initial_mid: f64,
+    /// Mean change of the mid per added order.
+    pub drift: f64,
+    /// Standard deviation of the mid's normally distributed change per added order.
+    pub volatility: f64,
+    /// Least distance from the mid a passive order is placed at.
+    pub half_spread: f64,
+    /// Mean of the exponentially distributed extra distance beyond `half_spread`, which sets
+    /// how deep the book builds.
+    pub mean_depth: f64,
+    /// Chance that an order is priced through the mid by the same distance instead, so it is
+    /// likely to trade on arrival.
+    pub aggressive_probability: f64,
+    /// Quantities are lognormal: `exp(size_mu + size_sigma * z)` rounded, at least 1.
+    pub size_mu: f64,
+    pub size_sigma: f64,
+    pub max_quantity: u64,
+    /// Chance that an added order is a buy; away from 0.5 the flow is imbalanced.
+    pub buy_probability: f64,
+    /// Chance that an event cancels an earlier order instead of adding one, as in
+    /// [`FlowConfig::cancel_probability`].
+    pub cancel_probability: f64,
+    pub tick_size: f64,
+}
+
+impl MarketRegime {
+    /// A quiet, balanced market around 100 with a narrow spread and steady cancels.
+    pub fn calm() -> Self {
+        Self {
+            initial_mid: 100.0,
+            drift: 0.0,
+            volatility: 0.005,
+            half_spread: 0.01,
+            mean_depth: 0.1,
+            aggressive_probability: 0.1,
+            size_mu: 3.0,
+            size_sigma: 0.8,
+            max_quantity: 10_000,
+            buy_probability: 0.5,
+            cancel_probability: 0.25,
+            tick_size: DEFAULT_TICK_SIZE,
+        }
+    }
+
+    /// A market marching upwards on heavier buying, with buyers crossing the spread more.
+    pub fn trending() -> Self {
+        Self { drift: 0.001, buy_probability: 0.6, aggressive_probability: 0.2, ..Self::calm() }
+    }
+
+    /// Big swings, wide and thin quoting and large, uneven sizes.
+    pub fn volatile() -> Self {
+        Self { volatility: 0.05, half_spread: 0.05, mean_depth: 0.5, size_sigma: 1.5, ..Self::calm() }
+    }
+}
+
+impl Default for MarketRegime {
+    fn default() -> Self {
+        Self::calm()
+    }
+}
+
+/// An endless, deterministic stream of order flow: the same seed and settings always give the
+/// same events.
+///
+/// Added orders are GTC limits with ids `0, 1, 2, ...` in the order they are added, and each
+/// event's timestamp is its position in the stream.
+#[derive(Debug, Clone)]
+pub struct FlowGenerator {
+    rng: StdRng,
+    model: Model,
+    next_id: u64,
+    position: u64,
+}
+
+#[derive(Debug, Clone)]
+enum Model {
+    Uniform(FlowConfig),
+    Regime { regime: MarketRegime, mid: f64 },
+}
+
+impl FlowGenerator {
+    /// Flow from `regime`, see [`MarketRegime`].
+    pub fn new(seed: u64, regime: MarketRegime) -> Self {
+        let mid = regime.initial_mid;
+        Self::with_model(seed, Model::Regime { regime, mid })
+    }
+
+    /// The uniform flow of [`generate`].
+    pub fn uniform(seed: u64, config: FlowConfig) -> Self {
+        Self::with_model(seed, Model::Uniform(config))
+    }
+
+    fn with_model(seed: u64, model: Model) -> Self {
+        Self { rng: StdRng::seed_from_u64(seed), model, next_id: 0, position: 0 }
+    }
+
+    /// Current mid of a regime's random walk; `None` for uniform flow.
+    pub fn mid(&self) -> Option<f64> {
+        match &self.model {
+            Model::Uniform(_) => None,
+            Model::Regime { mid, .. } => Some(*mid),
+        }
+    }
+}
+
+impl Iterator for FlowGenerator {
+    type Item = FlowEvent;
+
+    fn next(&mut self) -> Option<FlowEvent> {
+        let rng = &mut self.rng;
+        let timestamp = self.position;
+        self.position += 1;
+
+        let cancel_probability = match &self.model {
+            Model::Uniform(config) => config.cancel_probability,
+            Model::Regime { regime, .. } => regime.cancel_probability,
+        };
+        if self.next_id > 0 && rng.random_bool(cancel_probability) {
+            return Some(FlowEvent::Cancel(rng.random_range(0..self.next_id)));
+        }
+
+        let (side, price, quantity) = match &mut self.model {
+            Model::Uniform(config) => {
+                let price = Price::from_f64(rng.random_range(config.min_price..config.max_price), config.tick_size);
+                let quantity = rng.random_range(config.min_quantity..=config.max_quantity);
+                let side = if rng.random_bool(config.buy_probability) { Side::Buy } else { Side::Sell };
+                (side, price, quantity)
+            }
+            Model::Regime { regime, mid } => {
+                // Never let the walk reach zero, so every price stays at least a tick
+                *mid = (*mid + regime.drift + regime.volatility * standard_normal(rng)).max(regime.tick_size);
+                let side = if rng.random_bool(regime.buy_probability) { Side::Buy } else { Side::Sell };
+                let distance = regime.half_spread - regime.mean_depth * (1.0 - rng.random::<f64>()).ln();
+                let passive = match side {
+                    Side::Buy => -distance,
+                    Side::Sell => distance,
+                };
+                let offset = if rng.random_bool(regime.aggressive_probability) { -passive } else { passive };
+                let price = Price::from_f64(*mid + offset, regime.tick_size).max(Price(1));
+                let size = (regime.size_mu + regime.size_sigma * standard_normal(rng)).exp().round();
+                (side, price, (size as u64).clamp(1, regime.max_quantity.max(1)))
+            }
+        };
+
+        let order = Order::limit(self.next_id, side, price, quantity).with_timestamp(timestamp);
+        self.next_id += 1;
+        Some(FlowEvent::Add(order))
+    }
+}
+
+/// A standard normal draw, by Box-Muller.
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u1 = 1.0 - rng.random::<f64>();
+    let u2 = rng.random::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
+
+/// Generates `count` events from `seed` with prices and sizes drawn uniformly from `config`;
+/// the same seed and config always give the same flow. See [`FlowGenerator`] for ids and
+/// timestamps, and [`MarketRegime`] for flow that looks more like a real market.
+pub fn generate(seed: u64, count: usize, config: &FlowConfig) -> Vec<FlowEvent> {
+    FlowGenerator::uniform(seed, config.clone()).take(count).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_flow() {
+        let config = FlowConfig { cancel_probability: 0.3, ..FlowConfig::default() };
+
+        let flow = generate(7, 1_000, &config);
+        assert_eq!(flow, generate(7, 1_000, &config));
+        assert_ne!(flow, generate(8, 1_000, &config));
+
+        let cancels = flow.iter().filter(|event| matches!(event, FlowEvent::Cancel(_))).count();
+        assert!((200..400).contains(&cancels), "{} cancels", cancels);
+    }
+
+    #[test]
+    fn test_equal_quantity_bounds_give_one_size() {
+        let config = FlowConfig { min_quantity: 5, max_quantity: 5, ..FlowConfig::default() };
+        let flow = generate(7, 1_000, &config);
+        assert!(flow.iter().all(|event| matches!(event, FlowEvent::Add(order) if order.quantity == 5)));
+    }
+
+    #[test]
+    fn test_same_seed_same_regime_flow() {
+        let first: Vec<_> = FlowGenerator::new(7, MarketRegime::volatile()).take(10_000).collect();
+        assert_eq!(first, FlowGenerator::new(7, MarketRegime::volatile()).take(10_000).collect::<Vec<_>>());
+
+        let other: Vec<_> = FlowGenerator::new(8, MarketRegime::volatile()).take(10_000).collect();
+        let diverged = first.iter().zip(&other).position(|(a, b)| a != b);
+        assert!(diverged.is_some_and(|position| position < 10), "{:?}", diverged);
+    }
+
+    #[test]
+    fn test_regime_shapes_flow() {
+        let regime = MarketRegime { cancel_probability: 0.0, ..MarketRegime::trending() };
+        let mut generator = FlowGenerator::new(3, regime.clone());
+        let orders: Vec<Order> = generator
+            .by_ref()
+            .take(10_000)
+            .map(|event| match event {
+                FlowEvent::Add(order) => order,
+                FlowEvent::Cancel(id) => panic!("unexpected cancel of {}", id),
+            })
+            .collect();
+
+        // Drift of 0.001 per order adds about 10 over 10k orders, against a swing of about 0.5
+        let mid = generator.mid().unwrap();
+        assert!((108.0..112.0).contains(&mid), "mid {}", mid);
+
+        let buys = orders.iter().filter(|order| order.side == Side::Buy).count();
+        assert!((5_700..6_300).contains(&buys), "{} buys", buys);
+        assert!(orders.iter().all(|order| (1..=regime.max_quantity).contains(&order.quantity) && order.price.0 > 0));
+    }
+}
@@ -0,0 +1,34 @@
+//! A deterministic, in-memory limit order book with price-time priority matching.
+//!
+//! ```
+//! use lob_rs::{Order, OrderBook, Side};
+//!
+//! let mut book = OrderBook::new();
+//! let price = book.price(101.0).unwrap();
+//! book.add_order(Order::limit(1, Side::Sell, price, 10)).unwrap();
+//! let trades = book.add_order(Order::limit(2, Side::Buy, price, 4)).unwrap();
+//!
+//! assert_eq!(trades[0].maker_id, 1);
+//! assert_eq!(book.best_ask(), Some((price, 6)));
+//! ```
+
+#[cfg(feature = "itch")]
+pub mod codec;
+pub mod concurrent;
+mod engine;
+pub mod flow;
+#[cfg(feature = "persistence")]
+pub mod persistence;
+pub mod replay;
+#[cfg(feature = "server")]
+pub mod server;
+
+pub use engine::{
+    Accounting, AccountingError, AuctionResult, BestFirst, BookEvent, BookMetrics, BookSide, BookSnapshot,
+    BookStats, CancelError, Candle, DepthDistance, DepthSnapshot, EngineError, ExecutionReport, ExpiredOrder,
+    FillSimulation, FixtureError, InvariantViolation, L2Change, L2Update, L3Change, L3Update, LevelCapPolicy,
+    LevelIter, Levels, Liquidity, LobError, MarketOrderResult, MatchPolicy, MatchingEngine, ModifyError,
+    NoopListener, Order, OrderBook, OrderBookListener, OrderState, OrderStatus, OrderType, PostOnlyPolicy, Price,
+    PriceBand, PriceError, PriceLevel, RecordingListener, ReferencePrice, RenderOptions, SelfTradeCancel, Side,
+    StpPolicy, SubmitResult, SymbolId, Tape, TimeInForce, Trade, DEFAULT_TICK_SIZE,
+};
@@ -1,85 +1,78 @@
-mod engine;
-use engine::*;
-use std::time::Instant;
-use rand::Rng;
-use ordered_float::OrderedFloat;
+use lob_rs::flow::{FlowEvent, FlowGenerator, MarketRegime};
+use lob_rs::replay::{self, ReplayStats};
+use lob_rs::OrderBook;
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::process;
 
+const TOTAL_ORDERS: usize = 1_000_000;
+
+/// Demo run over 1,000,000 events of simulated market flow, or over the order flow in a CSV
+/// file. For proper per-scenario numbers use `cargo bench`.
+///
+/// ```text
+/// cargo run --release                        # random flow
+/// cargo run --release -- flow.csv            # replay flow.csv
+/// cargo run --release -- --export flow.csv   # write the random flow to flow.csv, then run it
+/// ```
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
     let mut book = OrderBook::new();
-    let mut rng = rand::rng();
-    let total_orders = 1_000_000;
-
-    // Generate random orders
-    println!("Generating random data...");
-    let mut orders = Vec::with_capacity(total_orders);
-    for i in 0..total_orders {
-        let price = rng.random_range(90.0..110.0);
-        let quantity = rng.random_range(1..100);
-        let side = if rng.random_bool(0.5) { Side::Buy } else { Side::Sell };
 
-        orders.push(Order {
-            _id: i as u64,
-            price,
-            quantity,
-            side,
-        });
-    }
-    println!("Generated orders...\n");
+    let events = match args.as_slice() {
+        [] => random_flow(TOTAL_ORDERS),
+        [flag, path] if flag == "--export" => {
+            let events = random_flow(TOTAL_ORDERS);
+            let file = File::create(path).unwrap_or_else(|err| exit(&format!("cannot create {}: {}", path, err)));
+            replay::write_csv(BufWriter::new(file), &events, book.tick_size())
+                .unwrap_or_else(|err| exit(&format!("cannot write {}: {}", path, err)));
+            println!("Wrote {} orders to {}\n", events.len(), path);
+            events
+        }
+        [path] => {
+            let file = File::open(path).unwrap_or_else(|err| exit(&format!("cannot open {}: {}", path, err)));
+            let events = replay::read_csv(file, &book).unwrap_or_else(|err| exit(&format!("{}: {}", path, err)));
+            println!("Loaded {} rows from {}\n", events.len(), path);
+            events
+        }
+        _ => exit("usage: lob-rs [--export FILE | FILE]"),
+    };
 
     // Simulating the market
     println!("Starting the simulation...");
-    let start = Instant::now();
-    for order in orders {
-        book.add_order(order);
-    }
+    let stats = replay::replay(events, &mut book);
     println!("End of simulation....\n");
 
-    // Benchmarks
-    let duration = start.elapsed();
-    let seconds = duration.as_secs_f64();
-    let throughput = total_orders as f64 / seconds;
-    let latency_per_order = (seconds * 1_000_000_000.0) / total_orders as f64;
-    println!("Simulation finished in: {:?}", duration);
-    println!("Throughput: {:.2} seconds", throughput);
-    println!("Latency per order: {:.2} nanoseconds", latency_per_order);
+    report(&stats, &book);
 
     // OUTPUT:-
     // Simulation finished in: 90.205291ms
-    // Throughput: 11085824.22 seconds
+    // Throughput: 11085824.22 orders/sec
     // Latency per order: 90.21 nanoseconds
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_simple_match() {
-        let mut book = OrderBook::new();
-        // Sell 100 @ 150
-        book.add_order(Order { _id: 1, price: 150.0, quantity: 100, side: Side::Sell });
-
-        // Buy 50 @ 150 (Should match)
-        book.add_order(Order { _id: 2, price: 150.0, quantity: 50, side: Side::Buy });
-
-        // Verify state: The Sell order should have 50 left
-        let best_ask = book.asks.values().next().unwrap().front().unwrap();
-        assert_eq!(best_ask.quantity, 50);
-    }
-
-    #[test]
-    fn test_price_priority() {
-        let mut book = OrderBook::new();
-        // Sell 100 @ 150
-        book.add_order(Order { _id: 1, price: 150.0, quantity: 100, side: Side::Sell });
-        // Sell 100 @ 140 (Better price!)
-        book.add_order(Order { _id: 2, price: 140.0, quantity: 100, side: Side::Sell });
+fn random_flow(total_orders: usize) -> Vec<FlowEvent> {
+    // Generate random orders
+    let seed = rand::random();
+    println!("Generating random data (seed {})...", seed);
+    let events = FlowGenerator::new(seed, MarketRegime::default()).take(total_orders).collect();
+    println!("Generated orders...\n");
+    events
+}
 
-        // Buy 100 @ 150. Should match the 140 sell first because it's cheaper.
-        book.add_order(Order { _id: 3, price: 150.0, quantity: 100, side: Side::Buy });
+fn report(stats: &ReplayStats, book: &OrderBook) {
+    // Benchmarks
+    println!("Simulation finished in: {:?}", stats.elapsed);
+    println!("Throughput: {:.2} orders/sec", stats.orders_per_second());
+    println!("Latency per order: {:.2} nanoseconds", stats.nanos_per_order());
+    println!("Trades executed: {} ({} units traded)", stats.trades, stats.volume);
+    println!("Rejected: {}", stats.rejects);
+    println!("Resting bids: {} orders across {} levels", book.bids().order_count(), book.bids().levels().len());
+    println!("Resting asks: {} orders across {} levels", book.asks().order_count(), book.asks().levels().len());
+}
 
-        // The 140 ask should be gone. The 150 ask should remain.
-        assert!(book.asks.get(&OrderedFloat(140.0)).is_none());
-        assert!(book.asks.get(&OrderedFloat(150.0)).is_some());
-    }
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
 }
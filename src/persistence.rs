@@ -0,0 +1,650 @@
+//! Write-ahead logging, so a book can be rebuilt after its process dies.
+//!
+//! Every command is appended to the log by a [`WalWriter`] before it is applied to the book;
+//! [`OrderBook::recover`] replays the log to rebuild the book exactly as it was. Since
+//! matching is deterministic, commands the book rejected are logged and replayed like any
+//! other and are rejected again.
+//!
+//! A log starts with the bytes `LOBW` and a little-endian `u32` format version, then a
+//! checkpoint of the book, followed by the commands since. Each record is framed as a
+//! little-endian `u32` payload length, a CRC-32 of the payload, then the payload. A crash
+//! mid-write leaves a short or damaged record at the end; recovery stops at the first command
+//! that doesn't check out and keeps everything before it. A log of another version, one whose
+//! checkpoint doesn't check out, or one with a checkpoint of a book that fails
+//! [`OrderBook::validate`], is refused with an [`io::ErrorKind::InvalidData`] error instead,
+//! and left as it is.
+//!
+//! Book settings (tick size, policies, price bands) are stored with each checkpoint only, so
+//! take a checkpoint after changing them.
+//!
+//! A book that reads its time from a clock (see [`OrderBook::set_clock`]) can't be logged:
+//! the clock is read inside each call, after the command is logged, and recovery has no clock
+//! to read, so [`WalWriter::create`] and [`WalWriter::checkpoint`] refuse one. Drive a logged
+//! book's time with [`Command::AdvanceTime`] and give orders their timestamps before logging
+//! them instead.
+//!
+//! ```no_run
+//! use lob_rs::persistence::{Command, WalWriter};
+//! use lob_rs::{Order, OrderBook, Side};
+//!
+//! let mut book = OrderBook::new();
+//! let mut wal = WalWriter::create("book.wal", &book)?;
+//! let order = Order::limit(1, Side::Buy, book.price(100.0).unwrap(), 10);
+//! wal.append(&Command::Add(order.clone()))?;
+//! book.add_order(order).unwrap();
+//!
+//! // After a crash:
+//! let recovered = OrderBook::recover("book.wal")?;
+//! assert_eq!(recovered.snapshot(), book.snapshot());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::flow::FlowEvent;
+use crate::{
+    BookSnapshot, LevelCapPolicy, MatchPolicy, Order, OrderBook, OrderType, PostOnlyPolicy, Price, PriceBand,
+    ReferencePrice, Side, StpPolicy, TimeInForce,
+};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A state-changing call on an [`OrderBook`], as stored in the log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Add(Order),
+    Cancel(u64),
+    Modify { id: u64, price: Price, quantity: u64 },
+    AdvanceTime(u64),
+    StartAuction,
+    Uncross,
+}
+
+impl Command {
+    /// Applies the command to `book`, discarding its result. To see the result, log the
+    /// command and then make the call on the book directly.
+    pub fn apply(self, book: &mut OrderBook) {
+        match self {
+            Command::Add(order) => {
+                let _ = book.add_order(order);
+            }
+            Command::Cancel(id) => {
+                let _ = book.cancel_order(id);
+            }
+            Command::Modify { id, price, quantity } => {
+                let _ = book.modify_order(id, price, quantity);
+            }
+            Command::AdvanceTime(now) => {
+                book.advance_time(now);
+            }
+            Command::StartAuction => book.start_auction(),
+            Command::Uncross => {
+                book.uncross();
+            }
+        }
+    }
+}
+
+impl From<FlowEvent> for Command {
+    fn from(event: FlowEvent) -> Self {
+        match event {
+            FlowEvent::Add(order) => Command::Add(order),
+            FlowEvent::Cancel(id) => Command::Cancel(id),
+        }
+    }
+}
+
+/// Appends commands to a log file.
+///
+/// Each record goes to the operating system before `append` returns, so it survives the
+/// process panicking or being killed; call [`WalWriter::sync`] to also survive power loss.
+#[derive(Debug)]
+pub struct WalWriter {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl WalWriter {
+    /// Starts a new log at `path`, replacing any file there, with a checkpoint of `book`. Fails
+    /// with [`io::ErrorKind::InvalidInput`] if `book` has a clock.
+    pub fn create(path: impl AsRef<Path>, book: &OrderBook) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (file, size) = write_checkpoint(&path, book)?;
+        Ok(Self { path, file, size })
+    }
+
+    /// Recovers the log at `path` and reopens it for appending, first cutting off any damaged
+    /// tail so new records follow the last good one.
+    pub fn resume(path: impl AsRef<Path>) -> io::Result<(Self, Recovery)> {
+        let path = path.as_ref().to_path_buf();
+        let recovery = recover(&path)?;
+        let file = OpenOptions::new().append(true).open(&path)?;
+        file.set_len(recovery.valid_len)?;
+        let writer = Self { path, file, size: recovery.valid_len };
+        Ok((writer, recovery))
+    }
+
+    pub fn append(&mut self, command: &Command) -> io::Result<()> {
+        let mut payload = Encoder(Vec::new());
+        payload.command(command);
+        let framed = frame(&payload.0);
+        self.file.write_all(&framed)?;
+        self.size += framed.len() as u64;
+        Ok(())
+    }
+
+    /// Replaces the log with a single checkpoint of `book`, so recovery no longer replays
+    /// anything before it. The new log is written alongside and renamed over the old one, so a
+    /// crash part way through leaves the old log intact. Fails, leaving the log as it was, if
+    /// `book` has a clock.
+    pub fn checkpoint(&mut self, book: &OrderBook) -> io::Result<()> {
+        let (file, size) = write_checkpoint(&self.path, book)?;
+        self.file = file;
+        self.size = size;
+        Ok(())
+    }
+
+    /// Flushes everything appended so far to disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Bytes in the log, up to the end of the last record appended.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// A book rebuilt from its log, and how much of the log it took.
+#[derive(Debug)]
+pub struct Recovery {
+    pub book: OrderBook,
+    /// Commands replayed after the checkpoint.
+    pub commands: usize,
+    /// Length of the intact part of the log.
+    pub valid_len: u64,
+    /// Bytes after that which were damaged or cut short and so were skipped.
+    pub discarded_bytes: u64,
+}
+
+/// Rebuilds a book from the log at `path`: its checkpoint, then every intact command after it.
+pub fn recover(path: impl AsRef<Path>) -> io::Result<Recovery> {
+    let path = path.as_ref();
+    let bytes = fs::read(path)?;
+    let invalid = |problem: String| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{} {}", path.display(), problem))
+    };
+
+    let header = bytes.split_first_chunk::<4>().filter(|(magic, _)| **magic == MAGIC);
+    let header = header.and_then(|(_, rest)| rest.split_first_chunk::<4>());
+    let (version, records) = header.ok_or_else(|| invalid("is not a write-ahead log".into()))?;
+    let version = u32::from_le_bytes(*version);
+    if version != VERSION {
+        let problem = format!("is a version {} write-ahead log; this build reads version {}", version, VERSION);
+        return Err(invalid(problem));
+    }
+    let checkpoint = unframe(records).and_then(|(payload, after)| match Decoder(payload).record()? {
+        Record::Checkpoint(snapshot) => Some((snapshot, after)),
+        Record::Command(_) => None,
+    });
+    let (snapshot, after) = checkpoint.ok_or_else(|| invalid("has no intact checkpoint".into()))?;
+    let restore = |snapshot| {
+        OrderBook::from_snapshot(snapshot).map_err(|err| invalid(format!("has a checkpoint of a broken book: {}", err)))
+    };
+    let book = restore(snapshot)?;
+    let mut recovery = Recovery { book, commands: 0, valid_len: 0, discarded_bytes: 0 };
+
+    let mut rest = after;
+    while let Some((payload, after)) = unframe(rest) {
+        let mut decoder = Decoder(payload);
+        match decoder.record() {
+            Some(Record::Checkpoint(snapshot)) => recovery.book = restore(snapshot)?,
+            Some(Record::Command(command)) => {
+                command.apply(&mut recovery.book);
+                recovery.commands += 1;
+            }
+            None => break,
+        }
+        rest = after;
+    }
+
+    recovery.valid_len = (bytes.len() - rest.len()) as u64;
+    recovery.discarded_bytes = rest.len() as u64;
+    Ok(recovery)
+}
+
+impl OrderBook {
+    /// Rebuilds a book from the write-ahead log at `path`, see [`persistence`](crate::persistence).
+    /// A damaged tail is skipped with a warning on stderr.
+    pub fn recover(path: impl AsRef<Path>) -> io::Result<OrderBook> {
+        let path = path.as_ref();
+        let recovery = recover(path)?;
+        if recovery.discarded_bytes > 0 {
+            eprintln!(
+                "warning: skipped {} damaged bytes at the end of {} after {} commands",
+                recovery.discarded_bytes,
+                path.display(),
+                recovery.commands
+            );
+        }
+        Ok(recovery.book)
+    }
+}
+
+/// Writes a log holding only a checkpoint of `book` next to `path` and renames it into place.
+/// Returns the new log opened for appending, and its size.
+fn write_checkpoint(path: &Path, book: &OrderBook) -> io::Result<(File, u64)> {
+    if book.has_clock() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "a book with a clock can't be logged"));
+    }
+    let mut payload = Encoder(Vec::new());
+    payload.snapshot(&book.snapshot());
+    let mut framed = [MAGIC, VERSION.to_le_bytes()].concat();
+    framed.extend(frame(&payload.0));
+
+    let mut temporary = OsString::from(path.as_os_str());
+    temporary.push(".tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(&framed)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)?;
+
+    let file = OpenOptions::new().append(true).open(path)?;
+    Ok((file, framed.len() as u64))
+}
+
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(payload.len() + 8);
+    framed.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    framed.extend_from_slice(&crc32(payload).to_le_bytes());
+    framed.extend_from_slice(payload);
+    framed
+}
+
+/// Splits the first record's payload off `bytes`, or `None` if it is cut short or fails its
+/// checksum.
+fn unframe(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let checksum = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?);
+    let payload = bytes.get(8..8usize.checked_add(len)?)?;
+    (crc32(payload) == checksum).then(|| (payload, &bytes[8 + len..]))
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE), as used by zip and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// Every log starts with these bytes, then [`VERSION`].
+const MAGIC: [u8; 4] = *b"LOBW";
+/// Layout of the records; bump it whenever an encoding below changes, so recovery refuses a
+/// log it would misread.
+const VERSION: u32 = 2;
+
+enum Record {
+    Checkpoint(BookSnapshot),
+    Command(Command),
+}
+
+const CHECKPOINT: u8 = 0;
+const ADD: u8 = 1;
+const CANCEL: u8 = 2;
+const MODIFY: u8 = 3;
+const ADVANCE_TIME: u8 = 4;
+const START_AUCTION: u8 = 5;
+const UNCROSS: u8 = 6;
+
+/// Record payloads: a tag byte, then fixed-width little-endian fields in declaration order.
+/// An `Option` is a presence byte followed by the value if present; a list is a `u64` count
+/// followed by its items.
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn option<T>(&mut self, value: Option<T>, put: impl FnOnce(&mut Self, T)) {
+        self.u8(value.is_some().into());
+        if let Some(value) = value {
+            put(self, value);
+        }
+    }
+
+    fn price(&mut self, price: Price) {
+        self.i64(price.0);
+    }
+
+    fn command(&mut self, command: &Command) {
+        match command {
+            Command::Add(order) => {
+                self.u8(ADD);
+                self.order(order);
+            }
+            Command::Cancel(id) => {
+                self.u8(CANCEL);
+                self.u64(*id);
+            }
+            Command::Modify { id, price, quantity } => {
+                self.u8(MODIFY);
+                self.u64(*id);
+                self.price(*price);
+                self.u64(*quantity);
+            }
+            Command::AdvanceTime(now) => {
+                self.u8(ADVANCE_TIME);
+                self.u64(*now);
+            }
+            Command::StartAuction => self.u8(START_AUCTION),
+            Command::Uncross => self.u8(UNCROSS),
+        }
+    }
+
+    fn order(&mut self, order: &Order) {
+        self.u64(order.id);
+        self.price(order.price);
+        self.u64(order.quantity);
+        self.u64(order.original_quantity);
+        self.u64(order.taker_quantity);
+        self.u8(order.side as u8);
+        self.u64(order.timestamp);
+        self.u64(order.seq);
+        self.u64(order.accepted_at);
+        match order.order_type {
+            OrderType::Limit => self.u8(0),
+            OrderType::Market => self.u8(1),
+            OrderType::Stop { trigger } => {
+                self.u8(2);
+                self.price(trigger);
+            }
+            OrderType::StopLimit { trigger, limit } => {
+                self.u8(3);
+                self.price(trigger);
+                self.price(limit);
+            }
+        }
+        self.u8(order.time_in_force as u8);
+        self.option(order.display_quantity, Self::u64);
+        self.u64(order.hidden_quantity);
+        self.option(order.participant_id, Self::u64);
+        self.u8(order.post_only.into());
+        self.option(order.expires_at, Self::u64);
+        self.option(order.max_sweep_levels, |encoder, levels| encoder.u64(levels as u64));
+        self.option(order.protection_price, Self::price);
+    }
+
+    fn orders<'a>(&mut self, orders: impl ExactSizeIterator<Item = &'a Order>) {
+        self.u64(orders.len() as u64);
+        orders.for_each(|order| self.order(order));
+    }
+
+    fn snapshot(&mut self, snapshot: &BookSnapshot) {
+        self.u8(CHECKPOINT);
+        self.f64(snapshot.tick_size);
+        self.u8(snapshot.strict_ticks.into());
+        self.u8(snapshot.stp_policy as u8);
+        match snapshot.match_policy {
+            MatchPolicy::Fifo => self.u8(0),
+            MatchPolicy::ProRata { min_allocation } => {
+                self.u8(1);
+                self.u64(min_allocation);
+            }
+        }
+        self.u8(snapshot.post_only_policy as u8);
+        self.option(snapshot.price_band, |encoder, band| {
+            encoder.u8(band.reference as u8);
+            encoder.f64(band.pct);
+        });
+        self.option(snapshot.max_levels_per_side, |encoder, max_levels| encoder.u64(max_levels as u64));
+        self.u8(snapshot.level_cap_policy as u8);
+        self.u8(snapshot.strict_timestamps.into());
+        self.u64(snapshot.now);
+        self.u64(snapshot.last_seq);
+        self.u64(snapshot.last_timestamp);
+        self.option(snapshot.last_trade_price, Self::price);
+        self.orders(snapshot.bids.iter());
+        self.orders(snapshot.asks.iter());
+        self.orders(snapshot.stops.iter());
+        self.option(snapshot.auction.as_ref(), |encoder, collected| encoder.orders(collected.iter()));
+    }
+}
+
+/// Reads what [`Encoder`] writes; every read returns `None` on running out of bytes or on a
+/// value that can't be there.
+struct Decoder<'a>(&'a [u8]);
+
+impl Decoder<'_> {
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.0.split_first_chunk()?;
+        self.0 = rest;
+        Some(*bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|[byte]| byte)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        self.bytes().map(i64::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        self.bytes().map(f64::from_le_bytes)
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn option<T>(&mut self, get: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.bool()? {
+            true => get(self).map(Some),
+            false => Some(None),
+        }
+    }
+
+    fn price(&mut self) -> Option<Price> {
+        self.i64().map(Price)
+    }
+
+    fn record(&mut self) -> Option<Record> {
+        let record = match self.u8()? {
+            CHECKPOINT => Record::Checkpoint(self.snapshot()?),
+            ADD => Record::Command(Command::Add(self.order()?)),
+            CANCEL => Record::Command(Command::Cancel(self.u64()?)),
+            MODIFY => Record::Command(Command::Modify { id: self.u64()?, price: self.price()?, quantity: self.u64()? }),
+            ADVANCE_TIME => Record::Command(Command::AdvanceTime(self.u64()?)),
+            START_AUCTION => Record::Command(Command::StartAuction),
+            UNCROSS => Record::Command(Command::Uncross),
+            _ => return None,
+        };
+        self.0.is_empty().then_some(record)
+    }
+
+    fn side(&mut self) -> Option<Side> {
+        match self.u8()? {
+            0 => Some(Side::Buy),
+            1 => Some(Side::Sell),
+            _ => None,
+        }
+    }
+
+    fn order(&mut self) -> Option<Order> {
+        let (id, price, quantity, original_quantity, taker_quantity) =
+            (self.u64()?, self.price()?, self.u64()?, self.u64()?, self.u64()?);
+        let (side, timestamp, seq, accepted_at) = (self.side()?, self.u64()?, self.u64()?, self.u64()?);
+        let order_type = match self.u8()? {
+            0 => OrderType::Limit,
+            1 => OrderType::Market,
+            2 => OrderType::Stop { trigger: self.price()? },
+            3 => OrderType::StopLimit { trigger: self.price()?, limit: self.price()? },
+            _ => return None,
+        };
+        let time_in_force = match self.u8()? {
+            0 => TimeInForce::Gtc,
+            1 => TimeInForce::Ioc,
+            2 => TimeInForce::Fok,
+            _ => return None,
+        };
+        Some(Order {
+            id,
+            price,
+            quantity,
+            original_quantity,
+            taker_quantity,
+            side,
+            timestamp,
+            seq,
+            accepted_at,
+            order_type,
+            time_in_force,
+            display_quantity: self.option(Self::u64)?,
+            hidden_quantity: self.u64()?,
+            participant_id: self.option(Self::u64)?,
+            post_only: self.bool()?,
+            expires_at: self.option(Self::u64)?,
+            max_sweep_levels: self.option(|decoder| decoder.u64().and_then(|levels| levels.try_into().ok()))?,
+            protection_price: self.option(Self::price)?,
+        })
+    }
+
+    fn orders(&mut self) -> Option<Vec<Order>> {
+        let count = self.u64()?;
+        // Every order takes well over a byte, so a count beyond the bytes left is damage
+        if count > self.0.len() as u64 {
+            return None;
+        }
+        (0..count).map(|_| self.order()).collect()
+    }
+
+    fn snapshot(&mut self) -> Option<BookSnapshot> {
+        let (tick_size, strict_ticks) = (self.f64()?, self.bool()?);
+        let stp_policy = match self.u8()? {
+            0 => StpPolicy::None,
+            1 => StpPolicy::CancelNewest,
+            2 => StpPolicy::CancelOldest,
+            3 => StpPolicy::CancelBoth,
+            _ => return None,
+        };
+        let match_policy = match self.u8()? {
+            0 => MatchPolicy::Fifo,
+            1 => MatchPolicy::ProRata { min_allocation: self.u64()? },
+            _ => return None,
+        };
+        let post_only_policy = match self.u8()? {
+            0 => PostOnlyPolicy::Reject,
+            1 => PostOnlyPolicy::Reprice,
+            _ => return None,
+        };
+        let price_band = self.option(|decoder| {
+            let reference = match decoder.u8()? {
+                0 => ReferencePrice::LastTrade,
+                1 => ReferencePrice::Mid,
+                _ => return None,
+            };
+            Some(PriceBand { reference, pct: decoder.f64()? })
+        })?;
+        let max_levels_per_side = self.option(|decoder| usize::try_from(decoder.u64()?).ok())?;
+        let level_cap_policy = match self.u8()? {
+            0 => LevelCapPolicy::Reject,
+            1 => LevelCapPolicy::Evict,
+            _ => return None,
+        };
+        Some(BookSnapshot {
+            tick_size,
+            strict_ticks,
+            stp_policy,
+            match_policy,
+            post_only_policy,
+            price_band,
+            max_levels_per_side,
+            level_cap_policy,
+            strict_timestamps: self.bool()?,
+            now: self.u64()?,
+            last_seq: self.u64()?,
+            last_timestamp: self.u64()?,
+            last_trade_price: self.option(Self::price)?,
+            bids: self.orders()?,
+            asks: self.orders()?,
+            stops: self.orders()?,
+            auction: self.option(Self::orders)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_every_order_field_round_trips() {
+        let order = Order {
+            hidden_quantity: 40,
+            original_quantity: 90,
+            taker_quantity: 20,
+            seq: 5,
+            accepted_at: 6,
+            ..Order::stop_limit(7, Side::Sell, Price(9_950), Price(9_900), 50)
+                .with_timestamp(3)
+                .with_time_in_force(TimeInForce::Fok)
+                .with_display_quantity(10)
+                .with_participant_id(11)
+                .with_post_only()
+                .with_expiry(12)
+                .with_max_sweep_levels(2)
+                .with_protection_price(Price(9_800))
+        };
+        for command in [
+            Command::Add(order),
+            Command::Add(Order::market(8, Side::Buy, 1)),
+            Command::Modify { id: 7, price: Price(-1), quantity: 4 },
+            Command::Uncross,
+        ] {
+            let mut encoder = Encoder(Vec::new());
+            encoder.command(&command);
+            assert!(matches!(Decoder(&encoder.0).record(), Some(Record::Command(decoded)) if decoded == command));
+        }
+    }
+}
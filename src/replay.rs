@@ -0,0 +1,210 @@
+//! Replaying order flow from CSV and writing it back out.
+//!
+//! One row per action, `id,side,price,quantity,action`:
+//!
+//! ```text
+//! id,side,price,quantity,action
+//! 1,sell,101.5,200,add
+//! 2,buy,101.5,50,add
+//! 1,,,,cancel
+//! ```
+//!
+//! `side` is `buy` or `sell` and `action` is `add` (a GTC limit order) or `cancel`. Cancels
+//! only need the id; the other fields may be left empty. A header row and blank lines are
+//! skipped.
+
+pub use crate::flow::FlowEvent;
+use crate::{Order, OrderBook, Side};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::time::{Duration, Instant};
+
+const HEADER: &str = "id,side,price,quantity,action";
+
+/// Totals from a replay run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayStats {
+    /// Rows applied to the book, rejected ones included.
+    pub orders_processed: usize,
+    pub trades: usize,
+    /// Sum of the quantity of every trade.
+    pub volume: u64,
+    /// Rows the book refused: adds it rejected and cancels of unknown ids.
+    pub rejects: usize,
+    /// Wall-clock time spent applying the rows, not parsing them.
+    pub elapsed: Duration,
+}
+
+impl ReplayStats {
+    pub fn orders_per_second(&self) -> f64 {
+        self.orders_processed as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn nanos_per_order(&self) -> f64 {
+        self.elapsed.as_nanos() as f64 / self.orders_processed as f64
+    }
+}
+
+/// Error returned when order flow cannot be read.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// A row that doesn't parse; `line` is 1-based.
+    Malformed { line: usize, message: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "failed to read order flow: {}", err),
+            ReplayError::Malformed { line, message } => write!(f, "malformed row on line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplayError::Io(err) => Some(err),
+            ReplayError::Malformed { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+/// Parses every row of `reader`, then replays them into `book`.
+///
+/// Nothing is applied if any row is malformed, so an error never leaves the book half-replayed.
+pub fn replay_csv<R: Read>(reader: R, book: &mut OrderBook) -> Result<ReplayStats, ReplayError> {
+    let events = read_csv(reader, book)?;
+    Ok(replay(events, book))
+}
+
+/// Parses CSV order flow, converting prices with `book`'s tick size.
+pub fn read_csv<R: Read>(reader: R, book: &OrderBook) -> Result<Vec<FlowEvent>, ReplayError> {
+    let mut events = Vec::new();
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        let row = line.trim();
+        if row.is_empty() || (index == 0 && row.eq_ignore_ascii_case(HEADER)) {
+            continue;
+        }
+
+        let timestamp = events.len() as u64;
+        let event = parse_row(row, book, timestamp).map_err(|message| ReplayError::Malformed { line: index + 1, message })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Applies `events` to `book` in order, timing only the book operations.
+pub fn replay(events: impl IntoIterator<Item = FlowEvent>, book: &mut OrderBook) -> ReplayStats {
+    let mut stats = ReplayStats::default();
+
+    let start = Instant::now();
+    for event in events {
+        stats.orders_processed += 1;
+        match event {
+            FlowEvent::Add(order) => match book.add_order(order) {
+                Ok(trades) => {
+                    stats.trades += trades.len();
+                    stats.volume += trades.iter().map(|trade| trade.quantity).sum::<u64>();
+                }
+                Err(_) => stats.rejects += 1,
+            },
+            FlowEvent::Cancel(id) => {
+                if book.cancel_order(id).is_err() {
+                    stats.rejects += 1;
+                }
+            }
+        }
+    }
+    stats.elapsed = start.elapsed();
+
+    stats
+}
+
+/// Writes `events` as CSV, header first, in the format [`read_csv`] accepts.
+pub fn write_csv<'a, W: Write>(
+    mut writer: W,
+    events: impl IntoIterator<Item = &'a FlowEvent>,
+    tick_size: f64,
+) -> io::Result<()> {
+    writeln!(writer, "{}", HEADER)?;
+    for event in events {
+        match event {
+            FlowEvent::Add(order) => {
+                let side = match order.side {
+                    Side::Buy => "buy",
+                    Side::Sell => "sell",
+                };
+                let price = order.price.to_f64(tick_size);
+                writeln!(writer, "{},{},{:?},{},add", order.id, side, price, order.quantity)?;
+            }
+            FlowEvent::Cancel(id) => writeln!(writer, "{},,,,cancel", id)?,
+        }
+    }
+    writer.flush()
+}
+
+fn parse_row(row: &str, book: &OrderBook, timestamp: u64) -> Result<FlowEvent, String> {
+    let fields: Vec<&str> = row.split(',').map(str::trim).collect();
+    let [id, side, price, quantity, action] = fields[..] else {
+        return Err(format!("expected 5 fields ({}), found {}", HEADER, fields.len()));
+    };
+
+    let id: u64 = id.parse().map_err(|_| format!("invalid id {:?}", id))?;
+    match action.to_ascii_lowercase().as_str() {
+        "cancel" => Ok(FlowEvent::Cancel(id)),
+        "add" => {
+            let side = match side.to_ascii_lowercase().as_str() {
+                "buy" => Side::Buy,
+                "sell" => Side::Sell,
+                _ => return Err(format!("invalid side {:?}, expected buy or sell", side)),
+            };
+            let price: f64 = price.parse().map_err(|_| format!("invalid price {:?}", price))?;
+            let price = book.price(price).map_err(|err| err.to_string())?;
+            let quantity: u64 = quantity.parse().map_err(|_| format!("invalid quantity {:?}", quantity))?;
+            if quantity == 0 {
+                return Err("quantity must be positive".to_string());
+            }
+            Ok(FlowEvent::Add(Order::limit(id, side, price, quantity).with_timestamp(timestamp)))
+        }
+        _ => Err(format!("invalid action {:?}, expected add or cancel", action)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed_row_reports_line() {
+        let mut book = OrderBook::new();
+        let csv = "id,side,price,quantity,action\n1,buy,100.0,10,add\n\n2,buy,abc,10,add\n";
+
+        let err = replay_csv(csv.as_bytes(), &mut book).unwrap_err();
+        assert!(matches!(err, ReplayError::Malformed { line: 4, .. }), "{}", err);
+        assert!(book.bids().is_empty());
+    }
+
+    #[test]
+    fn test_write_then_read_round_trip() {
+        let book = OrderBook::new();
+        let events = vec![
+            FlowEvent::Add(Order::limit(1, Side::Sell, book.price(101.25).unwrap(), 7)),
+            FlowEvent::Add(Order::limit(2, Side::Buy, book.price(99.0).unwrap(), 3).with_timestamp(1)),
+            FlowEvent::Cancel(1),
+        ];
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &events, book.tick_size()).unwrap();
+        assert_eq!(String::from_utf8(csv.clone()).unwrap().lines().nth(1), Some("1,sell,101.25,7,add"));
+        assert_eq!(read_csv(&csv[..], &book).unwrap(), events);
+    }
+}
@@ -0,0 +1,418 @@
+//! A TCP front end for one order book, speaking line-delimited JSON (feature `server`).
+//!
+//! Each line a client sends is one request, and each line it gets back is one message:
+//!
+//! ```text
+//! -> {"action":"add","side":"buy","price":100.5,"qty":10}
+//! <- {"type":"ack","id":1}
+//! <- {"type":"fill","id":1,"side":"buy","price":100.5,"qty":4,"liquidity":"taker"}
+//! -> {"action":"cancel","id":1}
+//! <- {"type":"cancelled","id":1,"qty":6}
+//! -> {"action":"subscribe"}
+//! <- {"type":"subscribed","sequence":7,"bids":[[100.0,30]],"asks":[[101.0,5]]}
+//! <- {"type":"trade","price":101.0,"qty":5}
+//! <- {"type":"depth","sequence":8,"side":"sell","price":101.0,"qty":0}
+//! ```
+//!
+//! An add without a `price` is a market order. Order ids belong to the connection: a client
+//! may pass its own `id` or leave it to the server, which numbers that connection's orders
+//! from 1. Two clients can use the same id without clashing, and the book itself never sees
+//! either. Every request is answered with an `ack`, `cancelled` or `reject` (with a
+//! `reason`), followed by a `fill` for each execution of the client's own orders, on either
+//! side of the trade.
+//!
+//! A subscriber gets the whole book once, then after every request that changed it the
+//! anonymous trade prints and one `depth` message per changed level (`qty` 0 once a level
+//! is gone), numbered so a gap is easy to spot.
+//!
+//! All requests go over one channel to a single engine thread that owns the book, so matching
+//! is deterministic in the order requests arrive. Replies go out through a writer thread per
+//! connection, so a client that stops reading never holds up matching for the others: once it
+//! has [`QUEUE_LIMIT`] messages waiting it is disconnected. A client's open orders are
+//! cancelled when it disconnects.
+
+use crate::{L2Change, LobError, Order, OrderBook, Price, Side, Trade};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+/// Messages a client may have waiting to be written before it counts as too slow and is
+/// disconnected.
+pub const QUEUE_LIMIT: usize = 1024;
+
+/// A bound listener, ready to serve a book with [`Server::run`].
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+}
+
+impl Server {
+    /// Binds to `addr`; port 0 picks a free one, see [`Server::local_addr`].
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self { listener: TcpListener::bind(addr)? })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves `book` to every client that connects. Only returns if accepting a connection
+    /// fails.
+    pub fn run(self, book: OrderBook) -> io::Result<()> {
+        let (events, inbox) = mpsc::channel();
+        thread::spawn(move || Engine::new(book).run(inbox));
+
+        for (connection, stream) in (1..).zip(self.listener.incoming()) {
+            let stream = stream?;
+            let reader = BufReader::new(stream.try_clone()?);
+            let writer = stream.try_clone()?;
+            let (outbox, queue) = mpsc::sync_channel(QUEUE_LIMIT);
+            thread::spawn(move || write_lines(writer, queue));
+            // Sent before the reader starts, so the engine knows the client before its requests
+            let _ = events.send(Event::Connected { connection, stream, outbox });
+
+            let events = events.clone();
+            thread::spawn(move || {
+                for line in reader.lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let request = serde_json::from_str(&line).map_err(|err| err.to_string());
+                    if events.send(Event::Request { connection, request }).is_err() {
+                        return;
+                    }
+                }
+                let _ = events.send(Event::Disconnected { connection });
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Writes the client's queued lines until the engine drops its end or the client stops
+/// taking them, then shuts the connection so its reader sees the disconnect too.
+fn write_lines(mut stream: TcpStream, queue: Receiver<String>) {
+    for line in queue {
+        if stream.write_all(line.as_bytes()).is_err() {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// What the connection threads tell the engine thread.
+enum Event {
+    Connected { connection: u64, stream: TcpStream, outbox: SyncSender<String> },
+    Request { connection: u64, request: Result<Request, String> },
+    Disconnected { connection: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum WireSide {
+    Buy,
+    Sell,
+}
+
+impl From<Side> for WireSide {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Buy => WireSide::Buy,
+            Side::Sell => WireSide::Sell,
+        }
+    }
+}
+
+impl From<WireSide> for Side {
+    fn from(side: WireSide) -> Self {
+        match side {
+            WireSide::Buy => Side::Buy,
+            WireSide::Sell => Side::Sell,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum Request {
+    Add {
+        #[serde(default)]
+        id: Option<u64>,
+        side: WireSide,
+        #[serde(default)]
+        price: Option<f64>,
+        qty: u64,
+    },
+    Cancel {
+        id: u64,
+    },
+    Subscribe,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Liquidity {
+    Maker,
+    Taker,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+    Ack {
+        id: u64,
+    },
+    Fill {
+        id: u64,
+        side: WireSide,
+        price: f64,
+        qty: u64,
+        liquidity: Liquidity,
+    },
+    Cancelled {
+        id: u64,
+        qty: u64,
+    },
+    Reject {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<u64>,
+        reason: String,
+    },
+    Subscribed {
+        /// Sequence of the last depth update already folded into the levels below.
+        sequence: u64,
+        bids: Vec<(f64, u64)>,
+        asks: Vec<(f64, u64)>,
+    },
+    Depth {
+        sequence: u64,
+        side: WireSide,
+        price: f64,
+        qty: u64,
+    },
+    Trade {
+        price: f64,
+        qty: u64,
+    },
+}
+
+struct Client {
+    /// Lines for the client's writer thread.
+    outbox: SyncSender<String>,
+    /// Only ever shut down, to cut off a client that has fallen too far behind.
+    stream: TcpStream,
+    subscribed: bool,
+    /// The client's ids of its open orders, mapped to the book's.
+    orders: HashMap<u64, u64>,
+    next_id: u64,
+}
+
+/// Owner of the book on the engine thread.
+struct Engine {
+    book: OrderBook,
+    clients: HashMap<u64, Client>,
+    /// `(connection, client id)` of every open order, by book id.
+    owners: HashMap<u64, (u64, u64)>,
+    next_order_id: u64,
+    last_sequence: u64,
+    /// Clients [`Engine::send`] gave up on, to disconnect once the current event is handled.
+    cut_off: Vec<u64>,
+}
+
+impl Engine {
+    fn new(mut book: OrderBook) -> Self {
+        book.enable_market_data();
+        let (clients, owners) = (HashMap::new(), HashMap::new());
+        Self { book, clients, owners, next_order_id: 1, last_sequence: 0, cut_off: Vec::new() }
+    }
+
+    fn run(mut self, inbox: Receiver<Event>) {
+        for event in inbox {
+            match event {
+                Event::Connected { connection, stream, outbox } => {
+                    let client = Client { outbox, stream, subscribed: false, orders: HashMap::new(), next_id: 1 };
+                    self.clients.insert(connection, client);
+                }
+                Event::Request { connection, request: Ok(request) } => self.handle(connection, request),
+                Event::Request { connection, request: Err(reason) } => {
+                    self.send(connection, &Message::Reject { id: None, reason });
+                }
+                Event::Disconnected { connection } => self.disconnect(connection),
+            }
+            // Disconnecting publishes, which can cut off more clients
+            while let Some(connection) = self.cut_off.pop() {
+                self.disconnect(connection);
+            }
+        }
+    }
+
+    fn handle(&mut self, connection: u64, request: Request) {
+        match request {
+            Request::Add { id, side, price, qty } => self.add(connection, id, side.into(), price, qty),
+            Request::Cancel { id } => self.cancel(connection, id),
+            Request::Subscribe => self.subscribe(connection),
+        }
+    }
+
+    fn add(&mut self, connection: u64, id: Option<u64>, side: Side, price: Option<f64>, quantity: u64) {
+        let Some(client) = self.clients.get_mut(&connection) else {
+            return;
+        };
+        let id = id.unwrap_or_else(|| {
+            while client.orders.contains_key(&client.next_id) {
+                client.next_id += 1;
+            }
+            client.next_id
+        });
+        if client.orders.contains_key(&id) {
+            return self.reject(connection, id, LobError::DuplicateOrderId(id));
+        }
+
+        let order_id = self.next_order_id;
+        let order = match price.map(|price| self.book.price(price)) {
+            None => Order::market(order_id, side, quantity),
+            Some(Ok(price)) => Order::limit(order_id, side, price, quantity),
+            Some(Err(err)) => return self.reject(connection, id, err),
+        };
+        let trades = match self.book.add_order(order) {
+            Ok(trades) => trades,
+            Err(err) => return self.reject(connection, id, err),
+        };
+
+        self.next_order_id += 1;
+        self.owners.insert(order_id, (connection, id));
+        if let Some(client) = self.clients.get_mut(&connection) {
+            client.orders.insert(id, order_id);
+        }
+        self.send(connection, &Message::Ack { id });
+        let maker_side = match side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+        for trade in &trades {
+            self.fill(trade, trade.maker_id, maker_side, Liquidity::Maker);
+            self.fill(trade, trade.taker_id, side, Liquidity::Taker);
+        }
+
+        // Forget orders that are no longer on the book, the new one included
+        for book_id in trades.iter().map(|trade| trade.maker_id).chain([order_id]) {
+            if !self.is_open(book_id) {
+                self.forget(book_id);
+            }
+        }
+        self.publish(&trades);
+    }
+
+    fn cancel(&mut self, connection: u64, id: u64) {
+        let Some(&order_id) = self.clients.get(&connection).and_then(|client| client.orders.get(&id)) else {
+            return self.reject(connection, id, format!("no open order {}", id));
+        };
+        let cancelled = self.book.cancel_order(order_id);
+        self.forget(order_id);
+        match cancelled {
+            Ok(order) => self.send(connection, &Message::Cancelled { id, qty: order.quantity }),
+            Err(err) => self.reject(connection, id, err),
+        }
+        self.publish(&[]);
+    }
+
+    fn subscribe(&mut self, connection: u64) {
+        let Some(client) = self.clients.get_mut(&connection) else {
+            return;
+        };
+        client.subscribed = true;
+
+        let tick_size = self.book.tick_size();
+        let depth = self.book.depth(usize::MAX);
+        let levels = |levels: Vec<(Price, u64, usize)>| {
+            levels.into_iter().map(|(price, quantity, _)| (price.to_f64(tick_size), quantity)).collect()
+        };
+        let (bids, asks) = (levels(depth.bids), levels(depth.asks));
+        self.send(connection, &Message::Subscribed { sequence: self.last_sequence, bids, asks });
+    }
+
+    /// Cancels everything the client still has open, oldest first.
+    fn disconnect(&mut self, connection: u64) {
+        let Some(client) = self.clients.remove(&connection) else {
+            return;
+        };
+        let mut order_ids: Vec<u64> = client.orders.into_values().collect();
+        order_ids.sort_unstable();
+        for order_id in order_ids {
+            self.owners.remove(&order_id);
+            let _ = self.book.cancel_order(order_id);
+        }
+        self.publish(&[]);
+    }
+
+    /// Sends the fill of `trade` to whoever owns `order_id`.
+    fn fill(&mut self, trade: &Trade, order_id: u64, side: Side, liquidity: Liquidity) {
+        let Some(&(connection, id)) = self.owners.get(&order_id) else {
+            return;
+        };
+        let price = trade.price.to_f64(self.book.tick_size());
+        self.send(connection, &Message::Fill { id, side: side.into(), price, qty: trade.quantity, liquidity });
+    }
+
+    /// Sends subscribers the trade prints and depth changes of the request just handled.
+    fn publish(&mut self, trades: &[Trade]) {
+        let tick_size = self.book.tick_size();
+        let mut messages: Vec<Message> = trades
+            .iter()
+            .map(|trade| Message::Trade { price: trade.price.to_f64(tick_size), qty: trade.quantity })
+            .collect();
+        for update in self.book.take_updates() {
+            let (side, price, qty) = match update.change {
+                L2Change::LevelAdded { side, price, quantity } => (side, price, quantity),
+                L2Change::LevelChanged { side, price, new_quantity } => (side, price, new_quantity),
+                L2Change::LevelRemoved { side, price } => (side, price, 0),
+            };
+            self.last_sequence = update.sequence;
+            let (sequence, side, price) = (update.sequence, side.into(), price.to_f64(tick_size));
+            messages.push(Message::Depth { sequence, side, price, qty });
+        }
+
+        let subscribers = self.clients.iter().filter(|(_, client)| client.subscribed).map(|(&connection, _)| connection);
+        for connection in subscribers.collect::<Vec<_>>() {
+            for message in &messages {
+                self.send(connection, message);
+            }
+        }
+    }
+
+    fn is_open(&self, order_id: u64) -> bool {
+        self.book.bids().contains(order_id) || self.book.asks().contains(order_id)
+    }
+
+    fn forget(&mut self, order_id: u64) {
+        if let Some((connection, id)) = self.owners.remove(&order_id)
+            && let Some(client) = self.clients.get_mut(&connection)
+        {
+            client.orders.remove(&id);
+        }
+    }
+
+    fn reject(&mut self, connection: u64, id: u64, reason: impl ToString) {
+        self.send(connection, &Message::Reject { id: Some(id), reason: reason.to_string() });
+    }
+
+    /// Queues one message line for the client's writer thread. A client whose queue is full,
+    /// or whose writer has stopped, is shut down and disconnected once the current event is
+    /// handled; whatever it still sends after that is ignored.
+    fn send(&mut self, connection: u64, message: &Message) {
+        if let Some(client) = self.clients.get_mut(&connection) {
+            let mut line = serde_json::to_string(message).expect("messages always serialize");
+            line.push('\n');
+            if client.outbox.try_send(line).is_err() {
+                let _ = client.stream.shutdown(Shutdown::Both);
+                self.cut_off.push(connection);
+            }
+        }
+    }
+}
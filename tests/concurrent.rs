@@ -0,0 +1,44 @@
+use lob_rs::concurrent::ConcurrentBook;
+use lob_rs::flow::{self, FlowConfig, FlowEvent};
+use lob_rs::{Order, OrderBook};
+use std::thread;
+
+const PRODUCERS: u64 = 4;
+// Debug builds validate the whole book after every order, which makes the full run quadratic
+const ORDERS_PER_PRODUCER: usize = if cfg!(debug_assertions) { 2_000 } else { 100_000 };
+
+#[test]
+fn test_producers_share_one_book() {
+    let book = ConcurrentBook::spawn(OrderBook::new());
+
+    let producers: Vec<_> = (0..PRODUCERS)
+        .map(|producer| {
+            let handle = book.handle();
+            thread::spawn(move || {
+                // Each producer gets its own id range so every order is accepted
+                let offset = producer * ORDERS_PER_PRODUCER as u64;
+                let (mut submitted, mut filled) = (0, 0);
+                for event in flow::generate(producer, ORDERS_PER_PRODUCER, &FlowConfig::default()) {
+                    let FlowEvent::Add(order) = event else { continue };
+                    submitted += order.quantity;
+                    let order = Order { id: order.id + offset, ..order };
+                    filled += handle.submit_and_wait(order).unwrap().iter().map(|trade| trade.quantity).sum::<u64>();
+                }
+                (submitted, filled)
+            })
+        })
+        .collect();
+
+    let (mut submitted, mut filled) = (0, 0);
+    for producer in producers {
+        let (producer_submitted, producer_filled) = producer.join().unwrap();
+        submitted += producer_submitted;
+        filled += producer_filled;
+    }
+
+    // Every unit submitted either still rests or traded, and each trade fills two orders
+    let (valid, resting) = book.inspect(|book| (book.validate(), book.total_bid_volume() + book.total_ask_volume()));
+    assert_eq!(valid, Ok(()));
+    assert!(filled > 0);
+    assert_eq!(submitted, resting + 2 * filled);
+}
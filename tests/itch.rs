@@ -0,0 +1,143 @@
+//! Decoding ITCH-style messages and replaying them through a book.
+
+use lob_rs::codec::{encode, replay_itch, CodecError, ExecutionMismatch, Header, ItchParser, Message};
+use lob_rs::{OrderBook, Side};
+use std::io::Read;
+
+/// Stock locate 7, tracking number 1, timestamp 256.
+const HEADER: [u8; 10] = [0, 7, 0, 1, 0, 0, 0, 0, 1, 0];
+
+/// One hand-built message of each type, framed, with what it decodes to.
+fn fixtures() -> Vec<(Vec<u8>, Message<'static>)> {
+    let header = Header { stock_locate: 7, tracking_number: 1, timestamp: 256 };
+    let (order_ref, new_ref) = ([0, 0, 0, 0, 0, 0, 0x30, 0x39], [0, 0, 0, 0, 0, 0, 0x30, 0x3A]);
+    let price = [0, 0x0F, 0x55, 0xC8];
+    let (stock, side, shares) = (b"ACME    ", Side::Sell, 100);
+    vec![
+        (
+            [&[0, 36, b'A'][..], &HEADER, &order_ref, b"S", &[0, 0, 0, 100], stock, &price].concat(),
+            Message::AddOrder { header, order_ref: 12345, side, shares, stock, price: 1_005_000 },
+        ),
+        (
+            [&[0, 31, b'E'][..], &HEADER, &order_ref, &[0, 0, 0, 40], &[0, 0, 0, 0, 0, 0, 0, 9]].concat(),
+            Message::OrderExecuted { header, order_ref: 12345, shares: 40, match_number: 9 },
+        ),
+        (
+            [&[0, 23, b'X'][..], &HEADER, &order_ref, &[0, 0, 0, 20]].concat(),
+            Message::OrderCancel { header, order_ref: 12345, shares: 20 },
+        ),
+        ([&[0, 19, b'D'][..], &HEADER, &order_ref].concat(), Message::OrderDelete { header, order_ref: 12345 }),
+        (
+            [&[0, 35, b'U'][..], &HEADER, &order_ref, &new_ref, &[0, 0, 0, 30], &price].concat(),
+            Message::OrderReplace { header, original_ref: 12345, new_ref: 12346, shares: 30, price: 1_005_000 },
+        ),
+        (
+            [&[0, 12, b'S'][..], &HEADER, b"O"].concat(),
+            Message::Other { kind: b'S', body: &[0, 7, 0, 1, 0, 0, 0, 0, 1, 0, b'O'] },
+        ),
+    ]
+}
+
+#[test]
+fn test_every_message_type_decodes_and_encodes() {
+    for (bytes, expected) in fixtures() {
+        let mut parser = ItchParser::new();
+        let (message, len) = parser.next(&bytes).unwrap();
+        assert_eq!((message, len), (expected, bytes.len()));
+        assert_eq!(parser.offset(), bytes.len() as u64);
+
+        let mut encoded = Vec::new();
+        encode(&message, &mut encoded);
+        assert_eq!(encoded, bytes);
+    }
+}
+
+#[test]
+fn test_malformed_messages_report_their_offset() {
+    let len = fixtures()[0].0.len() as u64;
+    let mut stream = fixtures().swap_remove(0).0;
+    stream.extend_from_slice(&[&[0, 20, b'D'][..], &HEADER, &[0; 9]].concat());
+    let mut bad_side = fixtures().swap_remove(0).0;
+    bad_side[21] = b'Q';
+
+    let mut parser = ItchParser::new();
+    let (_, consumed) = parser.next(&stream).unwrap();
+    assert!(matches!(parser.next(&stream[consumed..]), Err(CodecError::Malformed { offset, .. }) if offset == len));
+    let error = ItchParser::new().next(&bad_side).unwrap_err();
+    assert_eq!(error.to_string(), "malformed message at offset 0: side is neither B nor S");
+}
+
+/// A session touching every message type, prices in 1/10,000.
+fn session() -> Vec<u8> {
+    let header = Header::default();
+    let add = |order_ref, side, shares, price| {
+        Message::AddOrder { header, order_ref, side, shares, stock: b"ACME    ", price }
+    };
+    let messages = [
+        add(1, Side::Sell, 100, 1_005_000),
+        // Crosses, so the engine executes 40 of order 1 itself
+        add(2, Side::Buy, 40, 1_006_000),
+        Message::OrderExecuted { header, order_ref: 1, shares: 40, match_number: 1 },
+        // Reported but not made by the engine, so taken off order 1
+        Message::OrderExecuted { header, order_ref: 1, shares: 10, match_number: 2 },
+        Message::OrderCancel { header, order_ref: 1, shares: 20 },
+        add(3, Side::Buy, 25, 1_000_000),
+        Message::OrderReplace { header, original_ref: 3, new_ref: 4, shares: 30, price: 1_001_000 },
+        Message::OrderCancel { header, order_ref: 4, shares: 5 },
+        Message::Other { kind: b'S', body: &[0; 11] },
+        Message::OrderDelete { header, order_ref: 4 },
+        Message::OrderDelete { header, order_ref: 3 },
+        // Executed by the engine and never reported
+        add(5, Side::Buy, 10, 1_005_000),
+    ];
+    let mut stream = Vec::new();
+    messages.iter().for_each(|message| encode(message, &mut stream));
+    stream
+}
+
+#[test]
+fn test_replay_maps_messages_and_checks_executions() {
+    let mut book = OrderBook::new();
+    let stats = replay_itch(&session()[..], &mut book).unwrap();
+
+    assert_eq!((stats.messages, stats.trades, stats.volume), (12, 2, 50));
+    assert_eq!((stats.rejects, stats.skipped), (1, 1));
+    assert_eq!(
+        stats.mismatches,
+        vec![
+            ExecutionMismatch { order_ref: 1, reported: 10, matched: 0 },
+            ExecutionMismatch { order_ref: 1, reported: 0, matched: 10 },
+        ]
+    );
+    assert_eq!(book.best_ask(), Some((book.price(100.5).unwrap(), 20)));
+    assert_eq!(book.best_bid(), None);
+}
+
+#[test]
+fn test_truncation_at_every_byte_offset() {
+    let stream = session();
+    let mut expected_book = OrderBook::new();
+    let expected = replay_itch(&stream[..], &mut expected_book).unwrap();
+
+    for split in 0..=stream.len() {
+        // A prefix ending partway through a message leaves it for the next read
+        let mut parser = ItchParser::new();
+        let mut consumed = 0;
+        while let Ok((_, len)) = parser.next(&stream[consumed..split]) {
+            consumed += len;
+        }
+        assert!(matches!(parser.next(&stream[consumed..split]), Err(CodecError::Incomplete { .. })));
+
+        let mut book = OrderBook::new();
+        let reader = (&stream[..split]).chain(&stream[split..]);
+        assert_eq!(replay_itch(reader, &mut book).unwrap(), expected, "split at {}", split);
+        assert_eq!(book.snapshot(), expected_book.snapshot(), "split at {}", split);
+
+        // Cut off there instead, the partial message is reported where it starts
+        if consumed < split {
+            let result = replay_itch(&stream[..split], &mut OrderBook::new());
+            let truncated = matches!(result, Err(CodecError::Truncated { offset }) if offset == consumed as u64);
+            assert!(truncated, "cut at {}", split);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Write-ahead log recovery: a crash can cut the log at any byte, and recovery must give back
+//! exactly the book the fully written records describe.
+
+use lob_rs::flow::{FlowEvent, FlowGenerator, MarketRegime};
+use lob_rs::persistence::{self, Command, WalWriter};
+use lob_rs::{OrderBook, Price};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::path::PathBuf;
+
+/// Debug builds validate the whole book after every operation, so they get a shorter run.
+const OPS: usize = if cfg!(debug_assertions) { 3_000 } else { 100_000 };
+
+fn log_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("lob-rs-{}-{}.wal", name, std::process::id()))
+}
+
+/// Seeded flow with some cancels turned into modifies, some orders expiring and the clock
+/// moving, so every kind of command ends up in the log.
+fn commands() -> Vec<Command> {
+    let mut commands = Vec::with_capacity(OPS + OPS / 100);
+    for (i, event) in FlowGenerator::new(5, MarketRegime::volatile()).take(OPS).enumerate() {
+        let i = i as u64;
+        commands.push(match event {
+            FlowEvent::Add(order) if i.is_multiple_of(10) => Command::Add(order.with_expiry(i + 500)),
+            FlowEvent::Cancel(id) if i.is_multiple_of(3) => {
+                Command::Modify { id, price: Price(9_900 + (id % 200) as i64), quantity: 1 + id % 50 }
+            }
+            event => event.into(),
+        });
+        if i % 100 == 99 {
+            commands.push(Command::AdvanceTime(i));
+        }
+    }
+    commands
+}
+
+fn replayed(commands: &[Command]) -> OrderBook {
+    let mut book = OrderBook::new();
+    commands.iter().cloned().for_each(|command| command.apply(&mut book));
+    book
+}
+
+#[test]
+fn test_recovery_after_torn_write() {
+    let path = log_path("torn");
+    let commands = commands();
+    let checkpoint_at = commands.len() / 2;
+
+    let mut book = OrderBook::new();
+    let mut wal = WalWriter::create(&path, &book).unwrap();
+    let mut checkpoint_size = 0;
+    // End of the log after each command since the checkpoint
+    let mut ends = Vec::new();
+    for (i, command) in commands.iter().enumerate() {
+        if i == checkpoint_at {
+            wal.checkpoint(&book).unwrap();
+            checkpoint_size = wal.size();
+        }
+        wal.append(command).unwrap();
+        command.clone().apply(&mut book);
+        if i >= checkpoint_at {
+            ends.push(wal.size());
+        }
+    }
+    let log = fs::read(&path).unwrap();
+    assert_eq!(log.len() as u64, wal.size());
+    assert_eq!(OrderBook::recover(&path).unwrap().snapshot(), book.snapshot());
+
+    let mut rng = StdRng::seed_from_u64(9);
+    for _ in 0..5 {
+        let cut = rng.random_range(checkpoint_size..wal.size());
+        fs::write(&path, &log[..cut as usize]).unwrap();
+
+        // Only the commands whose whole record is before the cut made it
+        let persisted = ends.partition_point(|&end| end <= cut);
+        let recovery = persistence::recover(&path).unwrap();
+        assert_eq!(recovery.commands, persisted);
+        assert_eq!(recovery.valid_len, persisted.checked_sub(1).map_or(checkpoint_size, |last| ends[last]));
+        assert_eq!(recovery.valid_len + recovery.discarded_bytes, cut);
+        assert_eq!(recovery.book.snapshot(), replayed(&commands[..checkpoint_at + persisted]).snapshot());
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_resume_drops_damaged_record() {
+    let path = log_path("damaged");
+    let commands = commands();
+
+    let mut wal = WalWriter::create(&path, &OrderBook::new()).unwrap();
+    for command in &commands[..1_000] {
+        wal.append(command).unwrap();
+    }
+    drop(wal);
+
+    // A flipped byte fails the last record's checksum
+    let mut log = fs::read(&path).unwrap();
+    *log.last_mut().unwrap() ^= 0xFF;
+    fs::write(&path, &log).unwrap();
+
+    let (mut wal, recovery) = WalWriter::resume(&path).unwrap();
+    assert_eq!(recovery.commands, 999);
+    assert!(recovery.discarded_bytes > 0);
+    assert_eq!(recovery.book.snapshot(), replayed(&commands[..999]).snapshot());
+
+    // New records go where the damaged one was
+    for command in &commands[1_000..1_100] {
+        wal.append(command).unwrap();
+    }
+    let expected = replayed(&[&commands[..999], &commands[1_000..1_100]].concat());
+    let recovery = persistence::recover(&path).unwrap();
+    assert_eq!((recovery.commands, recovery.discarded_bytes), (1_099, 0));
+    assert_eq!(recovery.book.snapshot(), expected.snapshot());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_resume_refuses_log_it_cannot_read() {
+    let path = log_path("refused");
+    let mut wal = WalWriter::create(&path, &OrderBook::new()).unwrap();
+    for command in &commands()[..100] {
+        wal.append(command).unwrap();
+    }
+    drop(wal);
+    let log = fs::read(&path).unwrap();
+
+    // Another format version, a damaged checkpoint and a log without the header at all
+    let mut other_version = log.clone();
+    other_version[4] += 1;
+    let mut damaged_checkpoint = log.clone();
+    damaged_checkpoint[20] ^= 0xFF;
+    for bytes in [other_version, damaged_checkpoint, log[8..].to_vec()] {
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(persistence::recover(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(WalWriter::resume(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_books_with_a_clock_are_refused() {
+    let path = log_path("clock");
+    let mut book = OrderBook::new();
+    let mut wal = WalWriter::create(&path, &book).unwrap();
+    wal.append(&Command::AdvanceTime(5)).unwrap();
+    let log = fs::read(&path).unwrap();
+
+    // Recovery would see none of the clock's readings, so neither the log nor a new one starts
+    book.set_clock(Box::new(|| 10));
+    assert_eq!(wal.checkpoint(&book).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(fs::read(&path).unwrap(), log);
+    assert_eq!(WalWriter::create(log_path("clock-new"), &book).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert!(!log_path("clock-new").exists());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,192 @@
+//! Property tests: random add/cancel flow checked step by step against the book's invariants
+//! and against a deliberately simple reference matcher.
+
+use lob_rs::{Order, OrderBook, Price, Side, TimeInForce, Trade};
+use proptest::prelude::*;
+use proptest::sample::Index;
+use proptest::strategy::ValueTree;
+use proptest::test_runner::TestRunner;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Limit { side: Side, price: i64, quantity: u64, ioc: bool },
+    Market { side: Side, quantity: u64 },
+    /// Cancels one of the ids submitted so far, open or not.
+    Cancel(Index),
+}
+
+fn side() -> impl Strategy<Value = Side> {
+    prop_oneof![Just(Side::Buy), Just(Side::Sell)]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        6 => (side(), 95..105i64, 1..50u64, prop::bool::weighted(0.2))
+            .prop_map(|(side, price, quantity, ioc)| Op::Limit { side, price, quantity, ioc }),
+        1 => (side(), 1..80u64).prop_map(|(side, quantity)| Op::Market { side, quantity }),
+        3 => any::<Index>().prop_map(Op::Cancel),
+    ]
+}
+
+/// Price-time priority the slow way: every fill rescans a flat list of resting orders, kept
+/// in arrival order.
+#[derive(Default)]
+struct Reference {
+    resting: Vec<Order>,
+    last_seq: u64,
+}
+
+impl Reference {
+    fn add(&mut self, mut order: Order) -> Vec<Trade> {
+        self.last_seq += 1;
+        order.seq = self.last_seq;
+        let mut trades = Vec::new();
+        while order.quantity > 0 {
+            let crosses = |maker: &Order| match order.side {
+                _ if order.price == Price(0) => true,
+                Side::Buy => maker.price <= order.price,
+                Side::Sell => maker.price >= order.price,
+            };
+            let best = self
+                .resting
+                .iter()
+                .enumerate()
+                .filter(|(_, maker)| maker.side != order.side && crosses(maker))
+                .min_by_key(|(position, maker)| match order.side {
+                    Side::Buy => (maker.price.0, *position),
+                    Side::Sell => (-maker.price.0, *position),
+                })
+                .map(|(position, _)| position);
+            let Some(position) = best else { break };
+
+            let maker = &mut self.resting[position];
+            let quantity = maker.quantity.min(order.quantity);
+            trades.push(Trade {
+                maker_id: maker.id,
+                taker_id: order.id,
+                price: maker.price,
+                quantity,
+                timestamp: order.timestamp,
+                maker_seq: maker.seq,
+                taker_seq: order.seq,
+            });
+            maker.quantity -= quantity;
+            order.quantity -= quantity;
+            if maker.quantity == 0 {
+                self.resting.remove(position);
+            }
+        }
+
+        if order.quantity > 0 && order.price != Price(0) && order.time_in_force == TimeInForce::Gtc {
+            self.resting.push(order);
+        }
+        trades
+    }
+
+    fn cancel(&mut self, id: u64) -> Option<Order> {
+        let position = self.resting.iter().position(|order| order.id == id)?;
+        Some(self.resting.remove(position))
+    }
+}
+
+/// Replays `ops` into a book and the reference, checking after every step.
+fn check(ops: &[Op]) -> Result<(), TestCaseError> {
+    let mut book = OrderBook::new();
+    let mut reference = Reference::default();
+    let mut limits: HashMap<u64, Price> = HashMap::new();
+    let mut ids = Vec::new();
+    let (mut submitted, mut filled, mut cancelled) = (0, 0, 0);
+
+    for (step, op) in ops.iter().enumerate() {
+        let id = step as u64;
+        let order = match *op {
+            Op::Limit { side, price, quantity, ioc } => {
+                let order = Order::limit(id, side, Price(price), quantity).with_timestamp(id);
+                if ioc { order.with_time_in_force(TimeInForce::Ioc) } else { order }
+            }
+            Op::Market { side, quantity } => Order::market(id, side, quantity).with_timestamp(id),
+            Op::Cancel(index) => {
+                if ids.is_empty() {
+                    continue;
+                }
+                let target = ids[index.index(ids.len())];
+                let expected = reference.cancel(target);
+                let actual = book.cancel_order(target).ok();
+                prop_assert_eq!(actual.as_ref().map(Order::remaining), expected.map(|order| order.quantity));
+                cancelled += actual.map_or(0, |order| order.remaining());
+                continue;
+            }
+        };
+
+        submitted += order.quantity;
+        limits.insert(id, order.price);
+        ids.push(id);
+        let rests = order.price != Price(0) && order.time_in_force == TimeInForce::Gtc;
+        let simulated = match order.price {
+            Price(0) => book.simulate_fill(order.side, order.quantity),
+            price => book.simulate_limit(order.side, price, order.quantity),
+        };
+        let trades = book.add_order(order.clone()).unwrap();
+        prop_assert_eq!(&trades, &reference.add(order.clone()), "step {}", step);
+
+        // Simulating first predicts the fills exactly
+        let taken: u64 = trades.iter().map(|trade| trade.quantity).sum();
+        let notional: i128 = trades.iter().map(|trade| trade.price.0 as i128 * trade.quantity as i128).sum();
+        prop_assert_eq!((simulated.filled_quantity, simulated.notional), (taken, notional), "step {}", step);
+
+        // Every trade is at the maker's price, never through the taker's limit
+        for trade in &trades {
+            prop_assert_eq!(trade.price, limits[&trade.maker_id]);
+            match order.side {
+                _ if order.price == Price(0) => {}
+                Side::Buy => prop_assert!(trade.price <= order.price),
+                Side::Sell => prop_assert!(trade.price >= order.price),
+            }
+        }
+        filled += taken;
+        if !rests {
+            cancelled += order.quantity - taken;
+        }
+
+        // Nothing is created or lost: each trade fills two orders
+        let resting = book.total_bid_volume() + book.total_ask_volume();
+        prop_assert_eq!(submitted, resting + 2 * filled + cancelled, "step {}", step);
+
+        if let (Some((bid, _)), Some((ask, _))) = (book.best_bid(), book.best_ask()) {
+            prop_assert!(bid < ask, "crossed at step {}", step);
+        }
+
+        // Ids follow arrival order, so FIFO means ascending ids within each level
+        for side in [book.bids(), book.asks()] {
+            for (_, level) in side.iter_best_first() {
+                let ids: Vec<u64> = level.iter().map(|order| order.id).collect();
+                prop_assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ids);
+            }
+        }
+    }
+
+    let snapshot = book.snapshot();
+    let resting = snapshot.bids.iter().chain(&snapshot.asks);
+    let mut open: Vec<(u64, u64)> = resting.map(|order| (order.id, order.quantity)).collect();
+    let mut expected: Vec<(u64, u64)> = reference.resting.iter().map(|order| (order.id, order.quantity)).collect();
+    open.sort_unstable();
+    expected.sort_unstable();
+    prop_assert_eq!(open, expected);
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn test_matches_reference_and_keeps_invariants(ops in prop::collection::vec(op(), 1..200)) {
+        check(&ops)?;
+    }
+}
+
+#[test]
+fn test_long_seeded_run() {
+    // Same strategy as the property test, one long sequence from a fixed seed
+    let mut runner = TestRunner::deterministic();
+    let ops = prop::collection::vec(op(), 3_000).new_tree(&mut runner).unwrap().current();
+    check(&ops).unwrap();
+}
@@ -0,0 +1,37 @@
+use lob_rs::replay::{FlowEvent, read_csv, replay, replay_csv, write_csv};
+use lob_rs::OrderBook;
+use std::fs::File;
+
+const SAMPLE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/sample_flow.csv");
+
+#[test]
+fn test_replay_sample_file() {
+    let mut book = OrderBook::new();
+    let stats = replay_csv(File::open(SAMPLE).unwrap(), &mut book).unwrap();
+
+    // 6 takes 50 @ 100.5 then 10 of order 3; 7 hits 4 for 30; 10 lifts 20 of order 1
+    assert_eq!(stats.orders_processed, 12);
+    assert_eq!((stats.trades, stats.volume), (4, 110));
+    assert_eq!(stats.rejects, 1);
+
+    assert_eq!(book.best_bid(), Some((book.price(100.0).unwrap(), 40)));
+    assert_eq!(book.best_ask(), Some((book.price(101.0).unwrap(), 90)));
+}
+
+#[test]
+fn test_exported_flow_replays_identically() {
+    let reference = OrderBook::new();
+    let events = read_csv(File::open(SAMPLE).unwrap(), &reference).unwrap();
+
+    let mut csv = Vec::new();
+    write_csv(&mut csv, &events, reference.tick_size()).unwrap();
+    let reread = read_csv(&csv[..], &reference).unwrap();
+    assert_eq!(reread, events);
+    assert!(matches!(reread[7], FlowEvent::Cancel(3)));
+
+    let mut original = OrderBook::new();
+    let mut replayed = OrderBook::new();
+    replay(events, &mut original);
+    replay(reread, &mut replayed);
+    assert_eq!(replayed.snapshot(), original.snapshot());
+}
@@ -0,0 +1,126 @@
+//! Two clients trading through the TCP server.
+
+use lob_rs::server::Server;
+use lob_rs::OrderBook;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+struct Client {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        Self { stream, reader }
+    }
+
+    fn send(&mut self, request: Value) {
+        writeln!(self.stream, "{}", request).unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+}
+
+fn serve() -> SocketAddr {
+    let server = Server::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run(OrderBook::new()));
+    addr
+}
+
+#[test]
+fn test_crossing_orders_fill_both_clients() {
+    let addr = serve();
+    let (mut seller, mut buyer) = (Client::connect(addr), Client::connect(addr));
+
+    seller.send(json!({"action": "subscribe"}));
+    assert_eq!(seller.receive(), json!({"type": "subscribed", "sequence": 0, "bids": [], "asks": []}));
+    seller.send(json!({"action": "add", "id": 1, "side": "sell", "price": 100.5, "qty": 10}));
+    assert_eq!(seller.receive(), json!({"type": "ack", "id": 1}));
+    assert_eq!(seller.receive(), json!({"type": "depth", "sequence": 1, "side": "sell", "price": 100.5, "qty": 10}));
+
+    // The buyer's id 1 is its own, not the seller's
+    buyer.send(json!({"action": "add", "id": 1, "side": "buy", "price": 101.0, "qty": 4}));
+    assert_eq!(buyer.receive(), json!({"type": "ack", "id": 1}));
+    let fill = json!({"type": "fill", "id": 1, "side": "buy", "price": 100.5, "qty": 4, "liquidity": "taker"});
+    assert_eq!(buyer.receive(), fill);
+
+    let fill = json!({"type": "fill", "id": 1, "side": "sell", "price": 100.5, "qty": 4, "liquidity": "maker"});
+    assert_eq!(seller.receive(), fill);
+    assert_eq!(seller.receive(), json!({"type": "trade", "price": 100.5, "qty": 4}));
+    assert_eq!(seller.receive(), json!({"type": "depth", "sequence": 2, "side": "sell", "price": 100.5, "qty": 6}));
+
+    // The buyer's filled order is gone; the seller's rest can still be cancelled
+    buyer.send(json!({"action": "cancel", "id": 1}));
+    assert_eq!(buyer.receive(), json!({"type": "reject", "id": 1, "reason": "no open order 1"}));
+    seller.send(json!({"action": "cancel", "id": 1}));
+    assert_eq!(seller.receive(), json!({"type": "cancelled", "id": 1, "qty": 6}));
+    assert_eq!(seller.receive(), json!({"type": "depth", "sequence": 3, "side": "sell", "price": 100.5, "qty": 0}));
+}
+
+#[test]
+fn test_disconnect_cancels_open_orders() {
+    let addr = serve();
+    let mut watcher = Client::connect(addr);
+    watcher.send(json!({"action": "subscribe"}));
+    watcher.receive();
+
+    // Ids are handed out per connection when the client leaves them out
+    let mut trader = Client::connect(addr);
+    trader.send(json!({"action": "add", "side": "buy", "price": 99.0, "qty": 5}));
+    assert_eq!(trader.receive(), json!({"type": "ack", "id": 1}));
+    trader.send(json!({"action": "add", "side": "buy", "price": 98.0, "qty": 5}));
+    assert_eq!(trader.receive(), json!({"type": "ack", "id": 2}));
+    trader.send(json!({"action": "fly"}));
+    assert_eq!(trader.receive()["type"], "reject");
+    assert_eq!(watcher.receive()["qty"], 5);
+    assert_eq!(watcher.receive()["qty"], 5);
+
+    // Cancelled in the order they were entered
+    drop(trader);
+    assert_eq!(watcher.receive(), json!({"type": "depth", "sequence": 3, "side": "buy", "price": 99.0, "qty": 0}));
+    assert_eq!(watcher.receive(), json!({"type": "depth", "sequence": 4, "side": "buy", "price": 98.0, "qty": 0}));
+}
+
+#[test]
+fn test_slow_subscriber_is_cut_off_without_stalling_others() {
+    let addr = serve();
+    let mut trader = Client::connect(addr);
+    for price in 1..=500 {
+        trader.send(json!({"action": "add", "side": "buy", "price": price as f64, "qty": 1}));
+    }
+    for id in 1..=500 {
+        assert_eq!(trader.receive(), json!({"type": "ack", "id": id}));
+    }
+
+    // Asks for far more full-book snapshots than the socket buffers and its queue can hold,
+    // and never reads them
+    let mut slow = Client::connect(addr);
+    let subscribe = json!({"action": "subscribe"});
+    for _ in 0..20_000 {
+        // Writing fails once the server has cut the client off
+        if writeln!(slow.stream, "{}", subscribe).is_err() {
+            break;
+        }
+    }
+    // The engine may still be working through the snapshots, but never waits on the socket
+    trader.stream.set_read_timeout(Some(Duration::from_secs(60))).unwrap();
+    trader.send(json!({"action": "add", "side": "sell", "price": 500.0, "qty": 1}));
+    assert_eq!(trader.receive(), json!({"type": "ack", "id": 501}));
+
+    // What was written before the cut-off is still there, then the connection ends
+    if let Err(err) = io::copy(&mut slow.reader, &mut io::sink()) {
+        assert!(!matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut), "still connected");
+    }
+}